@@ -0,0 +1,11 @@
+-- multi-quantity orders: lift the qty=1 MVP restriction.
+
+alter table orders drop constraint if exists orders_qty_check;
+alter table orders add constraint orders_qty_check check (qty > 0);
+
+-- optional per-order cap, configured per ticket type (null = no cap)
+alter table ticket_types
+  add column if not exists max_qty_per_order int null check (max_qty_per_order > 0);
+
+alter table purchase_intents
+  add column if not exists qty int not null default 1 check (qty > 0);
@@ -0,0 +1,13 @@
+-- per-user purchase caps (null = no cap), replacing the one-active-order-per-ticket-type index.
+
+alter table ticket_types
+  add column if not exists max_per_user int null check (max_per_user > 0);
+
+alter table events
+  add column if not exists max_per_user int null check (max_per_user > 0);
+
+drop index if exists uq_orders_user_ticket_type_active;
+
+create index if not exists idx_orders_user_ticket_type_active
+  on orders(user_id, ticket_type_id)
+  where status in ('CREATED','PAID');
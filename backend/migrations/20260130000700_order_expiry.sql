@@ -0,0 +1,11 @@
+-- payment deadline for CREATED orders; expired orders are canceled and their stock returned.
+
+alter table ticket_types
+  add column if not exists payment_window_secs int null check (payment_window_secs > 0);
+
+alter table orders
+  add column if not exists expires_at timestamptz null;
+
+create index if not exists idx_orders_created_expires_at
+  on orders(expires_at)
+  where status = 'CREATED' and expires_at is not null;
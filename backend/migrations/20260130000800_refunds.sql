@@ -0,0 +1,21 @@
+-- refunds: ledger of full/partial refunds against PAID orders.
+
+alter table orders drop constraint if exists orders_status_check;
+alter table orders add constraint orders_status_check
+  check (status in ('CREATED','PAID','CANCELED','REFUNDED'));
+
+alter table orders
+  add column if not exists refunded_cents bigint not null default 0,
+  add column if not exists refunded_at timestamptz null;
+
+create table if not exists refunds (
+  id uuid primary key,
+  order_id uuid not null references orders(id) on delete cascade,
+  amount_cents bigint not null check (amount_cents > 0),
+  reason text not null,
+  actor text not null,
+  restocked_qty int not null default 0 check (restocked_qty >= 0),
+  created_at timestamptz not null default now()
+);
+
+create index if not exists idx_refunds_order on refunds(order_id);
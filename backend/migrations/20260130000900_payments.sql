@@ -0,0 +1,23 @@
+-- payments: attempts to pay an order through a payment provider.
+
+create table if not exists payments (
+  id uuid primary key,
+  order_id uuid not null references orders(id) on delete cascade,
+  provider text not null,
+  provider_ref text not null,
+  status text not null check (status in ('PENDING','SUCCEEDED','FAILED')),
+  amount_cents bigint not null,
+  checkout_url text null,
+  failure_reason text null,
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now()
+);
+
+create unique index if not exists uq_payments_provider_ref on payments(provider, provider_ref);
+
+-- at most one open attempt per order
+create unique index if not exists uq_payments_order_pending
+  on payments(order_id)
+  where status = 'PENDING';
+
+create index if not exists idx_payments_order on payments(order_id);
@@ -0,0 +1,11 @@
+-- provider webhook events, deduped by (provider, event_id).
+
+create table if not exists payment_webhook_events (
+  provider text not null,
+  event_id text not null,
+  payment_id uuid null references payments(id) on delete set null,
+  event_type text not null,
+  applied boolean not null,
+  received_at timestamptz not null default now(),
+  primary key (provider, event_id)
+);
@@ -0,0 +1,28 @@
+-- order_events: history of order status transitions.
+
+create table if not exists order_events (
+  id uuid primary key,
+  order_id uuid not null references orders(id) on delete cascade,
+  from_status text null,
+  to_status text not null,
+  actor text not null,
+  reason text null,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists idx_order_events_order on order_events(order_id, created_at);
+
+-- backfill a CREATED event (plus the current status, if it moved on) for existing orders
+insert into order_events (id, order_id, from_status, to_status, actor, reason, created_at)
+select gen_random_uuid(), id, null, 'CREATED', 'system:backfill', null, created_at
+from orders
+where not exists (select 1 from order_events e where e.order_id = orders.id);
+
+insert into order_events (id, order_id, from_status, to_status, actor, reason, created_at)
+select gen_random_uuid(), id,
+       case when status = 'REFUNDED' then 'PAID' else 'CREATED' end,
+       status, 'system:backfill', null,
+       coalesce(refunded_at, canceled_at, paid_at, created_at)
+from orders
+where status <> 'CREATED'
+  and (select count(*) from order_events e where e.order_id = orders.id) = 1;
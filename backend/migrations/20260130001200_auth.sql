@@ -0,0 +1,20 @@
+-- auth: password credentials and server-side refresh tokens.
+
+-- null = account created by passwordless dev login
+alter table users add column if not exists password_hash text null;
+
+create table if not exists refresh_tokens (
+  id uuid primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  -- all tokens rotated from one login share a family; reuse of a rotated token revokes it
+  family_id uuid not null,
+  token_hash text not null,
+  expires_at timestamptz not null,
+  created_at timestamptz not null default now(),
+  revoked_at timestamptz null,
+  replaced_by uuid null
+);
+
+create unique index if not exists uq_refresh_tokens_hash on refresh_tokens(token_hash);
+create index if not exists idx_refresh_tokens_user on refresh_tokens(user_id);
+create index if not exists idx_refresh_tokens_family on refresh_tokens(family_id);
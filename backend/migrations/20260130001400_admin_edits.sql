@@ -0,0 +1,19 @@
+-- admin edits: archived events and an audit trail of admin changes.
+
+-- archived events are hidden from the public listing and can't be bought from
+alter table events add column if not exists archived_at timestamptz null;
+
+create table if not exists admin_audit_log (
+  id uuid primary key,
+  actor text not null,
+  action text not null,
+  entity_type text not null,
+  entity_id uuid not null,
+  -- true when a guarded change was pushed through with `force`
+  forced boolean not null default false,
+  details jsonb not null default '{}'::jsonb,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists idx_admin_audit_log_entity on admin_audit_log(entity_type, entity_id, created_at);
+create index if not exists idx_admin_audit_log_created on admin_audit_log(created_at);
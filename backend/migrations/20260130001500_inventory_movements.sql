@@ -0,0 +1,27 @@
+-- inventory_movements: ledger of every stock change per ticket type.
+-- sum(total_delta) = inventory_total and sum(remaining_delta) = inventory_remaining.
+
+create table if not exists inventory_movements (
+  id uuid primary key,
+  ticket_type_id uuid not null references ticket_types(id) on delete cascade,
+  kind text not null check (kind in ('INITIAL','RESERVE','RELEASE','ADJUST')),
+  total_delta int not null,
+  remaining_delta int not null,
+  order_id uuid null,
+  actor text null,
+  reason text null,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists idx_inventory_movements_ticket_type
+  on inventory_movements(ticket_type_id, created_at);
+
+-- seats can now be adjusted down to zero
+alter table ticket_types drop constraint if exists ticket_types_inventory_total_check;
+alter table ticket_types add constraint ticket_types_inventory_total_check check (inventory_total >= 0);
+
+-- opening balance for existing ticket types
+insert into inventory_movements (id, ticket_type_id, kind, total_delta, remaining_delta, reason)
+select gen_random_uuid(), id, 'INITIAL', inventory_total, inventory_remaining, 'backfill'
+from ticket_types t
+where not exists (select 1 from inventory_movements m where m.ticket_type_id = t.id);
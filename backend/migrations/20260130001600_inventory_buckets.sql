@@ -0,0 +1,28 @@
+-- sharded inventory: stock of a hot ticket type split across bucket rows so concurrent
+-- grabs don't all queue on the ticket_types row.
+
+-- null = unsharded (stock lives in ticket_types.inventory_remaining)
+alter table ticket_types add column if not exists inventory_shards int null;
+alter table ticket_types drop constraint if exists ticket_types_inventory_shards_check;
+alter table ticket_types add constraint ticket_types_inventory_shards_check
+  check (inventory_shards is null or inventory_shards >= 2);
+
+create table if not exists inventory_buckets (
+  ticket_type_id uuid not null references ticket_types(id) on delete cascade,
+  bucket int not null,
+  remaining int not null check (remaining >= 0),
+  primary key (ticket_type_id, bucket)
+);
+
+-- Remaining stock of a ticket type whichever way it is stored. For sharded ticket types
+-- ticket_types.inventory_remaining stays 0 and the buckets hold the stock.
+create or replace function ticket_type_remaining(tt_id uuid, shards int, remaining int)
+returns int
+language sql
+stable
+as $$
+  select case
+    when shards is null then remaining
+    else (select coalesce(sum(b.remaining), 0)::int from inventory_buckets b where b.ticket_type_id = tt_id)
+  end
+$$;
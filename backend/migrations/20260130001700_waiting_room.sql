@@ -0,0 +1,25 @@
+-- waiting room: per ticket type queue in front of grab for on-sale moments.
+
+alter table ticket_types add column if not exists queue_enabled boolean not null default false;
+-- admitted users who haven't bought yet (and whose admission hasn't expired), at most
+alter table ticket_types add column if not exists queue_max_active int not null default 100;
+alter table ticket_types drop constraint if exists ticket_types_queue_max_active_check;
+alter table ticket_types add constraint ticket_types_queue_max_active_check check (queue_max_active > 0);
+
+create table if not exists queue_entries (
+  id uuid primary key,
+  ticket_type_id uuid not null references ticket_types(id) on delete cascade,
+  user_id uuid not null references users(id) on delete cascade,
+  joined_at timestamptz not null default now(),
+  -- draw order for everyone who joined before the sale opened
+  lottery_key double precision not null default random(),
+  -- assigned once the sale is open: lottery order first, then join order
+  position bigint null,
+  admitted_at timestamptz null,
+  admission_expires_at timestamptz null,
+  -- set by the grab that spent the admission
+  used_at timestamptz null,
+  unique (ticket_type_id, user_id)
+);
+
+create index if not exists idx_queue_entries_position on queue_entries(ticket_type_id, position);
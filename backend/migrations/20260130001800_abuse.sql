@@ -0,0 +1,43 @@
+-- anti-abuse: admin-managed blocklist, grab challenges, per-IP login tracking and a log of
+-- every rejection.
+
+create table if not exists blocklist (
+  id uuid primary key,
+  kind text not null check (kind in ('user', 'ip')),
+  -- user id or IP address, as text
+  value text not null,
+  reason text not null,
+  actor text not null,
+  created_at timestamptz not null default now(),
+  -- null = until removed
+  expires_at timestamptz null,
+  unique (kind, value)
+);
+
+create table if not exists abuse_events (
+  id uuid primary key,
+  reason text not null,
+  user_id uuid null,
+  ip text null,
+  path text null,
+  details jsonb not null default '{}'::jsonb,
+  created_at timestamptz not null default now()
+);
+
+create index if not exists idx_abuse_events_created_at on abuse_events(created_at desc);
+
+-- spent grab challenges (single use); rows can go once expired
+create table if not exists grab_challenge_redemptions (
+  nonce uuid primary key,
+  user_id uuid not null,
+  expires_at timestamptz not null,
+  redeemed_at timestamptz not null default now()
+);
+
+-- accounts seen logging in from each IP
+create table if not exists login_ips (
+  ip text not null,
+  user_id uuid not null references users(id) on delete cascade,
+  last_login_at timestamptz not null default now(),
+  primary key (ip, user_id)
+);
@@ -0,0 +1,18 @@
+-- purchase_intents: leased claims, retry backoff and a terminal FAILED status.
+
+alter table purchase_intents drop constraint if exists purchase_intents_status_check;
+alter table purchase_intents
+  add constraint purchase_intents_status_check
+  check (status in ('ACTIVE','FULFILLED','CANCELED','FAILED'));
+
+alter table purchase_intents
+  -- failed fulfilment attempts; waiting for the sale to open doesn't count
+  add column if not exists attempts int not null default 0,
+  add column if not exists next_attempt_at timestamptz not null default now(),
+  -- worker holding the intent until locked_until; a crashed worker's lease just runs out
+  add column if not exists locked_by text null,
+  add column if not exists locked_until timestamptz null;
+
+create index if not exists idx_purchase_intents_due
+  on purchase_intents(next_attempt_at)
+  where status = 'ACTIVE';
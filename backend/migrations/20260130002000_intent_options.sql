@@ -0,0 +1,14 @@
+-- purchase_intents: expiry, a price ceiling and fallback ticket types.
+
+alter table purchase_intents drop constraint if exists purchase_intents_status_check;
+alter table purchase_intents
+  add constraint purchase_intents_status_check
+  check (status in ('ACTIVE','FULFILLED','CANCELED','FAILED','EXPIRED'));
+
+alter table purchase_intents
+  -- null = until fulfilled or canceled
+  add column if not exists expires_at timestamptz null,
+  -- null = any price
+  add column if not exists max_price_cents bigint null check (max_price_cents >= 0),
+  -- tried in order after ticket_type_id
+  add column if not exists fallback_ticket_type_ids uuid[] not null default '{}';
@@ -0,0 +1,20 @@
+-- ticket_types: how stock is split between purchase intents and direct grabs.
+
+alter table ticket_types
+  -- 'equal': first come, first served; 'reserved_share': intent_reserved_pct of the stock
+  -- only intents can take; 'head_start': grabs open intent_head_start_secs after intents
+  add column if not exists intent_policy text not null default 'equal',
+  add column if not exists intent_reserved_pct int null check (intent_reserved_pct between 1 and 100),
+  add column if not exists intent_head_start_secs int null check (intent_head_start_secs > 0),
+  -- part of inventory_remaining still held for intents; intents draw it down first
+  add column if not exists intent_reserved_remaining int not null default 0;
+
+alter table ticket_types drop constraint if exists ticket_types_intent_policy_check;
+alter table ticket_types
+  add constraint ticket_types_intent_policy_check
+  check (intent_policy in ('equal','reserved_share','head_start'));
+
+alter table ticket_types drop constraint if exists ticket_types_intent_reserved_check;
+alter table ticket_types
+  add constraint ticket_types_intent_reserved_check
+  check (intent_reserved_remaining >= 0 and intent_reserved_remaining <= inventory_remaining);
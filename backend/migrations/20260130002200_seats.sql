@@ -0,0 +1,19 @@
+-- assigned seating: seats under a ticket type, held by CREATED orders and sold once paid.
+
+alter table ticket_types add column if not exists seated boolean not null default false;
+
+create table if not exists seats (
+  id uuid primary key,
+  ticket_type_id uuid not null references ticket_types(id) on delete cascade,
+  section text not null,
+  row_label text not null,
+  number int not null check (number > 0),
+  status text not null default 'AVAILABLE' check (status in ('AVAILABLE','HELD','SOLD')),
+  -- the order holding or owning the seat; checked at commit, as seats are taken together
+  -- with the stock, before the order row is inserted
+  order_id uuid null references orders(id) deferrable initially deferred,
+  unique (ticket_type_id, section, row_label, number),
+  constraint seats_order_check check ((status = 'AVAILABLE') = (order_id is null))
+);
+
+create index if not exists idx_seats_order on seats(order_id) where order_id is not null;
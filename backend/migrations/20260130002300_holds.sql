@@ -0,0 +1,33 @@
+-- holds: stock (and seats) set aside for a user until expires_at, turned into an order at
+-- checkout. Taken and returned through the same counters as orders.
+
+create table if not exists holds (
+  id uuid primary key,
+  user_id uuid not null references users(id) on delete cascade,
+  ticket_type_id uuid not null references ticket_types(id) on delete cascade,
+  qty int not null check (qty > 0),
+  -- terms when the stock was taken; checkout charges these
+  price_cents bigint not null,
+  payment_window_secs int null,
+  status text not null default 'ACTIVE'
+    check (status in ('ACTIVE','CONVERTED','RELEASED','EXPIRED')),
+  expires_at timestamptz not null,
+  -- set at checkout
+  order_id uuid null references orders(id),
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now()
+);
+
+create index if not exists idx_holds_expiry on holds(expires_at) where status = 'ACTIVE';
+create index if not exists idx_holds_user on holds(user_id, created_at);
+
+alter table inventory_movements add column if not exists hold_id uuid null;
+
+alter table seats add column if not exists hold_id uuid null
+  references holds(id) deferrable initially deferred;
+alter table seats drop constraint if exists seats_order_check;
+alter table seats
+  add constraint seats_order_check
+  check ((status = 'AVAILABLE') = (order_id is null and hold_id is null)
+         and (order_id is null or hold_id is null));
+create index if not exists idx_seats_hold on seats(hold_id) where hold_id is not null;
@@ -0,0 +1,43 @@
+-- promo codes: discounts applied at grab, counted in the same transaction as the order.
+
+create table if not exists promo_codes (
+  id uuid primary key,
+  -- matched case-insensitively
+  code text not null,
+  kind text not null check (kind in ('percent','fixed')),
+  percent_off int null check (percent_off between 1 and 100),
+  -- per order, capped at the order total
+  amount_off_cents bigint null check (amount_off_cents > 0),
+  max_uses int null check (max_uses > 0),
+  -- redemptions by orders that weren't canceled
+  uses int not null default 0,
+  once_per_user boolean not null default false,
+  valid_from timestamptz null,
+  valid_until timestamptz null,
+  -- empty: any event / ticket type
+  event_ids uuid[] not null default '{}',
+  ticket_type_ids uuid[] not null default '{}',
+  active boolean not null default true,
+  created_at timestamptz not null default now(),
+  updated_at timestamptz not null default now(),
+  constraint promo_codes_discount_check
+    check ((kind = 'percent') = (percent_off is not null)
+           and (kind = 'fixed') = (amount_off_cents is not null)),
+  constraint promo_codes_uses_check check (uses >= 0 and (max_uses is null or uses <= max_uses))
+);
+
+create unique index if not exists uq_promo_codes_code on promo_codes(upper(code));
+
+create table if not exists promo_redemptions (
+  id uuid primary key,
+  promo_code_id uuid not null references promo_codes(id),
+  user_id uuid not null references users(id) on delete cascade,
+  -- redeemed before the order row is inserted, in the same transaction
+  order_id uuid not null unique references orders(id) deferrable initially deferred,
+  discount_cents bigint not null check (discount_cents >= 0),
+  created_at timestamptz not null default now(),
+  -- the order was canceled and the use given back
+  canceled_at timestamptz null
+);
+
+create index if not exists idx_promo_redemptions_code_user on promo_redemptions(promo_code_id, user_id);
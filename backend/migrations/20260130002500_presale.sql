@@ -0,0 +1,28 @@
+-- presale: an early window before sale_starts_at for allow-listed users and presale code holders.
+
+alter table ticket_types add column if not exists presale_starts_at timestamptz null;
+
+alter table ticket_types drop constraint if exists ticket_types_presale_check;
+alter table ticket_types
+  add constraint ticket_types_presale_check
+  check (presale_starts_at is null or presale_starts_at < sale_starts_at);
+
+create table if not exists presale_allowlist (
+  ticket_type_id uuid not null references ticket_types(id) on delete cascade,
+  user_id uuid not null references users(id) on delete cascade,
+  created_at timestamptz not null default now(),
+  primary key (ticket_type_id, user_id)
+);
+
+create table if not exists presale_codes (
+  id uuid primary key,
+  ticket_type_id uuid not null references ticket_types(id) on delete cascade,
+  code text not null unique,
+  -- grabs during the presale the code can unlock
+  max_uses int not null check (max_uses > 0),
+  uses int not null default 0,
+  created_at timestamptz not null default now(),
+  constraint presale_codes_uses_check check (uses >= 0 and uses <= max_uses)
+);
+
+create index if not exists idx_presale_codes_ticket_type on presale_codes(ticket_type_id);
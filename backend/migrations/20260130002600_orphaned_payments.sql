@@ -0,0 +1,10 @@
+-- orphaned payments: the provider captured money for an order that was no longer payable
+-- (canceled or past its deadline). Kept apart from SUCCEEDED so support can refund them.
+
+alter table payments drop constraint if exists payments_status_check;
+alter table payments add constraint payments_status_check
+  check (status in ('PENDING','SUCCEEDED','SUCCEEDED_ORPHANED','FAILED'));
+
+create index if not exists idx_payments_orphaned
+  on payments(updated_at)
+  where status = 'SUCCEEDED_ORPHANED';
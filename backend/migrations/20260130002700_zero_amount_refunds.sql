@@ -0,0 +1,5 @@
+-- zero-amount refunds: free (or fully discounted) paid orders are refunded for 0 so they can
+-- still reach REFUNDED and give their stock back.
+
+alter table refunds drop constraint if exists refunds_amount_cents_check;
+alter table refunds add constraint refunds_amount_cents_check check (amount_cents >= 0);
@@ -0,0 +1,6 @@
+-- orders: units an intent order took out of ticket_types.intent_reserved_remaining, so
+-- canceling, expiring or refunding it can hand them back to the reserve.
+
+alter table orders
+  add column if not exists intent_reserved_qty int not null default 0
+    check (intent_reserved_qty >= 0);
@@ -0,0 +1,4 @@
+-- payments: an attempt is recorded before the provider is called (outside the order lock),
+-- so provider_ref is null until the provider has opened the session.
+
+alter table payments alter column provider_ref drop not null;
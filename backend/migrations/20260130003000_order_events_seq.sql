@@ -0,0 +1,7 @@
+-- order_events: events written in one transaction share created_at (now()); seq keeps
+-- them in insertion order.
+
+alter table order_events add column if not exists seq bigserial;
+
+drop index if exists idx_order_events_order;
+create index if not exists idx_order_events_order on order_events(order_id, created_at, seq);
@@ -0,0 +1,295 @@
+//! Anti-abuse checks: admin blocklist, grab proof-of-work challenges and the per-IP login
+//! account limit. Every rejection is written to `abuse_events`.
+
+use std::{
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+    sync::RwLock,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::Extensions,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgPool};
+use uuid::Uuid;
+
+use crate::{
+    error::{AbuseReason, AppError, AppResult},
+    state::AppState,
+};
+
+/// Grab headers carrying a solved challenge.
+pub const CHALLENGE_HEADER: &str = "x-grab-challenge";
+pub const SOLUTION_HEADER: &str = "x-grab-solution";
+
+const CHALLENGE_AUDIENCE: &str = "grab-challenge";
+const MAX_SOLUTION_LEN: usize = 64;
+
+/// The connecting peer, with IPv4-mapped IPv6 addresses unwrapped to match blocklist entries.
+pub fn peer_ip(extensions: &Extensions) -> Option<IpAddr> {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical())
+}
+
+pub struct AbuseEvent<'a> {
+    pub reason: AbuseReason,
+    pub user_id: Option<Uuid>,
+    pub ip: Option<IpAddr>,
+    pub path: Option<&'a str>,
+    pub details: serde_json::Value,
+}
+
+/// Best effort: the request is rejected either way, so a failed insert is only logged.
+pub async fn report(pool: &PgPool, event: AbuseEvent<'_>) {
+    let res = sqlx::query(
+        r#"insert into abuse_events (id, reason, user_id, ip, path, details)
+           values ($1,$2,$3,$4,$5,$6)"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(event.reason.as_str())
+    .bind(event.user_id)
+    .bind(event.ip.map(|ip| ip.to_string()))
+    .bind(event.path)
+    .bind(event.details)
+    .execute(pool)
+    .await;
+    if let Err(e) = res {
+        tracing::warn!(err = ?e, reason = event.reason.as_str(), "failed to record abuse event");
+    }
+}
+
+/// In-memory copy of the active `blocklist` rows, reloaded periodically and after every
+/// admin change.
+#[derive(Default)]
+pub struct Blocklist {
+    users: RwLock<HashSet<Uuid>>,
+    ips: RwLock<HashSet<IpAddr>>,
+}
+
+impl Blocklist {
+    pub fn is_user_blocked(&self, user_id: Uuid) -> bool {
+        self.users
+            .read()
+            .expect("blocklist lock poisoned")
+            .contains(&user_id)
+    }
+
+    pub fn is_ip_blocked(&self, ip: IpAddr) -> bool {
+        self.ips
+            .read()
+            .expect("blocklist lock poisoned")
+            .contains(&ip)
+    }
+
+    pub async fn refresh(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let rows: Vec<(String, String)> = sqlx::query_as(
+            "select kind, value from blocklist where expires_at is null or expires_at > now()",
+        )
+        .fetch_all(pool)
+        .await?;
+        let mut users = HashSet::new();
+        let mut ips = HashSet::new();
+        for (kind, value) in rows {
+            match kind.as_str() {
+                "user" => users.extend(value.parse::<Uuid>().ok()),
+                _ => ips.extend(value.parse::<IpAddr>().ok()),
+            }
+        }
+        *self.users.write().expect("blocklist lock poisoned") = users;
+        *self.ips.write().expect("blocklist lock poisoned") = ips;
+        Ok(())
+    }
+}
+
+/// Router middleware rejecting blocked IPs (blocked users are caught by `AuthUser`).
+pub async fn reject_blocked_ips(
+    State(state): State<AppState>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(ip) = peer_ip(req.extensions()) else {
+        return next.run(req).await;
+    };
+    if req.uri().path() == "/healthz" || !state.blocklist.is_ip_blocked(ip) {
+        return next.run(req).await;
+    }
+    report(
+        &state.db.pool,
+        AbuseEvent {
+            reason: AbuseReason::BlockedIp,
+            user_id: None,
+            ip: Some(ip),
+            path: Some(req.uri().path()),
+            details: serde_json::json!({}),
+        },
+    )
+    .await;
+    AppError::Abuse(AbuseReason::BlockedIp).into_response()
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChallengeClaims {
+    /// User id.
+    sub: String,
+    jti: String,
+    /// Leading zero bits required of `sha256(challenge || solution)`.
+    difficulty: u32,
+    aud: String,
+    exp: usize,
+}
+
+/// Signs a new single-use challenge for `user_id`.
+pub fn issue_challenge(
+    secret: &str,
+    user_id: Uuid,
+    difficulty: u32,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<String> {
+    let claims = ChallengeClaims {
+        sub: user_id.to_string(),
+        jti: Uuid::new_v4().to_string(),
+        difficulty,
+        aud: CHALLENGE_AUDIENCE.to_string(),
+        exp: expires_at.timestamp() as usize,
+    };
+    Ok(jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?)
+}
+
+/// Whether `sha256(challenge || solution)` starts with `difficulty` zero bits.
+pub fn solves(challenge: &str, solution: &str, difficulty: u32) -> bool {
+    let digest = Sha256::new()
+        .chain_update(challenge.as_bytes())
+        .chain_update(solution.as_bytes())
+        .finalize();
+    let mut bits = 0;
+    for byte in digest {
+        if byte == 0 {
+            bits += 8;
+            continue;
+        }
+        bits += byte.leading_zeros();
+        break;
+    }
+    bits >= difficulty
+}
+
+/// A checked challenge, still to be redeemed with [`redeem_challenge`].
+pub struct SolvedChallenge {
+    pub nonce: Uuid,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Checks signature, owner, expiry and proof of work; no database access.
+pub fn verify_challenge(
+    secret: &str,
+    user_id: Uuid,
+    min_difficulty: u32,
+    challenge: Option<&str>,
+    solution: Option<&str>,
+) -> Result<SolvedChallenge, AbuseReason> {
+    let (Some(challenge), Some(solution)) = (challenge, solution) else {
+        return Err(AbuseReason::ChallengeRequired);
+    };
+    let mut validation = Validation::default();
+    validation.set_audience(&[CHALLENGE_AUDIENCE]);
+    let claims = jsonwebtoken::decode::<ChallengeClaims>(
+        challenge,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )
+    .map_err(|_| AbuseReason::ChallengeInvalid)?
+    .claims;
+    // Challenges issued before the difficulty was raised don't count.
+    if claims.sub != user_id.to_string()
+        || claims.difficulty < min_difficulty
+        || solution.len() > MAX_SOLUTION_LEN
+        || !solves(challenge, solution, claims.difficulty)
+    {
+        return Err(AbuseReason::ChallengeInvalid);
+    }
+    let nonce = Uuid::parse_str(&claims.jti).map_err(|_| AbuseReason::ChallengeInvalid)?;
+    let expires_at =
+        DateTime::from_timestamp(claims.exp as i64, 0).ok_or(AbuseReason::ChallengeInvalid)?;
+    Ok(SolvedChallenge { nonce, expires_at })
+}
+
+/// Marks the challenge spent; `false` if it already was. Runs in the grab's transaction, so
+/// a grab that fails afterwards leaves the challenge usable.
+pub async fn redeem_challenge(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    solved: &SolvedChallenge,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query(
+        r#"insert into grab_challenge_redemptions (nonce, user_id, expires_at)
+           values ($1,$2,$3)
+           on conflict (nonce) do nothing"#,
+    )
+    .bind(solved.nonce)
+    .bind(user_id)
+    .bind(solved.expires_at)
+    .execute(&mut *conn)
+    .await?;
+    Ok(inserted.rows_affected() == 1)
+}
+
+/// Enforces `LOGIN_MAX_ACCOUNTS_PER_IP` and records the login. Logging in again to an
+/// account already seen from the IP is always allowed.
+pub async fn check_login_ip(
+    pool: &PgPool,
+    ip: IpAddr,
+    user_id: Uuid,
+    max_accounts: u32,
+    window_secs: i64,
+) -> AppResult<()> {
+    let others: i64 = sqlx::query_scalar(
+        r#"select count(*) from login_ips
+           where ip = $1 and user_id <> $2
+             and last_login_at > now() - make_interval(secs => $3)
+             and not exists (
+               select 1 from login_ips mine
+               where mine.ip = $1 and mine.user_id = $2
+                 and mine.last_login_at > now() - make_interval(secs => $3))"#,
+    )
+    .bind(ip.to_string())
+    .bind(user_id)
+    .bind(window_secs as f64)
+    .fetch_one(pool)
+    .await?;
+    if others >= max_accounts as i64 {
+        report(
+            pool,
+            AbuseEvent {
+                reason: AbuseReason::TooManyAccounts,
+                user_id: Some(user_id),
+                ip: Some(ip),
+                path: Some("/api/auth/login"),
+                details: serde_json::json!({ "accounts_in_window": others }),
+            },
+        )
+        .await;
+        return Err(AppError::Abuse(AbuseReason::TooManyAccounts));
+    }
+
+    sqlx::query(
+        r#"insert into login_ips (ip, user_id) values ($1, $2)
+           on conflict (ip, user_id) do update set last_login_at = now()"#,
+    )
+    .bind(ip.to_string())
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
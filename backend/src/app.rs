@@ -1,38 +1,119 @@
-use axum::{http::StatusCode, response::IntoResponse, routing::get, Router};
-use tower_governor::{governor::GovernorConfigBuilder, GovernorLayer};
+use std::sync::Arc;
+
+use axum::{http::StatusCode, middleware, routing::get, Router};
 use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
-use crate::{config::Config, db::Db, openapi::ApiDoc, routes, worker};
+use crate::{
+    abuse::{self, Blocklist},
+    auth,
+    config::Config,
+    db::Db,
+    openapi::ApiDoc,
+    payments,
+    rate_limit::{self, RateLimiter},
+    routes,
+    state::AppState,
+    stock_gate::StockGate,
+    worker,
+};
+
+pub async fn build_router(cfg: Config, db: Db) -> anyhow::Result<Router> {
+    cfg.validate()?;
+    auth::bootstrap_first_admin(&db.pool, &cfg).await?;
+
+    let rate_limiter = Arc::new(RateLimiter::new(cfg.rate_limits, cfg.jwt_secret.clone()));
+    rate_limit::spawn_sweeper(rate_limiter.clone(), std::time::Duration::from_secs(60));
+
+    let stock_gate = if cfg.stock_gate_refresh_ms > 0 {
+        let gate = Arc::new(StockGate::default());
+        gate.refresh(&db.pool).await?;
+        worker::spawn_stock_gate_refresh(
+            db.clone(),
+            gate.clone(),
+            std::time::Duration::from_millis(cfg.stock_gate_refresh_ms),
+        );
+        gate
+    } else {
+        Arc::new(StockGate::disabled())
+    };
 
-pub async fn build_router(cfg: Config, db: Db) -> anyhow::Result<Router<Db>> {
-    let governor_conf = GovernorConfigBuilder::default()
-        .per_second(cfg.rate_limit_rps)
-        .burst_size(cfg.rate_limit_burst)
-        .finish()
-        .expect("valid governor config");
+    let payments = payments::provider_from_config(&cfg)?;
+
+    let blocklist = Arc::new(Blocklist::default());
+    blocklist.refresh(&db.pool).await?;
+    worker::spawn_blocklist_refresh(
+        db.clone(),
+        blocklist.clone(),
+        std::time::Duration::from_millis(cfg.blocklist_refresh_ms),
+    );
 
     // Background worker for internal "auto-buy" intents.
-    worker::spawn_intent_worker(db.clone());
+    if cfg.intent_worker_embedded {
+        worker::spawn_intent_worker(db.clone(), cfg.intent_worker);
+    }
+    // Cancels unpaid orders past their payment deadline.
+    worker::spawn_order_reaper(
+        db.clone(),
+        stock_gate.clone(),
+        payments.clone(),
+        std::time::Duration::from_millis(cfg.order_reaper_interval_ms),
+    );
+
+    // Returns the stock of holds past their expiry.
+    worker::spawn_hold_sweeper(
+        db.clone(),
+        stock_gate.clone(),
+        std::time::Duration::from_millis(cfg.hold_sweep_interval_ms),
+    );
+
+    worker::spawn_queue_admitter(
+        db.clone(),
+        cfg.queue_admission_ttl_secs,
+        std::time::Duration::from_millis(cfg.queue_tick_ms),
+    );
+
+    let state = AppState {
+        payments,
+        cfg,
+        db,
+        stock_gate,
+        blocklist,
+    };
 
     let app = Router::new()
-        .with_state(db)
         .merge(routes::health::router())
         .merge(routes::auth::router())
         .merge(routes::admin::router())
+        .merge(routes::inventory::router())
+        .merge(routes::promo_codes::router())
+        .merge(routes::presale::router())
         .merge(routes::seckill::router())
+        .merge(routes::seats::router())
+        .merge(routes::holds::router())
         .merge(routes::orders::router())
         .merge(routes::purchase_intents::router())
+        .merge(routes::queue::router())
+        .merge(routes::refunds::router())
+        .merge(routes::payments::router(state.payments.name() == "mock"))
+        .merge(routes::abuse::router())
         .route("/", get(|| async { (StatusCode::OK, "ticket-seckill-backend") }))
         .merge(
             SwaggerUi::new("/docs").url("/api-doc/openapi.json", ApiDoc::openapi()),
         )
         .layer(CorsLayer::very_permissive())
         .layer(TraceLayer::new_for_http())
-        .layer(GovernorLayer {
-            config: std::sync::Arc::new(governor_conf),
-        })
-        .fallback(|| async { (StatusCode::NOT_FOUND, "not found") }.into_response());
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            abuse::reject_blocked_ips,
+        ))
+        .layer(middleware::from_fn_with_state(
+            rate_limiter,
+            rate_limit::limit,
+        ))
+        .fallback(|| async { (StatusCode::NOT_FOUND, "not found") })
+        .with_state(state);
 
     Ok(app)
 }
@@ -0,0 +1,32 @@
+//! Admin audit trail (`admin_audit_log`).
+
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+/// One admin change, written in the same transaction as the change itself.
+pub struct AuditEntry<'a> {
+    pub actor: &'a str,
+    /// e.g. `event.update`, `ticket_type.delete`.
+    pub action: &'a str,
+    pub entity_type: &'a str,
+    pub entity_id: Uuid,
+    pub forced: bool,
+    pub details: serde_json::Value,
+}
+
+pub async fn record(conn: &mut PgConnection, entry: AuditEntry<'_>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"insert into admin_audit_log (id, actor, action, entity_type, entity_id, forced, details)
+           values ($1,$2,$3,$4,$5,$6,$7)"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(entry.actor)
+    .bind(entry.action)
+    .bind(entry.entity_type)
+    .bind(entry.entity_id)
+    .bind(entry.forced)
+    .bind(entry.details)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
@@ -1,14 +1,61 @@
-use axum::{async_trait, extract::FromRequestParts, http::{request::Parts, StatusCode}};
+use std::sync::OnceLock;
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts, response::IntoResponse};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::error::AppError;
+use crate::{
+    abuse::{self, AbuseEvent},
+    config::Config,
+    error::{AbuseReason, AppError},
+    state::AppState,
+};
+
+/// `users.role`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    #[default]
+    User,
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Role::User => "user",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl std::str::FromStr for Role {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "user" => Ok(Role::User),
+            "admin" => Ok(Role::Admin),
+            other => anyhow::bail!("unknown role: {other}"),
+        }
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub uid: String,
+    /// Tokens issued before roles existed carry none and decode as `user`.
+    #[serde(default)]
+    pub role: Role,
     pub exp: usize,
 }
 
@@ -16,31 +63,42 @@ pub struct Claims {
 pub struct AuthUser {
     pub user_id: Uuid,
     pub username: String,
+    pub role: Role,
 }
 
-pub fn jwt_secret() -> String {
-    std::env::var("DEV_JWT_SECRET").unwrap_or_else(|_| "dev-secret-change-me".to_string())
-}
+/// An [`AuthUser`] whose token carries the `admin` role; rejects others with 403.
+///
+/// The role is read from the access token, so a role change applies on the next refresh.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub AuthUser);
 
-pub fn issue_token(user_id: Uuid, username: &str) -> anyhow::Result<String> {
-    let exp = (chrono::Utc::now() + chrono::Duration::hours(24)).timestamp() as usize;
+/// Signs a short-lived access token.
+pub fn issue_token(
+    secret: &str,
+    ttl_secs: i64,
+    user_id: Uuid,
+    username: &str,
+    role: Role,
+) -> anyhow::Result<String> {
+    let exp = (chrono::Utc::now() + chrono::Duration::seconds(ttl_secs)).timestamp() as usize;
     let claims = Claims {
         sub: username.to_string(),
         uid: user_id.to_string(),
+        role,
         exp,
     };
     let token = jsonwebtoken::encode(
         &Header::default(),
         &claims,
-        &EncodingKey::from_secret(jwt_secret().as_bytes()),
+        &EncodingKey::from_secret(secret.as_bytes()),
     )?;
     Ok(token)
 }
 
-pub fn decode_token(token: &str) -> Result<AuthUser, AppError> {
+pub fn decode_token(secret: &str, token: &str) -> Result<AuthUser, AppError> {
     let data = jsonwebtoken::decode::<Claims>(
         token,
-        &DecodingKey::from_secret(jwt_secret().as_bytes()),
+        &DecodingKey::from_secret(secret.as_bytes()),
         &Validation::default(),
     )
     .map_err(|_| AppError::Unauthorized)?;
@@ -49,17 +107,56 @@ pub fn decode_token(token: &str) -> Result<AuthUser, AppError> {
     Ok(AuthUser {
         user_id,
         username: data.claims.sub,
+        role: data.claims.role,
     })
 }
 
+/// Argon2id hash in PHC string format.
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("hash password: {e}"))?;
+    Ok(hash.to_string())
+}
+
+pub fn verify_password(password: &str, password_hash: &str) -> bool {
+    let Ok(parsed) = PasswordHash::new(password_hash) else {
+        return false;
+    };
+    Argon2::default()
+        .verify_password(password.as_bytes(), &parsed)
+        .is_ok()
+}
+
+/// Hash of a throwaway password, checked against when the user doesn't exist so a failed
+/// login costs one argon2 verify either way. Made with the same parameters as real hashes.
+pub fn dummy_password_hash() -> &'static str {
+    static HASH: OnceLock<String> = OnceLock::new();
+    HASH.get_or_init(|| hash_password("dummy password").expect("hashing a fixed password"))
+}
+
+/// Returns a new opaque refresh token and the hash stored in `refresh_tokens.token_hash`.
+pub fn new_refresh_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let hash = hash_refresh_token(&token);
+    (token, hash)
+}
+
+pub fn hash_refresh_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
 #[async_trait]
-impl<S> FromRequestParts<S> for AuthUser
-where
-    S: Send + Sync,
-{
+impl FromRequestParts<AppState> for AuthUser {
     type Rejection = axum::response::Response;
 
-    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
         let auth = parts
             .headers
             .get(axum::http::header::AUTHORIZATION)
@@ -71,6 +168,116 @@ where
             _ => return Err(AppError::Unauthorized.into_response()),
         };
 
-        decode_token(token).map_err(|e| e.into_response())
+        let user = decode_token(&state.cfg.jwt_secret, token).map_err(|e| e.into_response())?;
+        // Tokens stay valid until they expire, so blocks are enforced here, on every request.
+        if state.blocklist.is_user_blocked(user.user_id) {
+            abuse::report(
+                &state.db.pool,
+                AbuseEvent {
+                    reason: AbuseReason::BlockedUser,
+                    user_id: Some(user.user_id),
+                    ip: abuse::peer_ip(&parts.extensions),
+                    path: Some(parts.uri.path()),
+                    details: serde_json::json!({}),
+                },
+            )
+            .await;
+            return Err(AppError::Abuse(AbuseReason::BlockedUser).into_response());
+        }
+        Ok(user)
     }
 }
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminUser {
+    type Rejection = axum::response::Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let user = AuthUser::from_request_parts(parts, state).await?;
+        if user.role != Role::Admin {
+            return Err(AppError::Forbidden.into_response());
+        }
+        Ok(AdminUser(user))
+    }
+}
+
+/// Startup hook for `ADMIN_BOOTSTRAP_USERNAME`: does nothing once any admin exists.
+///
+/// Needs `ADMIN_BOOTSTRAP_PASSWORD`; an account someone already registered under that name
+/// is only promoted if the configured password is its password.
+pub async fn bootstrap_first_admin(pool: &PgPool, cfg: &Config) -> anyhow::Result<()> {
+    let Some(username) = cfg.admin_bootstrap_username.as_deref() else {
+        return Ok(());
+    };
+    let has_admin: bool =
+        sqlx::query_scalar("select exists(select 1 from users where role = 'admin')")
+            .fetch_one(pool)
+            .await?;
+    if !has_admin {
+        let Some(password) = cfg.admin_bootstrap_password.as_deref() else {
+            anyhow::bail!("ADMIN_BOOTSTRAP_PASSWORD must be set with ADMIN_BOOTSTRAP_USERNAME");
+        };
+        bootstrap_admin(pool, username, Some(password)).await?;
+        tracing::info!(%username, "bootstrapped first admin");
+    }
+    Ok(())
+}
+
+/// Makes `username` an admin, creating the account if needed.
+///
+/// Backs both [`bootstrap_first_admin`] and the `create-admin` command. Creating the
+/// account needs `password`. An existing account keeps its password and, when `password`
+/// is given, is only promoted if it matches.
+pub async fn bootstrap_admin(
+    pool: &PgPool,
+    username: &str,
+    password: Option<&str>,
+) -> anyhow::Result<Uuid> {
+    let username = username.trim();
+    if username.is_empty() {
+        anyhow::bail!("admin username must not be empty");
+    }
+
+    let existing: Option<(Uuid, Option<String>)> =
+        sqlx::query_as("select id, password_hash from users where username = $1")
+            .bind(username)
+            .fetch_optional(pool)
+            .await?;
+    if let Some((user_id, password_hash)) = existing {
+        if let Some(password) = password {
+            let matches = password_hash
+                .as_deref()
+                .is_some_and(|hash| verify_password(password, hash));
+            if !matches {
+                anyhow::bail!(
+                    "user {username} already exists with a different password; refusing to make it an admin"
+                );
+            }
+        }
+        sqlx::query("update users set role = 'admin' where id = $1")
+            .bind(user_id)
+            .execute(pool)
+            .await?;
+        return Ok(user_id);
+    }
+
+    let Some(password) = password else {
+        anyhow::bail!("user {username} does not exist; a password is required to create it");
+    };
+    let password_hash = hash_password(password)?;
+    let created: Option<Uuid> = sqlx::query_scalar(
+        r#"insert into users (id, username, password_hash, role)
+           values ($1, $2, $3, 'admin')
+           on conflict (username) do nothing
+           returning id"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(username)
+    .bind(password_hash)
+    .fetch_optional(pool)
+    .await?;
+    created.ok_or_else(|| anyhow::anyhow!("user {username} was registered concurrently; run again"))
+}
@@ -2,6 +2,8 @@ use axum::{http::StatusCode, response::IntoResponse, Json};
 use serde::Serialize;
 use thiserror::Error;
 
+use crate::order_state::OrderStatus;
+
 #[derive(Debug, Error)]
 pub enum AppError {
     #[error("not found")]
@@ -13,12 +15,31 @@ pub enum AppError {
     #[error("unauthorized")]
     Unauthorized,
 
+    #[error("forbidden")]
+    Forbidden,
+
     #[error("conflict: {0}")]
     Conflict(String),
 
+    #[error("waiting room admission required")]
+    AdmissionRequired,
+
+    /// Presale in progress and the buyer is neither allow-listed nor holding a usable code.
+    #[error("presale access required")]
+    PresaleRequired,
+
     #[error("too many requests")]
     TooManyRequests,
 
+    #[error("request rejected")]
+    Abuse(AbuseReason),
+
+    #[error("purchase limit exceeded")]
+    PurchaseLimit(LimitReason),
+
+    #[error("invalid order transition: {from} -> {to}")]
+    InvalidTransition { from: OrderStatus, to: OrderStatus },
+
     #[error("db error")]
     Db(#[from] sqlx::Error),
 
@@ -26,9 +47,54 @@ pub enum AppError {
     Internal(#[from] anyhow::Error),
 }
 
+/// Machine-readable reason for [`AppError::PurchaseLimit`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitReason {
+    TicketTypeLimit,
+    EventLimit,
+}
+
+impl LimitReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LimitReason::TicketTypeLimit => "ticket_type_limit",
+            LimitReason::EventLimit => "event_limit",
+        }
+    }
+}
+
+/// Machine-readable reason for [`AppError::Abuse`]; also `abuse_events.reason`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbuseReason {
+    BlockedUser,
+    BlockedIp,
+    /// `grab` without a challenge while `GRAB_POW_DIFFICULTY` is set.
+    ChallengeRequired,
+    /// Forged, expired, someone else's or unsolved.
+    ChallengeInvalid,
+    ChallengeReused,
+    /// Too many accounts logged in from one IP (`LOGIN_MAX_ACCOUNTS_PER_IP`).
+    TooManyAccounts,
+}
+
+impl AbuseReason {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AbuseReason::BlockedUser => "blocked_user",
+            AbuseReason::BlockedIp => "blocked_ip",
+            AbuseReason::ChallengeRequired => "challenge_required",
+            AbuseReason::ChallengeInvalid => "challenge_invalid",
+            AbuseReason::ChallengeReused => "challenge_reused",
+            AbuseReason::TooManyAccounts => "too_many_accounts",
+        }
+    }
+}
+
 #[derive(Serialize, Clone)]
 pub struct ErrorBody {
     pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
 
 impl AppError {
@@ -37,12 +103,29 @@ impl AppError {
             AppError::NotFound => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             AppError::Unauthorized => (StatusCode::UNAUTHORIZED, self.to_string()),
+            AppError::Forbidden => (StatusCode::FORBIDDEN, self.to_string()),
             AppError::Conflict(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::AdmissionRequired => (StatusCode::FORBIDDEN, self.to_string()),
+            AppError::PresaleRequired => (StatusCode::FORBIDDEN, self.to_string()),
             AppError::TooManyRequests => (StatusCode::TOO_MANY_REQUESTS, self.to_string()),
+            AppError::Abuse(AbuseReason::TooManyAccounts) => {
+                (StatusCode::TOO_MANY_REQUESTS, self.to_string())
+            }
+            AppError::Abuse(_) => (StatusCode::FORBIDDEN, self.to_string()),
+            AppError::PurchaseLimit(_) => (StatusCode::CONFLICT, self.to_string()),
+            AppError::InvalidTransition { .. } => (StatusCode::CONFLICT, self.to_string()),
             AppError::Db(_) => (StatusCode::INTERNAL_SERVER_ERROR, "db error".to_string()),
             AppError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "internal error".to_string()),
         };
-        (status, Json(ErrorBody { error: msg }))
+        let reason = match &self {
+            AppError::PurchaseLimit(r) => Some(r.as_str().to_string()),
+            AppError::Abuse(r) => Some(r.as_str().to_string()),
+            AppError::InvalidTransition { .. } => Some("invalid_transition".to_string()),
+            AppError::AdmissionRequired => Some("admission_required".to_string()),
+            AppError::PresaleRequired => Some("presale_required".to_string()),
+            _ => None,
+        };
+        (status, Json(ErrorBody { error: msg, reason }))
     }
 }
 
@@ -0,0 +1,29 @@
+//! Holds: stock (and seats) set aside for one user for a few minutes.
+//!
+//! A hold takes its stock through `purchase::reserve_stock` like an order does, so
+//! `inventory_remaining` already excludes it and holds plus orders can never exceed the total.
+//! Checkout turns an `ACTIVE` hold into a `CREATED` order without touching the counter again;
+//! every other way out ([`end`]) puts the stock back.
+
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::purchase::{self, Holder};
+
+/// Returns a hold's stock and seats and closes it as `status` (`RELEASED` or `EXPIRED`).
+/// The caller must have the hold row locked while it is still `ACTIVE`.
+pub async fn end(
+    conn: &mut PgConnection,
+    hold_id: Uuid,
+    ticket_type_id: Uuid,
+    qty: i32,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    purchase::release_stock(conn, ticket_type_id, qty, Holder::Hold(hold_id)).await?;
+    sqlx::query("update holds set status = $2, updated_at = now() where id = $1")
+        .bind(hold_id)
+        .bind(status)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
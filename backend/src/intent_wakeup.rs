@@ -0,0 +1,122 @@
+//! Wakes the purchase-intent workers when there may be something to buy.
+//!
+//! Writers call [`wake`] inside their transaction when stock comes back, a sale window moves
+//! or an intent is created; Postgres delivers the `NOTIFY` on commit to every worker. Workers
+//! otherwise sleep until the next known deadline (a retry, an expiring lease or intent, a sale
+//! opening), with `INTENT_WORKER_POLL_MS` as a slow fallback.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::{postgres::PgListener, PgConnection, PgPool};
+use tracing::warn;
+use uuid::Uuid;
+
+pub const CHANNEL: &str = "purchase_intents_wakeup";
+
+/// Makes the ticket type's waiting intents due now and notifies the workers, both on commit.
+///
+/// Intents a worker is attempting right now are skipped rather than waited for: that attempt
+/// sees the new state anyway, and waiting could deadlock against its ticket type lock.
+pub async fn wake(conn: &mut PgConnection, ticket_type_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"update purchase_intents set next_attempt_at = now()
+           where id in (
+             select id from purchase_intents
+             where (ticket_type_id = $1 or $1 = any(fallback_ticket_type_ids))
+               and status = 'ACTIVE' and next_attempt_at > now()
+             for update skip locked)"#,
+    )
+    .bind(ticket_type_id)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("select pg_notify($1, $2)")
+        .bind(CHANNEL)
+        .bind(ticket_type_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// When the earliest waiting intent becomes claimable or expires, or a ticket type someone
+/// is waiting for opens; `None` if nothing is waiting.
+async fn next_due(pool: &PgPool) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"select least(
+             (select min(least(greatest(next_attempt_at, coalesce(locked_until, next_attempt_at)),
+                               expires_at))
+              from purchase_intents where status = 'ACTIVE'),
+             (select min(t.sale_starts_at) from ticket_types t
+              where t.sale_starts_at > now()
+                and exists (select 1 from purchase_intents i
+                            where (i.ticket_type_id = t.id or t.id = any(i.fallback_ticket_type_ids))
+                              and i.status = 'ACTIVE')))"#,
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// One worker's view of the wakeup channel.
+pub struct Wakeups {
+    pool: PgPool,
+    fallback: Duration,
+    /// Reconnected lazily; while it's down the worker falls back to deadlines and polling.
+    listener: Option<PgListener>,
+}
+
+impl Wakeups {
+    pub fn new(pool: PgPool, fallback: Duration) -> Self {
+        Self {
+            pool,
+            fallback,
+            listener: None,
+        }
+    }
+
+    async fn listener(&mut self) -> Option<&mut PgListener> {
+        if self.listener.is_none() {
+            let connected = async {
+                let mut listener = PgListener::connect_with(&self.pool).await?;
+                listener.listen(CHANNEL).await?;
+                Ok::<_, sqlx::Error>(listener)
+            }
+            .await;
+            match connected {
+                Ok(listener) => self.listener = Some(listener),
+                Err(e) => warn!(err = ?e, "intent wakeup listener unavailable; polling"),
+            }
+        }
+        self.listener.as_mut()
+    }
+
+    /// Returns on a notification, at the next deadline or after the fallback interval,
+    /// whichever comes first.
+    pub async fn wait(&mut self) {
+        let mut sleep = self.fallback;
+        match next_due(&self.pool).await {
+            Ok(Some(due)) => {
+                let until = (due - Utc::now()).to_std().unwrap_or(Duration::ZERO);
+                sleep = sleep.min(until);
+            }
+            Ok(None) => {}
+            Err(e) => warn!(err = ?e, "intent wakeup deadline lookup failed"),
+        }
+        if sleep.is_zero() {
+            return;
+        }
+
+        let Some(listener) = self.listener().await else {
+            tokio::time::sleep(sleep).await;
+            return;
+        };
+        tokio::select! {
+            received = listener.recv() => {
+                if let Err(e) = received {
+                    warn!(err = ?e, "intent wakeup listener failed");
+                    self.listener = None;
+                }
+            }
+            _ = tokio::time::sleep(sleep) => {}
+        }
+    }
+}
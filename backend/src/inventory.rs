@@ -0,0 +1,228 @@
+//! Stock movement ledger (`inventory_movements`).
+//!
+//! Every change to `ticket_types.inventory_total` / `inventory_remaining` (or, for sharded
+//! ticket types, to their `inventory_buckets`) writes a row here in the same transaction, so
+//! per ticket type the deltas sum to the current stock (checked by [`reconcile`]).
+
+use serde::Serialize;
+use sqlx::{PgConnection, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovementKind {
+    /// Stock set up when the ticket type is created.
+    Initial,
+    /// Taken by an order or a hold.
+    Reserve,
+    /// Returned by a canceled, expired or restocked order, or a released or expired hold.
+    Release,
+    /// Manual change by an admin.
+    Adjust,
+}
+
+impl MovementKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MovementKind::Initial => "INITIAL",
+            MovementKind::Reserve => "RESERVE",
+            MovementKind::Release => "RELEASE",
+            MovementKind::Adjust => "ADJUST",
+        }
+    }
+}
+
+pub struct Movement<'a> {
+    pub ticket_type_id: Uuid,
+    pub kind: MovementKind,
+    pub total_delta: i32,
+    pub remaining_delta: i32,
+    pub order_id: Option<Uuid>,
+    pub hold_id: Option<Uuid>,
+    pub actor: Option<&'a str>,
+    pub reason: Option<&'a str>,
+}
+
+pub async fn record(conn: &mut PgConnection, m: Movement<'_>) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"insert into inventory_movements (id, ticket_type_id, kind, total_delta, remaining_delta, order_id, hold_id, actor, reason)
+           values ($1,$2,$3,$4,$5,$6,$7,$8,$9)"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(m.ticket_type_id)
+    .bind(m.kind.as_str())
+    .bind(m.total_delta)
+    .bind(m.remaining_delta)
+    .bind(m.order_id)
+    .bind(m.hold_id)
+    .bind(m.actor)
+    .bind(m.reason)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Upper bound for `ticket_types.inventory_shards`.
+pub const MAX_SHARDS: i32 = 64;
+
+/// Creates the buckets of a new sharded ticket type, spreading `qty` evenly.
+pub async fn create_buckets(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    shards: i32,
+    qty: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"insert into inventory_buckets (ticket_type_id, bucket, remaining)
+           select $1, b, $2 / $3 + case when b < $2 % $3 then 1 else 0 end
+           from generate_series(0, $3 - 1) b"#,
+    )
+    .bind(ticket_type_id)
+    .bind(qty)
+    .bind(shards)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Adds `qty` across all buckets, spread evenly (admin adjustments).
+pub async fn spread_into_buckets(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    shards: i32,
+    qty: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"update inventory_buckets
+           set remaining = remaining + $2 / $3 + case when bucket < $2 % $3 then 1 else 0 end
+           where ticket_type_id = $1"#,
+    )
+    .bind(ticket_type_id)
+    .bind(qty)
+    .bind(shards)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Returns `qty` to one bucket (releases pick a random one).
+pub async fn add_to_bucket(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    bucket: i32,
+    qty: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "update inventory_buckets set remaining = remaining + $3 where ticket_type_id = $1 and bucket = $2",
+    )
+    .bind(ticket_type_id)
+    .bind(bucket)
+    .bind(qty)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Takes `qty` from a single bucket that holds enough, trying `start` first and then the
+/// following buckets. Buckets locked by other transactions are skipped rather than waited
+/// on, so `false` doesn't mean sold out; see [`take_spread`].
+pub async fn take_from_free_bucket(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    shards: i32,
+    start: i32,
+    qty: i32,
+) -> Result<bool, sqlx::Error> {
+    let taken = sqlx::query(
+        r#"update inventory_buckets
+           set remaining = remaining - $2
+           where ticket_type_id = $1
+             and bucket = (
+               select bucket from inventory_buckets
+               where ticket_type_id = $1 and remaining >= $2
+               order by (bucket - $3 + $4) % $4
+               limit 1
+               for update skip locked)"#,
+    )
+    .bind(ticket_type_id)
+    .bind(qty)
+    .bind(start)
+    .bind(shards)
+    .execute(&mut *conn)
+    .await?;
+    Ok(taken.rows_affected() == 1)
+}
+
+/// Slow path: locks every bucket (in bucket order, so callers can't deadlock) and takes `qty`
+/// from the fullest ones. Changes nothing and returns `false` if the buckets hold less than `qty`.
+pub async fn take_spread(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    qty: i32,
+) -> Result<bool, sqlx::Error> {
+    let mut buckets: Vec<(i32, i32)> = sqlx::query_as(
+        r#"select bucket, remaining from inventory_buckets
+           where ticket_type_id = $1 order by bucket for update"#,
+    )
+    .bind(ticket_type_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    if buckets.iter().map(|(_, r)| *r as i64).sum::<i64>() < qty as i64 {
+        return Ok(false);
+    }
+
+    buckets.sort_by_key(|(_, remaining)| std::cmp::Reverse(*remaining));
+    let mut need = qty;
+    for (bucket, remaining) in buckets {
+        if need == 0 {
+            break;
+        }
+        let take = need.min(remaining);
+        if take == 0 {
+            continue;
+        }
+        sqlx::query(
+            "update inventory_buckets set remaining = remaining - $3 where ticket_type_id = $1 and bucket = $2",
+        )
+        .bind(ticket_type_id)
+        .bind(bucket)
+        .bind(take)
+        .execute(&mut *conn)
+        .await?;
+        need -= take;
+    }
+    Ok(true)
+}
+
+/// A ticket type whose ledger doesn't add up to its stock columns.
+#[derive(Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct Discrepancy {
+    pub ticket_type_id: Uuid,
+    pub inventory_total: i32,
+    pub inventory_remaining: i32,
+    pub ledger_total: i64,
+    pub ledger_remaining: i64,
+}
+
+/// Compares every ticket type with the sum of its movements; returns the mismatches.
+pub async fn reconcile(pool: &PgPool) -> Result<Vec<Discrepancy>, sqlx::Error> {
+    sqlx::query_as::<_, Discrepancy>(
+        r#"select * from (
+             select t.id as ticket_type_id, t.inventory_total,
+                    ticket_type_remaining(t.id, t.inventory_shards, t.inventory_remaining) as inventory_remaining,
+                    coalesce(m.total, 0) as ledger_total,
+                    coalesce(m.remaining, 0) as ledger_remaining
+             from ticket_types t
+             left join (
+               select ticket_type_id, sum(total_delta) as total, sum(remaining_delta) as remaining
+               from inventory_movements
+               group by ticket_type_id
+             ) m on m.ticket_type_id = t.id
+           ) r
+           where r.ledger_total <> r.inventory_total
+              or r.ledger_remaining <> r.inventory_remaining
+           order by r.ticket_type_id"#,
+    )
+    .fetch_all(pool)
+    .await
+}
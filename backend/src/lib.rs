@@ -1,8 +1,23 @@
+pub mod abuse;
 pub mod app;
+pub mod audit;
 pub mod auth;
 pub mod config;
 pub mod db;
 pub mod error;
+pub mod holds;
+pub mod intent_wakeup;
+pub mod inventory;
 pub mod openapi;
+pub mod order_state;
+pub mod payments;
+pub mod presale;
+pub mod promo;
+pub mod purchase;
+pub mod rate_limit;
 pub mod routes;
+pub mod seats;
+pub mod state;
+pub mod stock_gate;
+pub mod waiting_room;
 pub mod worker;
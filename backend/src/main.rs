@@ -1,5 +1,5 @@
 use anyhow::Context;
-use ticket_seckill_backend::{config, config::Config, db::Db};
+use ticket_seckill_backend::{auth, config, config::Config, db::Db, worker};
 use tracing::info;
 
 #[tokio::main]
@@ -12,6 +12,31 @@ async fn main() -> anyhow::Result<()> {
     // Ensure migrations are applied on startup in dev/test.
     db.migrate().await?;
 
+    // `create-admin <username>`: promotes the user, or creates it with a password read from stdin.
+    // `worker`: runs only the purchase-intent worker (pair with INTENT_WORKER_EMBEDDED=false).
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args.as_slice() {
+        [] => {}
+        [cmd] if cmd == "worker" => {
+            cfg.validate()?;
+            tokio::select! {
+                _ = worker::run_intent_worker(db, cfg.intent_worker) => {}
+                _ = tokio::signal::ctrl_c() => info!("worker shutting down"),
+            }
+            return Ok(());
+        }
+        [cmd, username] if cmd == "create-admin" => {
+            let mut password = String::new();
+            std::io::stdin().read_line(&mut password)?;
+            let password = password.trim_end_matches(['\r', '\n']);
+            let password = (!password.is_empty()).then_some(password);
+            let user_id = auth::bootstrap_admin(&db.pool, username, password).await?;
+            info!(%user_id, %username, "admin ready");
+            return Ok(());
+        }
+        _ => anyhow::bail!("usage: ticket-seckill-backend [worker | create-admin <username>]"),
+    }
+
     let listener = tokio::net::TcpListener::bind(&cfg.server_addr)
         .await
         .with_context(|| format!("bind {}", cfg.server_addr))?;
@@ -1,44 +1,132 @@
 use utoipa::OpenApi;
 
-use crate::routes;
+use crate::{inventory, order_state, routes, seats, waiting_room};
 
 #[derive(OpenApi)]
 #[openapi(
     paths(
         routes::health::healthz,
+        routes::auth::register,
         routes::auth::login,
+        routes::auth::refresh,
+        routes::auth::logout,
         routes::admin::create_event,
         routes::admin::list_events,
         routes::admin::create_ticket_type,
         routes::admin::create_ticket_type_flat,
         routes::admin::list_ticket_types,
+        routes::admin::update_event,
+        routes::admin::archive_event,
+        routes::admin::unarchive_event,
+        routes::admin::delete_event,
+        routes::admin::update_ticket_type,
+        routes::admin::delete_ticket_type,
+        routes::admin::audit_log,
+        routes::inventory::adjust_inventory,
+        routes::inventory::list_movements,
+        routes::inventory::reconcile,
+        routes::promo_codes::create_promo_code,
+        routes::promo_codes::list_promo_codes,
+        routes::promo_codes::update_promo_code,
+        routes::promo_codes::delete_promo_code,
+        routes::presale::upload_allowlist,
+        routes::presale::list_allowlist,
+        routes::presale::remove_from_allowlist,
+        routes::presale::generate_codes,
+        routes::presale::list_codes,
         routes::seckill::grab,
+        routes::seats::seat_map,
+        routes::seats::order_seats,
+        routes::holds::create_hold,
+        routes::holds::my_holds,
+        routes::holds::checkout,
+        routes::holds::release_hold,
         routes::orders::my_orders,
         routes::orders::get_order,
         routes::orders::pay_order,
+        routes::orders::cancel_order,
+        routes::orders::order_history,
         routes::purchase_intents::create_intent,
         routes::purchase_intents::my_intents,
+        routes::purchase_intents::cancel_intent,
+        routes::queue::join_queue,
+        routes::queue::queue_status,
+        routes::queue::queue_stream,
+        routes::refunds::create_refund,
+        routes::refunds::list_refunds,
+        routes::payments::get_payment,
+        routes::payments::mock_complete,
+        routes::payments::webhook,
+        routes::abuse::grab_challenge,
+        routes::abuse::block,
+        routes::abuse::list_blocklist,
+        routes::abuse::unblock,
+        routes::abuse::abuse_events,
     ),
     components(schemas(
         routes::health::HealthzResponse,
+        routes::auth::RegisterRequest,
         routes::auth::LoginRequest,
+        routes::auth::RefreshRequest,
+        routes::auth::LogoutRequest,
         routes::auth::LoginResponse,
         routes::admin::CreateEventRequest,
         routes::admin::EventDto,
         routes::admin::CreateTicketTypeRequest,
         routes::admin::CreateTicketTypeFlatRequest,
         routes::admin::TicketTypeDto,
+        routes::admin::UpdateEventRequest,
+        routes::admin::UpdateTicketTypeRequest,
+        routes::admin::AuditEntryDto,
+        routes::inventory::AdjustInventoryRequest,
+        routes::inventory::MovementDto,
+        routes::inventory::ReconcileReport,
+        inventory::Discrepancy,
+        routes::promo_codes::CreatePromoCodeRequest,
+        routes::promo_codes::UpdatePromoCodeRequest,
+        routes::promo_codes::PromoCodeDto,
+        routes::presale::UploadAllowlistRequest,
+        routes::presale::AllowlistUploadResult,
+        routes::presale::AllowlistEntryDto,
+        routes::presale::GenerateCodesRequest,
+        routes::presale::PresaleCodeDto,
         routes::seckill::GrabRequest,
         routes::seckill::OrderDto,
+        seats::SeatRow,
+        seats::SeatDto,
+        routes::holds::CreateHoldRequest,
+        routes::holds::CheckoutRequest,
+        routes::holds::HoldDto,
         routes::orders::OrderDto,
+        routes::orders::OrderEventDto,
+        order_state::OrderStatus,
         routes::purchase_intents::CreateIntentRequest,
         routes::purchase_intents::IntentDto,
+        waiting_room::QueueStatus,
+        waiting_room::QueueState,
+        routes::refunds::CreateRefundRequest,
+        routes::refunds::RefundDto,
+        routes::refunds::RefundResponse,
+        routes::payments::PaymentDto,
+        routes::payments::WebhookEvent,
+        routes::payments::WebhookAck,
+        routes::abuse::GrabChallenge,
+        routes::abuse::BlockRequest,
+        routes::abuse::BlockDto,
+        routes::abuse::AbuseEventDto,
     )),
     tags(
         (name = "health", description = "Health check"),
-        (name = "admin", description = "Admin endpoints (no auth in MVP)"),
+        (name = "admin", description = "Admin endpoints (bearer token with the admin role)"),
+        (name = "promo_codes", description = "Promo code management (admin)"),
+        (name = "presale", description = "Presale allow-lists and codes (admin)"),
         (name = "seckill", description = "Seckill / purchase"),
-        (name = "orders", description = "Order read & simulated payment")
+        (name = "seats", description = "Seat maps of seated ticket types"),
+        (name = "holds", description = "Short stock holds and their checkout"),
+        (name = "orders", description = "Order read, payment & cancellation"),
+        (name = "queue", description = "Waiting room for queued ticket types"),
+        (name = "payments", description = "Payment sessions & provider callbacks"),
+        (name = "abuse", description = "Grab challenges, blocklist & abuse log")
     )
 )]
 pub struct ApiDoc;
@@ -0,0 +1,177 @@
+//! Order state machine.
+//!
+//! Every status change goes through [`transition`], which checks the move against
+//! [`OrderStatus::can_transition_to`], updates the row and appends to `order_events`.
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{
+    encode::IsNull,
+    error::BoxDynError,
+    postgres::{PgArgumentBuffer, PgTypeInfo, PgValueRef},
+    PgConnection, Postgres,
+};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult},
+    promo, seats,
+};
+
+/// `orders.status`, stored as text (see the `orders_status_check` constraint).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum OrderStatus {
+    Created,
+    Paid,
+    Canceled,
+    Refunded,
+}
+
+impl OrderStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OrderStatus::Created => "CREATED",
+            OrderStatus::Paid => "PAID",
+            OrderStatus::Canceled => "CANCELED",
+            OrderStatus::Refunded => "REFUNDED",
+        }
+    }
+
+    pub fn can_transition_to(self, to: OrderStatus) -> bool {
+        use OrderStatus::*;
+        matches!(
+            (self, to),
+            (Created, Paid) | (Created, Canceled) | (Paid, Refunded)
+        )
+    }
+
+    /// Fails with [`AppError::InvalidTransition`] if `self -> to` is not allowed.
+    pub fn ensure_transition(self, to: OrderStatus) -> AppResult<()> {
+        if self.can_transition_to(to) {
+            Ok(())
+        } else {
+            Err(AppError::InvalidTransition { from: self, to })
+        }
+    }
+}
+
+impl fmt::Display for OrderStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for OrderStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "CREATED" => Ok(OrderStatus::Created),
+            "PAID" => Ok(OrderStatus::Paid),
+            "CANCELED" => Ok(OrderStatus::Canceled),
+            "REFUNDED" => Ok(OrderStatus::Refunded),
+            other => Err(format!("unknown order status: {other}")),
+        }
+    }
+}
+
+impl sqlx::Type<Postgres> for OrderStatus {
+    fn type_info() -> PgTypeInfo {
+        <&str as sqlx::Type<Postgres>>::type_info()
+    }
+
+    fn compatible(ty: &PgTypeInfo) -> bool {
+        <&str as sqlx::Type<Postgres>>::compatible(ty)
+    }
+}
+
+impl sqlx::Encode<'_, Postgres> for OrderStatus {
+    fn encode_by_ref(&self, buf: &mut PgArgumentBuffer) -> IsNull {
+        <&str as sqlx::Encode<Postgres>>::encode(self.as_str(), buf)
+    }
+}
+
+impl<'r> sqlx::Decode<'r, Postgres> for OrderStatus {
+    fn decode(value: PgValueRef<'r>) -> Result<Self, BoxDynError> {
+        let s = <&str as sqlx::Decode<Postgres>>::decode(value)?;
+        Ok(s.parse()?)
+    }
+}
+
+/// Records the initial `CREATED` event for a freshly inserted order.
+pub async fn record_created(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    actor: &str,
+) -> Result<(), sqlx::Error> {
+    insert_event(conn, order_id, None, OrderStatus::Created, actor, None).await
+}
+
+/// Moves an order from `from` to `to` and records the event; paying also marks its seats sold,
+/// canceling gives back its promo code use.
+///
+/// The caller must hold the order's row lock (`select ... for update`) and pass the
+/// status it read under that lock.
+pub async fn transition(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    from: OrderStatus,
+    to: OrderStatus,
+    actor: &str,
+    reason: Option<&str>,
+) -> AppResult<()> {
+    from.ensure_transition(to)?;
+
+    let updated = sqlx::query(
+        r#"update orders
+           set status = $3,
+               paid_at = case when $3 = 'PAID' then now() else paid_at end,
+               canceled_at = case when $3 = 'CANCELED' then now() else canceled_at end,
+               refunded_at = case when $3 = 'REFUNDED' then now() else refunded_at end
+           where id = $1 and status = $2"#,
+    )
+    .bind(order_id)
+    .bind(from)
+    .bind(to)
+    .execute(&mut *conn)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Err(AppError::Conflict(
+            "order status changed concurrently".into(),
+        ));
+    }
+
+    insert_event(conn, order_id, Some(from), to, actor, reason).await?;
+    match to {
+        OrderStatus::Paid => seats::mark_sold(conn, order_id).await?,
+        OrderStatus::Canceled => promo::release(conn, order_id).await?,
+        _ => {}
+    }
+    Ok(())
+}
+
+async fn insert_event(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+    from: Option<OrderStatus>,
+    to: OrderStatus,
+    actor: &str,
+    reason: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"insert into order_events (id, order_id, from_status, to_status, actor, reason)
+           values ($1,$2,$3,$4,$5,$6)"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(order_id)
+    .bind(from)
+    .bind(to)
+    .bind(actor)
+    .bind(reason)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
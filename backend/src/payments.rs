@@ -0,0 +1,316 @@
+//! Payment provider abstraction.
+//!
+//! `pay_order` opens a payment with the configured provider and returns a session;
+//! the order only becomes `PAID` once the provider reports success through a callback
+//! (see [`apply_outcome`]).
+
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
+
+use axum::async_trait;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::PgConnection;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    config::Config,
+    error::AppResult,
+    order_state::{self, OrderStatus},
+};
+
+/// What a provider hands back when a payment is opened.
+#[derive(Debug, Clone)]
+pub struct ProviderSession {
+    pub provider_ref: String,
+    pub checkout_url: Option<String>,
+}
+
+/// Provider-side state of a payment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PaymentOutcome {
+    Pending,
+    Succeeded,
+    Failed(String),
+}
+
+#[async_trait]
+pub trait PaymentProvider: Send + Sync {
+    /// Stable name stored in `payments.provider`.
+    fn name(&self) -> &'static str;
+
+    /// Opens a payment for `amount_cents` against `order_id`.
+    async fn create_payment(
+        &self,
+        payment_id: Uuid,
+        order_id: Uuid,
+        amount_cents: i64,
+    ) -> anyhow::Result<ProviderSession>;
+
+    /// Completes the buyer-facing checkout step and reports the result.
+    async fn confirm(&self, provider_ref: &str) -> anyhow::Result<PaymentOutcome>;
+
+    /// Reads the current state without side effects.
+    async fn query(&self, provider_ref: &str) -> anyhow::Result<PaymentOutcome>;
+
+    /// Voids an open payment so it can no longer be captured. Providers without such a
+    /// call keep the default no-op.
+    async fn cancel(&self, _provider_ref: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Behaviour of [`MockPaymentProvider`] once checkout is confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockMode {
+    Succeed,
+    Fail,
+    /// The provider never answers; the payment stays pending.
+    Timeout,
+}
+
+impl std::str::FromStr for MockMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "succeed" => Ok(MockMode::Succeed),
+            "fail" => Ok(MockMode::Fail),
+            "timeout" => Ok(MockMode::Timeout),
+            other => anyhow::bail!("unknown mock payment mode: {other}"),
+        }
+    }
+}
+
+/// Offline provider: checkout is "completed" via `POST /api/payments/mock/{ref}/complete`.
+pub struct MockPaymentProvider {
+    mode: MockMode,
+    confirmed: Mutex<HashSet<String>>,
+    canceled: Mutex<HashSet<String>>,
+}
+
+impl MockPaymentProvider {
+    pub fn new(mode: MockMode) -> Self {
+        Self {
+            mode,
+            confirmed: Mutex::new(HashSet::new()),
+            canceled: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn outcome(&self) -> PaymentOutcome {
+        match self.mode {
+            MockMode::Succeed => PaymentOutcome::Succeeded,
+            MockMode::Fail => PaymentOutcome::Failed("card declined (mock)".into()),
+            MockMode::Timeout => PaymentOutcome::Pending,
+        }
+    }
+}
+
+#[async_trait]
+impl PaymentProvider for MockPaymentProvider {
+    fn name(&self) -> &'static str {
+        "mock"
+    }
+
+    async fn create_payment(
+        &self,
+        _payment_id: Uuid,
+        _order_id: Uuid,
+        _amount_cents: i64,
+    ) -> anyhow::Result<ProviderSession> {
+        let provider_ref = format!("mock_{}", Uuid::new_v4().simple());
+        Ok(ProviderSession {
+            checkout_url: Some(format!("/api/payments/mock/{provider_ref}/complete")),
+            provider_ref,
+        })
+    }
+
+    async fn confirm(&self, provider_ref: &str) -> anyhow::Result<PaymentOutcome> {
+        if self.canceled.lock().unwrap().contains(provider_ref) {
+            return Ok(PaymentOutcome::Failed("payment canceled (mock)".into()));
+        }
+        self.confirmed
+            .lock()
+            .unwrap()
+            .insert(provider_ref.to_string());
+        Ok(self.outcome())
+    }
+
+    async fn query(&self, provider_ref: &str) -> anyhow::Result<PaymentOutcome> {
+        if self.confirmed.lock().unwrap().contains(provider_ref) {
+            Ok(self.outcome())
+        } else {
+            Ok(PaymentOutcome::Pending)
+        }
+    }
+
+    async fn cancel(&self, provider_ref: &str) -> anyhow::Result<()> {
+        self.canceled
+            .lock()
+            .unwrap()
+            .insert(provider_ref.to_string());
+        Ok(())
+    }
+}
+
+pub fn provider_from_config(cfg: &Config) -> anyhow::Result<Arc<dyn PaymentProvider>> {
+    match cfg.payment_provider.as_str() {
+        "mock" => Ok(Arc::new(MockPaymentProvider::new(
+            cfg.mock_payment_mode.parse()?,
+        ))),
+        other => anyhow::bail!("unknown payment provider: {other}"),
+    }
+}
+
+/// Applies a provider outcome to a `PENDING` payment and its order.
+///
+/// Locks the order, then the payment (same order as `pay_order`). Only a `CREATED`
+/// order still inside its payment window is moved to `PAID`; anything else is left alone,
+/// so late or repeated callbacks can't resurrect a canceled or expired order. A success for
+/// such an order marks the payment `SUCCEEDED_ORPHANED`, even if [`close_pending`] already
+/// closed it: the money was taken and has to be refunded. Returns `false` if nothing changed.
+pub async fn apply_outcome(
+    conn: &mut PgConnection,
+    payment_id: Uuid,
+    outcome: &PaymentOutcome,
+) -> AppResult<bool> {
+    let failure_reason = match outcome {
+        PaymentOutcome::Pending => return Ok(false),
+        PaymentOutcome::Succeeded => None,
+        PaymentOutcome::Failed(reason) => Some(reason.as_str()),
+    };
+
+    let payment: Option<(Uuid, String)> =
+        sqlx::query_as("select order_id, provider from payments where id = $1")
+            .bind(payment_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+    let Some((order_id, provider)) = payment else {
+        return Ok(false);
+    };
+
+    // Same deadline check as `pay_order`, so an expired order the reaper hasn't got to
+    // yet isn't paid.
+    let (order_status, expired): (OrderStatus, bool) = sqlx::query_as(
+        r#"select status, coalesce(expires_at <= now(), false)
+           from orders where id = $1 for update"#,
+    )
+    .bind(order_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    let payable = order_status.can_transition_to(OrderStatus::Paid) && !expired;
+
+    let status = match (failure_reason, payable) {
+        (Some(_), _) => "FAILED",
+        (None, true) => "SUCCEEDED",
+        (None, false) => "SUCCEEDED_ORPHANED",
+    };
+
+    // A payment closed by `close_pending` can still be captured if the provider couldn't
+    // void it in time; that money must not go unrecorded either.
+    let updated = sqlx::query(
+        r#"update payments set status = $2, failure_reason = $3, updated_at = now()
+           where id = $1
+             and (status = 'PENDING'
+                  or (status = 'FAILED' and failure_reason = $4 and $2 = 'SUCCEEDED_ORPHANED'))"#,
+    )
+    .bind(payment_id)
+    .bind(status)
+    .bind(failure_reason)
+    .bind(ORDER_CANCELED)
+    .execute(&mut *conn)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(false);
+    }
+
+    match status {
+        "SUCCEEDED" => {
+            order_state::transition(
+                conn,
+                order_id,
+                order_status,
+                OrderStatus::Paid,
+                &format!("provider:{provider}"),
+                None,
+            )
+            .await?;
+        }
+        "SUCCEEDED_ORPHANED" => {
+            warn!(%payment_id, %order_id, %order_status, expired, "payment succeeded for an order that is no longer payable; needs a refund");
+        }
+        _ => {}
+    }
+    Ok(true)
+}
+
+/// `failure_reason` of payments closed because their order was canceled.
+pub const ORDER_CANCELED: &str = "order canceled";
+
+/// An open payment closed by [`close_pending`], still to be voided at the provider.
+#[derive(Debug, sqlx::FromRow)]
+pub struct ClosedPayment {
+    pub provider: String,
+    /// `null` if the session wasn't open yet; `pay_order` voids it when it finds the
+    /// attempt closed.
+    pub provider_ref: Option<String>,
+}
+
+/// Marks the order's `PENDING` payment `FAILED` when the order is canceled, in the caller's
+/// transaction, so nothing is left open for a late callback to capture against. Call
+/// [`void_closed`] once the transaction has committed.
+pub async fn close_pending(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+) -> Result<Option<ClosedPayment>, sqlx::Error> {
+    sqlx::query_as(
+        r#"update payments set status = 'FAILED', failure_reason = $2, updated_at = now()
+           where order_id = $1 and status = 'PENDING'
+           returning provider, provider_ref"#,
+    )
+    .bind(order_id)
+    .bind(ORDER_CANCELED)
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+/// Voids payments closed by [`close_pending`] at the provider. Best effort: a capture that
+/// slips through still lands as `SUCCEEDED_ORPHANED` (see [`apply_outcome`]).
+pub async fn void_closed(provider: &dyn PaymentProvider, closed: &[ClosedPayment]) {
+    for payment in closed.iter().filter(|p| p.provider == provider.name()) {
+        let Some(provider_ref) = &payment.provider_ref else {
+            continue;
+        };
+        if let Err(e) = provider.cancel(provider_ref).await {
+            warn!(err = ?e, %provider_ref, "voiding a closed payment failed");
+        }
+    }
+}
+
+/// Hex HMAC-SHA256 over `"{timestamp}.{body}"`, as sent in `x-webhook-signature`.
+pub fn sign_webhook(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    hex::encode(webhook_mac(secret, timestamp, body).finalize().into_bytes())
+}
+
+/// Constant-time check of a webhook signature.
+pub fn verify_webhook(secret: &str, timestamp: i64, body: &[u8], signature_hex: &str) -> bool {
+    let Ok(signature) = hex::decode(signature_hex) else {
+        return false;
+    };
+    webhook_mac(secret, timestamp, body)
+        .verify_slice(&signature)
+        .is_ok()
+}
+
+fn webhook_mac(secret: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    mac
+}
@@ -0,0 +1,81 @@
+//! Presales: an early window from `presale_starts_at` to `sale_starts_at` open only to users
+//! on the ticket type's allow-list and to holders of one of its presale codes.
+//!
+//! [`admit`] decides, inside the grab's transaction, whether the buyer gets
+//! `purchase::Buyer::Presale`; the decrement itself then checks the window atomically. A code
+//! use is counted by a conditional UPDATE in the same transaction, so it is only spent if the
+//! grab succeeds and can't be spent more than `max_uses` times.
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+
+/// Length of generated presale codes.
+pub const CODE_LEN: usize = 10;
+
+/// Letters and digits that can't be confused with each other when read out.
+const CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+/// A fresh random presale code.
+pub fn generate_code() -> String {
+    let mut rng = rand::thread_rng();
+    (0..CODE_LEN)
+        .map(|_| CODE_ALPHABET[rng.gen_range(0..CODE_ALPHABET.len())] as char)
+        .collect()
+}
+
+/// Whether the buyer may grab early. Outside the presale window this is always `false`
+/// (the ordinary sale rules apply); inside it the buyer must be allow-listed or bring a
+/// valid `code`, otherwise it fails with [`AppError::PresaleRequired`].
+pub async fn admit(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    ticket_type_id: Uuid,
+    code: Option<&str>,
+    now: DateTime<Utc>,
+) -> AppResult<bool> {
+    let in_presale: Option<bool> = sqlx::query_scalar(
+        r#"select coalesce(presale_starts_at <= $2 and sale_starts_at > $2, false)
+           from ticket_types where id = $1"#,
+    )
+    .bind(ticket_type_id)
+    .bind(now)
+    .fetch_optional(&mut *conn)
+    .await?;
+    match in_presale {
+        None => return Err(AppError::NotFound),
+        Some(false) => return Ok(false),
+        Some(true) => {}
+    }
+
+    let listed: bool = sqlx::query_scalar(
+        r#"select exists(select 1 from presale_allowlist
+                         where ticket_type_id = $1 and user_id = $2)"#,
+    )
+    .bind(ticket_type_id)
+    .bind(user_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    if listed {
+        return Ok(true);
+    }
+
+    let Some(code) = code else {
+        return Err(AppError::PresaleRequired);
+    };
+    let redeemed = sqlx::query(
+        r#"update presale_codes set uses = uses + 1
+           where code = upper($1) and ticket_type_id = $2 and uses < max_uses"#,
+    )
+    .bind(code.trim())
+    .bind(ticket_type_id)
+    .execute(&mut *conn)
+    .await?;
+    if redeemed.rows_affected() == 0 {
+        return Err(AppError::PresaleRequired);
+    }
+    Ok(true)
+}
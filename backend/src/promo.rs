@@ -0,0 +1,122 @@
+//! Promo codes applied at grab.
+//!
+//! [`redeem`] runs in the grab's transaction after the stock is taken. The use is counted by a
+//! conditional `UPDATE promo_codes ... WHERE uses < max_uses`, which both row-locks the code
+//! and refuses the redemption past the cap, so concurrent grabs can't over-redeem it; if the
+//! order insert then fails, the use rolls back with it. `once_per_user` is checked under the
+//! user's row lock taken by `purchase::check_purchase_limits`. Canceling an unpaid order gives
+//! the use back ([`release`]); refunds don't.
+
+use chrono::{DateTime, Utc};
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+
+#[derive(sqlx::FromRow)]
+struct Redeemable {
+    id: Uuid,
+    kind: String,
+    percent_off: Option<i32>,
+    amount_off_cents: Option<i64>,
+    once_per_user: bool,
+    applies: bool,
+    in_window: bool,
+}
+
+/// Counts one use of `code` for `order_id` and returns the discount on `total_cents`.
+pub async fn redeem(
+    conn: &mut PgConnection,
+    code: &str,
+    user_id: Uuid,
+    ticket_type_id: Uuid,
+    order_id: Uuid,
+    total_cents: i64,
+    now: DateTime<Utc>,
+) -> AppResult<i64> {
+    let promo: Option<Redeemable> = sqlx::query_as(
+        r#"select p.id, p.kind, p.percent_off, p.amount_off_cents, p.once_per_user,
+                  (cardinality(p.event_ids) = 0 or t.event_id = any(p.event_ids))
+                    and (cardinality(p.ticket_type_ids) = 0 or t.id = any(p.ticket_type_ids))
+                    as applies,
+                  (p.valid_from is null or p.valid_from <= $3)
+                    and (p.valid_until is null or p.valid_until > $3) as in_window
+           from promo_codes p, ticket_types t
+           where upper(p.code) = upper($1) and p.active and t.id = $2"#,
+    )
+    .bind(code.trim())
+    .bind(ticket_type_id)
+    .bind(now)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(promo) = promo else {
+        return Err(AppError::BadRequest("unknown promo code".into()));
+    };
+    if !promo.in_window {
+        return Err(AppError::BadRequest("promo code is not valid now".into()));
+    }
+    if !promo.applies {
+        return Err(AppError::BadRequest(
+            "promo code does not apply to this ticket type".into(),
+        ));
+    }
+
+    if promo.once_per_user {
+        let used: bool = sqlx::query_scalar(
+            r#"select exists(select 1 from promo_redemptions
+                             where promo_code_id = $1 and user_id = $2 and canceled_at is null)"#,
+        )
+        .bind(promo.id)
+        .bind(user_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        if used {
+            return Err(AppError::Conflict("promo code already used".into()));
+        }
+    }
+
+    let counted = sqlx::query(
+        r#"update promo_codes set uses = uses + 1
+           where id = $1 and (max_uses is null or uses < max_uses)"#,
+    )
+    .bind(promo.id)
+    .execute(&mut *conn)
+    .await?;
+    if counted.rows_affected() == 0 {
+        return Err(AppError::Conflict("promo code used up".into()));
+    }
+
+    let discount = match promo.kind.as_str() {
+        "percent" => total_cents * promo.percent_off.unwrap_or(0) as i64 / 100,
+        _ => promo.amount_off_cents.unwrap_or(0).min(total_cents),
+    };
+    sqlx::query(
+        r#"insert into promo_redemptions (id, promo_code_id, user_id, order_id, discount_cents)
+           values ($1,$2,$3,$4,$5)"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(promo.id)
+    .bind(user_id)
+    .bind(order_id)
+    .bind(discount)
+    .execute(&mut *conn)
+    .await?;
+    Ok(discount)
+}
+
+/// The order was canceled: its code can be used again.
+pub async fn release(conn: &mut PgConnection, order_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"with released as (
+             update promo_redemptions set canceled_at = now()
+             where order_id = $1 and canceled_at is null
+             returning promo_code_id
+           )
+           update promo_codes p set uses = uses - 1
+           from released r where p.id = r.promo_code_id"#,
+    )
+    .bind(order_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,402 @@
+//! Order placement steps shared by `routes::seckill::grab` and the intent worker.
+//!
+//! Everything here runs inside the caller's transaction so both paths apply
+//! exactly the same rules.
+
+use chrono::{DateTime, Utc};
+use rand::Rng;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult, LimitReason},
+    intent_wakeup,
+    inventory::{self, Movement, MovementKind},
+    seats,
+};
+
+/// Validates `qty` against the ticket type's `max_qty_per_order`.
+pub async fn check_order_qty(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    qty: i32,
+) -> AppResult<()> {
+    if qty < 1 {
+        return Err(AppError::BadRequest("qty must be >= 1".into()));
+    }
+
+    let row: Option<(Option<i32>,)> =
+        sqlx::query_as("select max_qty_per_order from ticket_types where id = $1")
+            .bind(ticket_type_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+
+    let Some((max_qty,)) = row else {
+        return Err(AppError::NotFound);
+    };
+
+    if let Some(max) = max_qty {
+        if qty > max {
+            return Err(AppError::BadRequest(format!(
+                "qty exceeds max_qty_per_order ({max})"
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Enforces `max_per_user` on the ticket type and on its event; active holds count like
+/// unpaid orders. A ticket type without a cap allows one active order per user.
+///
+/// Locks the user's row first, so concurrent purchases by the same user (grab or
+/// intent worker) are serialized until the caller's transaction ends.
+pub async fn check_purchase_limits(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    ticket_type_id: Uuid,
+    qty: i32,
+) -> AppResult<()> {
+    sqlx::query("select id from users where id = $1 for update")
+        .bind(user_id)
+        .execute(&mut *conn)
+        .await?;
+
+    let limits: Option<(Uuid, Option<i32>, Option<i32>)> = sqlx::query_as(
+        r#"select t.event_id, t.max_per_user, e.max_per_user
+           from ticket_types t join events e on e.id = t.event_id
+           where t.id = $1"#,
+    )
+    .bind(ticket_type_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((event_id, ticket_type_max, event_max)) = limits else {
+        return Err(AppError::NotFound);
+    };
+
+    let (held_orders, held_tickets): (i64, i64) = sqlx::query_as(
+        r#"select (select count(*) from orders
+                   where user_id = $1 and ticket_type_id = $2 and status in ('CREATED','PAID'))
+                + (select count(*) from holds
+                   where user_id = $1 and ticket_type_id = $2 and status = 'ACTIVE'),
+                  (select coalesce(sum(qty), 0) from orders
+                   where user_id = $1 and ticket_type_id = $2 and status in ('CREATED','PAID'))
+                + (select coalesce(sum(qty), 0) from holds
+                   where user_id = $1 and ticket_type_id = $2 and status = 'ACTIVE')"#,
+    )
+    .bind(user_id)
+    .bind(ticket_type_id)
+    .fetch_one(&mut *conn)
+    .await?;
+    let over_limit = match ticket_type_max {
+        Some(max) => held_tickets + qty as i64 > max as i64,
+        // No cap set: one active order (or hold) per ticket type, as before caps existed.
+        None => held_orders > 0,
+    };
+    if over_limit {
+        return Err(AppError::PurchaseLimit(LimitReason::TicketTypeLimit));
+    }
+
+    if let Some(max) = event_max {
+        let held: i64 = sqlx::query_scalar(
+            r#"select (select coalesce(sum(o.qty), 0) from orders o
+                       join ticket_types t on t.id = o.ticket_type_id
+                       where o.user_id = $1 and t.event_id = $2 and o.status in ('CREATED','PAID'))
+                    + (select coalesce(sum(h.qty), 0) from holds h
+                       join ticket_types t on t.id = h.ticket_type_id
+                       where h.user_id = $1 and t.event_id = $2 and h.status = 'ACTIVE')"#,
+        )
+        .bind(user_id)
+        .bind(event_id)
+        .fetch_one(&mut *conn)
+        .await?;
+        if held + qty as i64 > max as i64 {
+            return Err(AppError::PurchaseLimit(LimitReason::EventLimit));
+        }
+    }
+
+    Ok(())
+}
+
+/// Pricing and payment terms of a successful [`reserve_stock`].
+#[derive(Debug, Clone, Copy, sqlx::FromRow)]
+pub struct Reserved {
+    pub price_cents: i64,
+    pub payment_window_secs: Option<i32>,
+    /// Units taken out of `intent_reserved_remaining`; stored on the order so
+    /// [`release_stock`] can put them back there.
+    #[sqlx(default)]
+    pub intent_reserved_qty: i32,
+}
+
+impl Reserved {
+    /// `expires_at` for an order created at `now`.
+    pub fn payment_deadline(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.payment_window_secs
+            .map(|secs| now + chrono::Duration::seconds(secs as i64))
+    }
+}
+
+/// What stock is taken for: an order, or a hold that becomes one at checkout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Holder {
+    Order(Uuid),
+    Hold(Uuid),
+}
+
+impl Holder {
+    pub fn order_id(self) -> Option<Uuid> {
+        match self {
+            Holder::Order(id) => Some(id),
+            Holder::Hold(_) => None,
+        }
+    }
+
+    pub fn hold_id(self) -> Option<Uuid> {
+        match self {
+            Holder::Order(_) => None,
+            Holder::Hold(id) => Some(id),
+        }
+    }
+}
+
+/// Who is buying, for the ticket type's `intent_policy` and presale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buyer {
+    /// `grab`: can't touch `intent_reserved_remaining` and waits out `intent_head_start_secs`.
+    Grab,
+    /// A grab let into the presale (see `presale::admit`): as `Grab`, but can buy from
+    /// `presale_starts_at`.
+    Presale,
+    /// The intent worker: draws down `intent_reserved_remaining` first.
+    Intent,
+}
+
+/// Atomically takes `qty` units of stock (single UPDATE guarded by remaining>=qty + sale window)
+/// and records the movement against `holder`. Sharded ticket types take from their buckets
+/// instead (see [`reserve_sharded`]).
+///
+/// For [`Buyer::Presale`] the window opens at `presale_starts_at` (when the ticket type has
+/// one) instead of `sale_starts_at`.
+///
+/// The intent policy is part of the same guard, so grabs and the worker can't race past it.
+///
+/// Returns `None` when out of stock / not in the sale window / the event is archived.
+pub async fn reserve_stock(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    qty: i32,
+    holder: Holder,
+    now: DateTime<Utc>,
+    buyer: Buyer,
+) -> Result<Option<Reserved>, sqlx::Error> {
+    let intent = buyer == Buyer::Intent;
+    let presale = buyer == Buyer::Presale;
+    // How much of the intent reserve this takes; the row lock keeps it exact until the update.
+    let reserve_before: Option<i32> = if intent {
+        sqlx::query_scalar(
+            r#"select intent_reserved_remaining from ticket_types
+               where id = $1 and inventory_shards is null
+               for update"#,
+        )
+        .bind(ticket_type_id)
+        .fetch_optional(&mut *conn)
+        .await?
+    } else {
+        None
+    };
+    let reserved = sqlx::query_as::<_, Reserved>(
+        r#"update ticket_types
+           set inventory_remaining = inventory_remaining - $3,
+               intent_reserved_remaining = case when $4 then greatest(intent_reserved_remaining - $3, 0)
+                                                else intent_reserved_remaining end
+           where id = $1
+             and inventory_shards is null
+             and inventory_remaining - case when $4 then 0 else intent_reserved_remaining end >= $3
+             and coalesce(
+                   case when $5 then presale_starts_at end,
+                   sale_starts_at
+                     + case when $4 then interval '0'
+                            else make_interval(secs => coalesce(intent_head_start_secs, 0)) end
+                 ) <= $2
+             and sale_ends_at > $2
+             and not exists (
+               select 1 from events e
+               where e.id = ticket_types.event_id and e.archived_at is not null)
+           returning price_cents, payment_window_secs"#,
+    )
+    .bind(ticket_type_id)
+    .bind(now)
+    .bind(qty)
+    .bind(intent)
+    .bind(presale)
+    .fetch_optional(&mut *conn)
+    .await?
+    .map(|reserved| Reserved {
+        intent_reserved_qty: reserve_before.unwrap_or(0).min(qty),
+        ..reserved
+    });
+
+    let reserved = match reserved {
+        Some(reserved) => Some(reserved),
+        None => reserve_sharded(conn, ticket_type_id, qty, now, buyer).await?,
+    };
+
+    if reserved.is_some() {
+        inventory::record(
+            conn,
+            Movement {
+                ticket_type_id,
+                kind: MovementKind::Reserve,
+                total_delta: 0,
+                remaining_delta: -qty,
+                order_id: holder.order_id(),
+                hold_id: holder.hold_id(),
+                actor: None,
+                reason: None,
+            },
+        )
+        .await?;
+    }
+    Ok(reserved)
+}
+
+/// Sharded path of [`reserve_stock`]: a random bucket first, then the others.
+///
+/// The ticket type row is only held `for key share`, which concurrent grabs don't block on
+/// but admin edits (`for update`) do. Returns `None` for unsharded ticket types. Sharded
+/// ticket types can't reserve a share for intents, so only the head start applies.
+async fn reserve_sharded(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    qty: i32,
+    now: DateTime<Utc>,
+    buyer: Buyer,
+) -> Result<Option<Reserved>, sqlx::Error> {
+    let row: Option<(i32, i64, Option<i32>, bool)> = sqlx::query_as(
+        r#"select inventory_shards, price_cents, payment_window_secs,
+                  coalesce(
+                    case when $4 then presale_starts_at end,
+                    sale_starts_at
+                      + case when $3 then interval '0'
+                             else make_interval(secs => coalesce(intent_head_start_secs, 0)) end
+                  ) <= $2
+                    and sale_ends_at > $2
+                    and not exists (
+                      select 1 from events e
+                      where e.id = ticket_types.event_id and e.archived_at is not null)
+           from ticket_types
+           where id = $1 and inventory_shards is not null
+           for key share"#,
+    )
+    .bind(ticket_type_id)
+    .bind(now)
+    .bind(buyer == Buyer::Intent)
+    .bind(buyer == Buyer::Presale)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    let Some((shards, price_cents, payment_window_secs, on_sale)) = row else {
+        return Ok(None);
+    };
+    if !on_sale {
+        return Ok(None);
+    }
+
+    let start = rand::thread_rng().gen_range(0..shards);
+    let taken = inventory::take_from_free_bucket(conn, ticket_type_id, shards, start, qty).await?
+        || inventory::take_spread(conn, ticket_type_id, qty).await?;
+
+    Ok(taken.then_some(Reserved {
+        price_cents,
+        payment_window_secs,
+        intent_reserved_qty: 0,
+    }))
+}
+
+/// Returns `qty` units of stock taken by `holder` (canceled, expired or restocked order,
+/// released or expired hold), frees its seats and wakes the intents waiting for it.
+///
+/// Units an order took from the intent reserve go back to it first, up to what the ticket
+/// type's policy reserves now. The caller must hold the order row lock. Returns how many
+/// units grabs can take again (for the stock gate).
+pub async fn release_stock(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    qty: i32,
+    holder: Holder,
+) -> Result<i32, sqlx::Error> {
+    let mut to_reserve = 0;
+    if let Some(order_id) = holder.order_id() {
+        let taken: i32 = sqlx::query_scalar("select intent_reserved_qty from orders where id = $1")
+            .bind(order_id)
+            .fetch_one(&mut *conn)
+            .await?;
+        let from_reserve = taken.min(qty);
+        if from_reserve > 0 {
+            sqlx::query(
+                "update orders set intent_reserved_qty = intent_reserved_qty - $2 where id = $1",
+            )
+            .bind(order_id)
+            .bind(from_reserve)
+            .execute(&mut *conn)
+            .await?;
+            // Locked until the update below, so the room left in the reserve can't change.
+            let room: Option<i32> = sqlx::query_scalar(
+                r#"select greatest(case when intent_policy = 'reserved_share'
+                                        then inventory_total * intent_reserved_pct / 100
+                                        else 0 end
+                                   - intent_reserved_remaining, 0)
+                   from ticket_types
+                   where id = $1 and inventory_shards is null
+                   for update"#,
+            )
+            .bind(ticket_type_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+            to_reserve = from_reserve.min(room.unwrap_or(0));
+        }
+    }
+
+    let updated = sqlx::query(
+        r#"update ticket_types
+           set inventory_remaining = inventory_remaining + $2,
+               intent_reserved_remaining = intent_reserved_remaining + $3
+           where id = $1 and inventory_shards is null"#,
+    )
+    .bind(ticket_type_id)
+    .bind(qty)
+    .bind(to_reserve)
+    .execute(&mut *conn)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        let shards: Option<i32> =
+            sqlx::query_scalar("select inventory_shards from ticket_types where id = $1")
+                .bind(ticket_type_id)
+                .fetch_one(&mut *conn)
+                .await?;
+        if let Some(shards) = shards {
+            let bucket = rand::thread_rng().gen_range(0..shards);
+            inventory::add_to_bucket(conn, ticket_type_id, bucket, qty).await?;
+        }
+    }
+
+    inventory::record(
+        conn,
+        Movement {
+            ticket_type_id,
+            kind: MovementKind::Release,
+            total_delta: 0,
+            remaining_delta: qty,
+            order_id: holder.order_id(),
+            hold_id: holder.hold_id(),
+            actor: None,
+            reason: None,
+        },
+    )
+    .await?;
+    seats::release(conn, holder).await?;
+    intent_wakeup::wake(conn, ticket_type_id).await?;
+    Ok(qty - to_reserve)
+}
@@ -0,0 +1,200 @@
+//! Per-route-group token buckets, keyed on the caller.
+//!
+//! Requests with a valid access token are counted per user, everything else per peer IP, so
+//! users behind one NAT don't share a budget. Every limited response carries
+//! `x-ratelimit-limit` / `x-ratelimit-remaining` / `x-ratelimit-reset`; rejections are 429
+//! with `retry-after`. Buckets live in this process only.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use uuid::Uuid;
+
+use crate::{
+    auth,
+    config::{RateLimitPolicy, RateLimits},
+    error::AppError,
+};
+
+const LIMIT_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const REMAINING_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const RESET_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    Grab,
+    Auth,
+    Read,
+    /// Writes other than grab and auth.
+    Default,
+}
+
+impl RouteGroup {
+    /// `None` for routes that are never limited: health checks and provider webhooks
+    /// (those are authenticated by signature and must not be dropped).
+    pub fn classify(method: &Method, path: &str) -> Option<Self> {
+        match path {
+            "/" | "/healthz" | "/api/payments/webhook" => None,
+            "/api/tickets/grab" | "/seckill" | "/api/holds" => Some(RouteGroup::Grab),
+            p if p.starts_with("/api/auth/") || p.starts_with("/auth/") => Some(RouteGroup::Auth),
+            _ if method == Method::GET || method == Method::HEAD => Some(RouteGroup::Read),
+            _ => Some(RouteGroup::Default),
+        }
+    }
+
+    fn policy(self, limits: &RateLimits) -> RateLimitPolicy {
+        match self {
+            RouteGroup::Grab => limits.grab,
+            RouteGroup::Auth => limits.auth,
+            RouteGroup::Read => limits.read,
+            RouteGroup::Default => limits.default,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Caller {
+    User(Uuid),
+    Ip(IpAddr),
+    /// No token and no peer address (only when served without connect info).
+    Unknown,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+struct Decision {
+    allowed: bool,
+    limit: u32,
+    remaining: u32,
+    /// Seconds until the next request would be allowed (when rejected) or the bucket is
+    /// full again (when allowed).
+    reset_secs: u64,
+}
+
+pub struct RateLimiter {
+    limits: RateLimits,
+    jwt_secret: String,
+    buckets: Mutex<HashMap<(RouteGroup, Caller), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(limits: RateLimits, jwt_secret: String) -> Self {
+        Self {
+            limits,
+            jwt_secret,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn check(&self, group: RouteGroup, caller: Caller, now: Instant) -> Decision {
+        let policy = group.policy(&self.limits);
+        let burst = policy.burst as f64;
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        let bucket = buckets.entry((group, caller)).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * policy.per_second).min(burst);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let missing = if allowed {
+            burst - bucket.tokens
+        } else {
+            1.0 - bucket.tokens
+        };
+        Decision {
+            allowed,
+            limit: policy.burst,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: (missing / policy.per_second).ceil() as u64,
+        }
+    }
+
+    /// Drops buckets that have refilled completely; they'd start full anyway.
+    pub fn sweep(&self) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        buckets.retain(|(group, _), bucket| {
+            let policy = group.policy(&self.limits);
+            let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * policy.per_second < policy.burst as f64
+        });
+    }
+
+    fn caller(&self, headers: &HeaderMap, peer: Option<SocketAddr>) -> Caller {
+        let user = headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .and_then(|token| auth::decode_token(&self.jwt_secret, token).ok());
+        match (user, peer) {
+            (Some(user), _) => Caller::User(user.user_id),
+            (None, Some(addr)) => Caller::Ip(addr.ip()),
+            (None, None) => Caller::Unknown,
+        }
+    }
+}
+
+/// Periodically forgets idle callers so the bucket map doesn't grow without bound.
+pub fn spawn_sweeper(limiter: Arc<RateLimiter>, interval: Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            limiter.sweep();
+        }
+    });
+}
+
+fn set_headers(headers: &mut HeaderMap, decision: &Decision) {
+    headers.insert(LIMIT_HEADER, HeaderValue::from(decision.limit));
+    headers.insert(REMAINING_HEADER, HeaderValue::from(decision.remaining));
+    headers.insert(RESET_HEADER, HeaderValue::from(decision.reset_secs));
+}
+
+/// `axum::middleware::from_fn_with_state` handler.
+pub async fn limit(State(limiter): State<Arc<RateLimiter>>, req: Request, next: Next) -> Response {
+    let Some(group) = RouteGroup::classify(req.method(), req.uri().path()) else {
+        return next.run(req).await;
+    };
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| *addr);
+    let caller = match group {
+        // Mostly called without a token anyway; per IP, one client can't spread password
+        // guesses over several accounts' budgets.
+        RouteGroup::Auth => peer.map_or(Caller::Unknown, |addr| Caller::Ip(addr.ip())),
+        _ => limiter.caller(req.headers(), peer),
+    };
+
+    let decision = limiter.check(group, caller, Instant::now());
+    let mut response = if decision.allowed {
+        next.run(req).await
+    } else {
+        let mut response = AppError::TooManyRequests.into_response();
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(decision.reset_secs));
+        response
+    };
+    set_headers(response.headers_mut(), &decision);
+    response
+}
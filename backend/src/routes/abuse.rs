@@ -0,0 +1,267 @@
+use std::net::IpAddr;
+
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+use crate::{
+    abuse,
+    audit::{self, AuditEntry},
+    auth::{AdminUser, AuthUser},
+    error::{AppError, AppResult},
+    state::AppState,
+};
+
+#[derive(Serialize, ToSchema)]
+pub struct GrabChallenge {
+    /// Send back as the `x-grab-challenge` header; valid for one successful grab.
+    pub challenge: String,
+    /// Find a string `s` such that `sha256(challenge || s)` starts with this many zero bits
+    /// and send it as `x-grab-solution`. 0 means challenges are off.
+    pub difficulty: u32,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/grab-challenge",
+    responses((status=200, body=GrabChallenge), (status=401), (status=403, description="Account blocked"))
+)]
+pub async fn grab_challenge(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    auth: AuthUser,
+) -> AppResult<Json<GrabChallenge>> {
+    let difficulty = state.cfg.grab_pow_difficulty;
+    let expires_at = Utc::now() + chrono::Duration::seconds(state.cfg.grab_challenge_ttl_secs);
+    let challenge =
+        abuse::issue_challenge(&state.cfg.jwt_secret, auth.user_id, difficulty, expires_at)?;
+    Ok(Json(GrabChallenge {
+        challenge,
+        difficulty,
+        expires_at,
+    }))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct BlockRequest {
+    /// `user` or `ip`.
+    pub kind: String,
+    /// User id or IP address.
+    pub value: String,
+    pub reason: String,
+    /// Omit to block until removed.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct BlockDto {
+    pub id: Uuid,
+    pub kind: String,
+    pub value: String,
+    pub reason: String,
+    pub actor: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Canonical text form, so `::ffff:1.2.3.4`-style variants and user ids in other cases can't
+/// slip past an existing entry.
+fn normalize_block_value(kind: &str, value: &str) -> AppResult<String> {
+    let value = value.trim();
+    match kind {
+        "user" => value
+            .parse::<Uuid>()
+            .map(|id| id.to_string())
+            .map_err(|_| AppError::BadRequest("value must be a user id".into())),
+        "ip" => value
+            .parse::<IpAddr>()
+            .map(|ip| ip.to_canonical().to_string())
+            .map_err(|_| AppError::BadRequest("value must be an IP address".into())),
+        _ => Err(AppError::BadRequest("kind must be user or ip".into())),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/blocklist",
+    request_body = BlockRequest,
+    responses((status=200, body=BlockDto, description="Created, or the existing entry updated"), (status=400), (status=401), (status=403))
+)]
+pub async fn block(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AdminUser(admin): AdminUser,
+    Json(req): Json<BlockRequest>,
+) -> AppResult<Json<BlockDto>> {
+    let value = normalize_block_value(&req.kind, &req.value)?;
+    let reason = req.reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::BadRequest("reason required".into()));
+    }
+    if req.expires_at.is_some_and(|t| t <= Utc::now()) {
+        return Err(AppError::BadRequest(
+            "expires_at must be in the future".into(),
+        ));
+    }
+
+    let mut tx = state.db.pool.begin().await?;
+    let entry = sqlx::query_as::<_, BlockDto>(
+        r#"insert into blocklist (id, kind, value, reason, actor, expires_at)
+           values ($1,$2,$3,$4,$5,$6)
+           on conflict (kind, value) do update
+             set reason = excluded.reason, actor = excluded.actor,
+                 created_at = now(), expires_at = excluded.expires_at
+           returning id, kind, value, reason, actor, created_at, expires_at"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(&req.kind)
+    .bind(&value)
+    .bind(reason)
+    .bind(&admin.username)
+    .bind(req.expires_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "blocklist.add",
+            entity_type: "blocklist",
+            entity_id: entry.id,
+            forced: false,
+            details: json!({ "kind": entry.kind, "value": entry.value, "reason": entry.reason }),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    state.blocklist.refresh(&state.db.pool).await?;
+    Ok(Json(entry))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/blocklist",
+    responses((status=200, body=[BlockDto], description="Active entries"), (status=401), (status=403))
+)]
+pub async fn list_blocklist(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    _admin: AdminUser,
+) -> AppResult<Json<Vec<BlockDto>>> {
+    let rows = sqlx::query_as::<_, BlockDto>(
+        r#"select id, kind, value, reason, actor, created_at, expires_at
+           from blocklist
+           where expires_at is null or expires_at > now()
+           order by created_at desc"#,
+    )
+    .fetch_all(&state.db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/blocklist/{id}",
+    params(("id" = Uuid, Path, description = "Blocklist entry id")),
+    responses((status=204), (status=401), (status=403), (status=404))
+)]
+pub async fn unblock(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    AdminUser(admin): AdminUser,
+    Path(id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    let mut tx = state.db.pool.begin().await?;
+    let removed: Option<(String, String)> =
+        sqlx::query_as("delete from blocklist where id = $1 returning kind, value")
+            .bind(id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let Some((kind, value)) = removed else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "blocklist.remove",
+            entity_type: "blocklist",
+            entity_id: id,
+            forced: false,
+            details: json!({ "kind": kind, "value": value }),
+        },
+    )
+    .await?;
+    tx.commit().await?;
+
+    state.blocklist.refresh(&state.db.pool).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AbuseEventQuery {
+    /// e.g. `challenge_invalid`, `blocked_ip`, `too_many_accounts`.
+    pub reason: Option<String>,
+    pub user_id: Option<Uuid>,
+    pub ip: Option<String>,
+    /// Default 100, max 1000.
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct AbuseEventDto {
+    pub id: Uuid,
+    pub reason: String,
+    pub user_id: Option<Uuid>,
+    pub ip: Option<String>,
+    pub path: Option<String>,
+    #[schema(value_type = Object)]
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/abuse-events",
+    params(AbuseEventQuery),
+    responses((status=200, body=[AbuseEventDto], description="Newest first"), (status=401), (status=403))
+)]
+pub async fn abuse_events(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    _admin: AdminUser,
+    Query(q): Query<AbuseEventQuery>,
+) -> AppResult<Json<Vec<AbuseEventDto>>> {
+    let rows = sqlx::query_as::<_, AbuseEventDto>(
+        r#"select id, reason, user_id, ip, path, details, created_at
+           from abuse_events
+           where ($1::text is null or reason = $1)
+             and ($2::uuid is null or user_id = $2)
+             and ($3::text is null or ip = $3)
+           order by created_at desc
+           limit $4"#,
+    )
+    .bind(q.reason)
+    .bind(q.user_id)
+    .bind(q.ip)
+    .bind(q.limit.unwrap_or(100).clamp(1, 1000))
+    .fetch_all(&state.db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/grab-challenge", get(grab_challenge))
+        .route("/api/admin/blocklist", get(list_blocklist).post(block))
+        .route("/api/admin/blocklist/:id", delete(unblock))
+        .route("/api/admin/abuse-events", get(abuse_events))
+}
@@ -1,23 +1,38 @@
+use std::sync::Arc;
+
 use axum::{
-    extract::Path,
-    routing::{get, post},
+    extract::{Path, Query},
+    http::StatusCode,
+    routing::{get, patch, post},
     Json, Router,
 };
 use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::json;
+use sqlx::PgConnection;
 use uuid::Uuid;
 
 use crate::{
+    audit::{self, AuditEntry},
+    auth::{AdminUser, AuthUser},
     db::Db,
     error::{AppError, AppResult},
+    intent_wakeup,
+    inventory::{self, Movement, MovementKind},
+    seats::{self, SeatRow},
+    state::AppState,
+    stock_gate::StockGate,
 };
-use utoipa::ToSchema;
+use utoipa::{IntoParams, ToSchema};
 
 #[derive(Deserialize, ToSchema)]
 pub struct CreateEventRequest {
     pub name: String,
     pub starts_at: DateTime<Utc>,
     pub ends_at: DateTime<Utc>,
+    /// Max tickets per user across all ticket types of the event; omit for no cap.
+    #[serde(default)]
+    pub max_per_user: Option<i32>,
 }
 
 #[derive(Serialize, ToSchema, sqlx::FromRow)]
@@ -26,32 +41,50 @@ pub struct EventDto {
     pub name: String,
     pub starts_at: DateTime<Utc>,
     pub ends_at: DateTime<Utc>,
+    pub max_per_user: Option<i32>,
+    pub archived_at: Option<DateTime<Utc>>,
+}
+
+fn validate_event(
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    max_per_user: Option<i32>,
+) -> AppResult<()> {
+    if ends_at <= starts_at {
+        return Err(AppError::BadRequest(
+            "ends_at must be after starts_at".into(),
+        ));
+    }
+    if matches!(max_per_user, Some(max) if max <= 0) {
+        return Err(AppError::BadRequest("max_per_user must be > 0".into()));
+    }
+    Ok(())
 }
 
 #[utoipa::path(
     post,
     path = "/api/admin/events",
     request_body = CreateEventRequest,
-    responses((status=200, body=EventDto))
+    responses((status=200, body=EventDto), (status=401), (status=403))
 )]
 pub async fn create_event(
     axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
     Json(req): Json<CreateEventRequest>,
 ) -> AppResult<Json<EventDto>> {
-    if req.ends_at <= req.starts_at {
-        return Err(AppError::BadRequest("ends_at must be after starts_at".into()));
-    }
+    validate_event(req.starts_at, req.ends_at, req.max_per_user)?;
 
     let id = Uuid::new_v4();
     let rec = sqlx::query_as::<_, EventDto>(
-        r#"insert into events (id, name, starts_at, ends_at)
-           values ($1, $2, $3, $4)
-           returning id, name, starts_at, ends_at"#,
+        r#"insert into events (id, name, starts_at, ends_at, max_per_user)
+           values ($1, $2, $3, $4, $5)
+           returning id, name, starts_at, ends_at, max_per_user, archived_at"#,
     )
     .bind(id)
     .bind(req.name)
     .bind(req.starts_at)
     .bind(req.ends_at)
+    .bind(req.max_per_user)
     .fetch_one(&db.pool)
     .await?;
 
@@ -67,7 +100,8 @@ pub async fn list_events(
     axum::extract::State(db): axum::extract::State<Db>,
 ) -> AppResult<Json<Vec<EventDto>>> {
     let rows = sqlx::query_as::<_, EventDto>(
-        r#"select id, name, starts_at, ends_at from events order by starts_at desc"#,
+        r#"select id, name, starts_at, ends_at, max_per_user, archived_at
+           from events where archived_at is null order by starts_at desc"#,
     )
     .fetch_all(&db.pool)
     .await?;
@@ -81,8 +115,57 @@ pub struct CreateTicketTypeRequest {
     pub inventory_total: i32,
     pub sale_starts_at: DateTime<Utc>,
     pub sale_ends_at: DateTime<Utc>,
+    /// Presale opens here, before `sale_starts_at`, for allow-listed users and presale code
+    /// holders only; omit for no presale.
+    #[serde(default)]
+    pub presale_starts_at: Option<DateTime<Utc>>,
+    /// Max tickets per order; omit for no cap.
+    #[serde(default)]
+    pub max_qty_per_order: Option<i32>,
+    /// Max tickets per user for this ticket type, across orders; omit to allow one active
+    /// order per user.
+    #[serde(default)]
+    pub max_per_user: Option<i32>,
+    /// Seconds a `CREATED` order has to be paid before it expires; omit for no deadline.
+    #[serde(default)]
+    pub payment_window_secs: Option<i32>,
+    /// Split stock across this many bucket rows (2..=64) so concurrent grabs of a hot
+    /// ticket type don't queue on one row; omit for a single counter.
+    #[serde(default)]
+    pub inventory_shards: Option<i32>,
+    /// Sell through the waiting room: `grab` then needs an admission token.
+    #[serde(default)]
+    pub queue_enabled: bool,
+    /// Max outstanding waiting-room admissions; defaults to 100.
+    #[serde(default)]
+    pub queue_max_active: Option<i32>,
+    /// How stock is split between purchase intents and grabs: `equal` (default, first come
+    /// first served), `reserved_share` (needs `intent_reserved_pct`) or `head_start` (needs
+    /// `intent_head_start_secs`).
+    #[serde(default)]
+    pub intent_policy: Option<String>,
+    /// Percent of `inventory_total` only intents can buy (1..=100); unsharded ticket types only.
+    #[serde(default)]
+    pub intent_reserved_pct: Option<i32>,
+    /// Seconds after `sale_starts_at` during which only intents can buy.
+    #[serde(default)]
+    pub intent_head_start_secs: Option<i32>,
+    /// Assigned seating: one entry per row. `inventory_total` must equal the seat count;
+    /// omit for general admission.
+    #[serde(default)]
+    pub seat_rows: Vec<SeatRow>,
 }
 
+const DEFAULT_QUEUE_MAX_ACTIVE: i32 = 100;
+
+/// Column list for [`TicketTypeDto`]; `inventory_remaining` is summed over the buckets of
+/// sharded ticket types.
+pub(crate) const TICKET_TYPE_COLUMNS: &str = "id, event_id, name, price_cents, inventory_total, \
+     ticket_type_remaining(id, inventory_shards, inventory_remaining) as inventory_remaining, \
+     inventory_shards, sale_starts_at, sale_ends_at, max_qty_per_order, max_per_user, payment_window_secs, \
+     queue_enabled, queue_max_active, intent_policy, intent_reserved_pct, intent_head_start_secs, \
+     intent_reserved_remaining, seated, presale_starts_at";
+
 #[derive(Serialize, ToSchema, sqlx::FromRow)]
 pub struct TicketTypeDto {
     pub id: Uuid,
@@ -91,8 +174,142 @@ pub struct TicketTypeDto {
     pub price_cents: i64,
     pub inventory_total: i32,
     pub inventory_remaining: i32,
+    /// Number of inventory buckets; `null` when unsharded.
+    pub inventory_shards: Option<i32>,
     pub sale_starts_at: DateTime<Utc>,
     pub sale_ends_at: DateTime<Utc>,
+    pub max_qty_per_order: Option<i32>,
+    pub max_per_user: Option<i32>,
+    pub payment_window_secs: Option<i32>,
+    pub queue_enabled: bool,
+    pub queue_max_active: i32,
+    /// `equal`, `reserved_share` or `head_start`.
+    pub intent_policy: String,
+    pub intent_reserved_pct: Option<i32>,
+    pub intent_head_start_secs: Option<i32>,
+    /// Part of `inventory_remaining` grabs can't take; intents use it up first.
+    pub intent_reserved_remaining: i32,
+    /// Sold by seat (see `GET /api/ticket-types/{id}/seats`).
+    pub seated: bool,
+    /// Start of the presale for allow-listed users and presale codes; `null` for none.
+    pub presale_starts_at: Option<DateTime<Utc>>,
+}
+
+impl TicketTypeDto {
+    /// Stock grabs can still take, for [`StockGate::observe`] (as in `StockGate::refresh`).
+    pub(crate) fn grabbable(&self) -> i32 {
+        (self.inventory_remaining - self.intent_reserved_remaining).max(0)
+    }
+}
+
+/// The editable, validated part of a ticket type (everything but name and inventory).
+struct TicketTypeTerms {
+    price_cents: i64,
+    sale_starts_at: DateTime<Utc>,
+    sale_ends_at: DateTime<Utc>,
+    presale_starts_at: Option<DateTime<Utc>>,
+    max_qty_per_order: Option<i32>,
+    max_per_user: Option<i32>,
+    payment_window_secs: Option<i32>,
+    queue_enabled: bool,
+    queue_max_active: i32,
+    intent_policy: String,
+    intent_reserved_pct: Option<i32>,
+    intent_head_start_secs: Option<i32>,
+}
+
+impl TicketTypeTerms {
+    /// Stock held back for intents out of `inventory_total`.
+    fn intent_reserved(&self, inventory_total: i32) -> i32 {
+        match (self.intent_policy.as_str(), self.intent_reserved_pct) {
+            ("reserved_share", Some(pct)) => (inventory_total as i64 * pct as i64 / 100) as i32,
+            _ => 0,
+        }
+    }
+}
+
+fn validate_ticket_type(t: &TicketTypeTerms) -> AppResult<()> {
+    if t.price_cents < 0 {
+        return Err(AppError::BadRequest("price_cents must be >= 0".into()));
+    }
+    if t.sale_ends_at <= t.sale_starts_at {
+        return Err(AppError::BadRequest(
+            "sale_ends_at must be after sale_starts_at".into(),
+        ));
+    }
+    if matches!(t.presale_starts_at, Some(at) if at >= t.sale_starts_at) {
+        return Err(AppError::BadRequest(
+            "presale_starts_at must be before sale_starts_at".into(),
+        ));
+    }
+    if matches!(t.max_qty_per_order, Some(max) if max <= 0) {
+        return Err(AppError::BadRequest("max_qty_per_order must be > 0".into()));
+    }
+    if matches!(t.max_per_user, Some(max) if max <= 0) {
+        return Err(AppError::BadRequest("max_per_user must be > 0".into()));
+    }
+    if matches!(t.payment_window_secs, Some(secs) if secs <= 0) {
+        return Err(AppError::BadRequest(
+            "payment_window_secs must be > 0".into(),
+        ));
+    }
+    if t.queue_max_active <= 0 {
+        return Err(AppError::BadRequest("queue_max_active must be > 0".into()));
+    }
+    // The waiting room only admits from sale_starts_at, so a queued presale
+    // would let nobody in until the public sale.
+    if t.queue_enabled && t.presale_starts_at.is_some() {
+        return Err(AppError::BadRequest(
+            "presale_starts_at can't be combined with queue_enabled".into(),
+        ));
+    }
+    let (pct_required, head_start_required) = match t.intent_policy.as_str() {
+        "equal" => (false, false),
+        "reserved_share" => (true, false),
+        "head_start" => (false, true),
+        _ => {
+            return Err(AppError::BadRequest(
+                "intent_policy must be equal, reserved_share or head_start".into(),
+            ))
+        }
+    };
+    match t.intent_reserved_pct {
+        Some(_) if !pct_required => {
+            return Err(AppError::BadRequest(
+                "intent_reserved_pct only applies to reserved_share".into(),
+            ));
+        }
+        Some(pct) if !(1..=100).contains(&pct) => {
+            return Err(AppError::BadRequest(
+                "intent_reserved_pct must be in 1..=100".into(),
+            ));
+        }
+        None if pct_required => {
+            return Err(AppError::BadRequest(
+                "reserved_share needs intent_reserved_pct".into(),
+            ));
+        }
+        _ => {}
+    }
+    match t.intent_head_start_secs {
+        Some(_) if !head_start_required => {
+            return Err(AppError::BadRequest(
+                "intent_head_start_secs only applies to head_start".into(),
+            ));
+        }
+        Some(secs) if secs <= 0 => {
+            return Err(AppError::BadRequest(
+                "intent_head_start_secs must be > 0".into(),
+            ));
+        }
+        None if head_start_required => {
+            return Err(AppError::BadRequest(
+                "head_start needs intent_head_start_secs".into(),
+            ));
+        }
+        _ => {}
+    }
+    Ok(())
 }
 
 #[utoipa::path(
@@ -100,37 +317,76 @@ pub struct TicketTypeDto {
     path = "/api/admin/events/{event_id}/ticket_types",
     params(("event_id" = Uuid, Path, description = "Event id")),
     request_body = CreateTicketTypeRequest,
-    responses((status=200, body=TicketTypeDto))
+    responses((status=200, body=TicketTypeDto), (status=401), (status=403))
 )]
 pub async fn create_ticket_type(
     axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(stock_gate): axum::extract::State<Arc<StockGate>>,
+    AdminUser(admin): AdminUser,
     Path(event_id): Path<Uuid>,
     Json(req): Json<CreateTicketTypeRequest>,
 ) -> AppResult<Json<TicketTypeDto>> {
     if req.inventory_total <= 0 {
         return Err(AppError::BadRequest("inventory_total must be > 0".into()));
     }
-    if req.price_cents < 0 {
-        return Err(AppError::BadRequest("price_cents must be >= 0".into()));
+    if matches!(req.inventory_shards, Some(n) if !(2..=inventory::MAX_SHARDS).contains(&n)) {
+        return Err(AppError::BadRequest(format!(
+            "inventory_shards must be in 2..={}",
+            inventory::MAX_SHARDS
+        )));
+    }
+    let queue_max_active = req.queue_max_active.unwrap_or(DEFAULT_QUEUE_MAX_ACTIVE);
+    let terms = TicketTypeTerms {
+        price_cents: req.price_cents,
+        sale_starts_at: req.sale_starts_at,
+        sale_ends_at: req.sale_ends_at,
+        presale_starts_at: req.presale_starts_at,
+        max_qty_per_order: req.max_qty_per_order,
+        max_per_user: req.max_per_user,
+        payment_window_secs: req.payment_window_secs,
+        queue_enabled: req.queue_enabled,
+        queue_max_active,
+        intent_policy: req.intent_policy.unwrap_or_else(|| "equal".into()),
+        intent_reserved_pct: req.intent_reserved_pct,
+        intent_head_start_secs: req.intent_head_start_secs,
+    };
+    validate_ticket_type(&terms)?;
+    if req.inventory_shards.is_some() && terms.intent_policy == "reserved_share" {
+        return Err(AppError::BadRequest(
+            "reserved_share needs an unsharded ticket type".into(),
+        ));
     }
-    if req.sale_ends_at <= req.sale_starts_at {
-        return Err(AppError::BadRequest("sale_ends_at must be after sale_starts_at".into()));
+    let seated = !req.seat_rows.is_empty();
+    if seated {
+        if seats::validate_layout(&req.seat_rows)? != req.inventory_total {
+            return Err(AppError::BadRequest(
+                "inventory_total must equal the number of seats".into(),
+            ));
+        }
+        if req.inventory_shards.is_some() {
+            return Err(AppError::BadRequest(
+                "seated ticket types can't be sharded".into(),
+            ));
+        }
     }
 
+    let mut tx = db.pool.begin().await?;
+
     // ensure event exists
     let exists: bool = sqlx::query_scalar("select exists(select 1 from events where id = $1)")
         .bind(event_id)
-        .fetch_one(&db.pool)
+        .fetch_one(&mut *tx)
         .await?;
     if !exists {
+        tx.rollback().await?;
         return Err(AppError::NotFound);
     }
 
     let id = Uuid::new_v4();
-    let rec = sqlx::query_as::<_, TicketTypeDto>(
-        r#"insert into ticket_types (id, event_id, name, price_cents, inventory_total, inventory_remaining, sale_starts_at, sale_ends_at)
-           values ($1,$2,$3,$4,$5,$5,$6,$7)
-           returning id, event_id, name, price_cents, inventory_total, inventory_remaining, sale_starts_at, sale_ends_at"#,
+    // Sharded stock lives in the buckets; the ticket type's own counter stays 0.
+    sqlx::query(
+        r#"insert into ticket_types (id, event_id, name, price_cents, inventory_total, inventory_remaining, sale_starts_at, sale_ends_at, max_qty_per_order, max_per_user, payment_window_secs, inventory_shards, queue_enabled, queue_max_active, intent_policy, intent_reserved_pct, intent_head_start_secs, intent_reserved_remaining, seated, presale_starts_at)
+           values ($1,$2,$3,$4,$5,case when $11::int is null then $5 else 0 end,$6,$7,$8,$9,$10,$11,$12,$13,$14,$15,$16,$17,$18,$19)"#,
     )
     .bind(id)
     .bind(event_id)
@@ -139,9 +395,50 @@ pub async fn create_ticket_type(
     .bind(req.inventory_total)
     .bind(req.sale_starts_at)
     .bind(req.sale_ends_at)
-    .fetch_one(&db.pool)
+    .bind(req.max_qty_per_order)
+    .bind(req.max_per_user)
+    .bind(req.payment_window_secs)
+    .bind(req.inventory_shards)
+    .bind(req.queue_enabled)
+    .bind(queue_max_active)
+    .bind(&terms.intent_policy)
+    .bind(terms.intent_reserved_pct)
+    .bind(terms.intent_head_start_secs)
+    .bind(terms.intent_reserved(req.inventory_total))
+    .bind(seated)
+    .bind(terms.presale_starts_at)
+    .execute(&mut *tx)
     .await?;
+    seats::create(&mut tx, id, &req.seat_rows).await?;
+
+    if let Some(shards) = req.inventory_shards {
+        inventory::create_buckets(&mut tx, id, shards, req.inventory_total).await?;
+    }
 
+    let rec = sqlx::query_as::<_, TicketTypeDto>(&format!(
+        "select {TICKET_TYPE_COLUMNS} from ticket_types where id = $1"
+    ))
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    inventory::record(
+        &mut tx,
+        Movement {
+            ticket_type_id: id,
+            kind: MovementKind::Initial,
+            total_delta: rec.inventory_total,
+            remaining_delta: rec.inventory_remaining,
+            order_id: None,
+            hold_id: None,
+            actor: Some(&admin.username),
+            reason: None,
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    stock_gate.observe(id, rec.grabbable());
     Ok(Json(rec))
 }
 
@@ -155,10 +452,9 @@ pub async fn list_ticket_types(
     axum::extract::State(db): axum::extract::State<Db>,
     Path(event_id): Path<Uuid>,
 ) -> AppResult<Json<Vec<TicketTypeDto>>> {
-    let rows = sqlx::query_as::<_, TicketTypeDto>(
-        r#"select id, event_id, name, price_cents, inventory_total, inventory_remaining, sale_starts_at, sale_ends_at
-           from ticket_types where event_id = $1 order by created_at asc"#,
-    )
+    let rows = sqlx::query_as::<_, TicketTypeDto>(&format!(
+        "select {TICKET_TYPE_COLUMNS} from ticket_types where event_id = $1 order by created_at asc"
+    ))
     .bind(event_id)
     .fetch_all(&db.pool)
     .await?;
@@ -173,20 +469,46 @@ pub struct CreateTicketTypeFlatRequest {
     pub inventory_total: i32,
     pub sale_starts_at: DateTime<Utc>,
     pub sale_ends_at: DateTime<Utc>,
+    #[serde(default)]
+    pub presale_starts_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub max_qty_per_order: Option<i32>,
+    #[serde(default)]
+    pub max_per_user: Option<i32>,
+    #[serde(default)]
+    pub payment_window_secs: Option<i32>,
+    #[serde(default)]
+    pub inventory_shards: Option<i32>,
+    #[serde(default)]
+    pub queue_enabled: bool,
+    #[serde(default)]
+    pub queue_max_active: Option<i32>,
+    #[serde(default)]
+    pub intent_policy: Option<String>,
+    #[serde(default)]
+    pub intent_reserved_pct: Option<i32>,
+    #[serde(default)]
+    pub intent_head_start_secs: Option<i32>,
+    #[serde(default)]
+    pub seat_rows: Vec<SeatRow>,
 }
 
 #[utoipa::path(
     post,
     path = "/api/admin/ticket-types",
     request_body = CreateTicketTypeFlatRequest,
-    responses((status=200, body=TicketTypeDto))
+    responses((status=200, body=TicketTypeDto), (status=401), (status=403))
 )]
 pub async fn create_ticket_type_flat(
     axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(stock_gate): axum::extract::State<Arc<StockGate>>,
+    admin: AdminUser,
     Json(req): Json<CreateTicketTypeFlatRequest>,
 ) -> AppResult<Json<TicketTypeDto>> {
     create_ticket_type(
         axum::extract::State(db),
+        axum::extract::State(stock_gate),
+        admin,
         Path(req.event_id),
         Json(CreateTicketTypeRequest {
             name: req.name,
@@ -194,12 +516,546 @@ pub async fn create_ticket_type_flat(
             inventory_total: req.inventory_total,
             sale_starts_at: req.sale_starts_at,
             sale_ends_at: req.sale_ends_at,
+            presale_starts_at: req.presale_starts_at,
+            max_qty_per_order: req.max_qty_per_order,
+            max_per_user: req.max_per_user,
+            payment_window_secs: req.payment_window_secs,
+            inventory_shards: req.inventory_shards,
+            queue_enabled: req.queue_enabled,
+            queue_max_active: req.queue_max_active,
+            intent_policy: req.intent_policy,
+            intent_reserved_pct: req.intent_reserved_pct,
+            intent_head_start_secs: req.intent_head_start_secs,
+            seat_rows: req.seat_rows,
         }),
     )
     .await
 }
 
-pub fn router() -> Router<Db> {
+/// Deserializes a present field (including `null`) as `Some(..)`, so PATCH bodies can
+/// tell "clear this" (`null`) apart from "leave it" (absent).
+pub(crate) fn present<'de, T, D>(d: D) -> Result<Option<T>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    T::deserialize(d).map(Some)
+}
+
+async fn has_orders_for_event(
+    conn: &mut PgConnection,
+    event_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"select exists(
+             select 1 from orders o join ticket_types t on t.id = o.ticket_type_id
+             where t.event_id = $1)"#,
+    )
+    .bind(event_id)
+    .fetch_one(&mut *conn)
+    .await
+}
+
+async fn has_orders_for_ticket_type(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar("select exists(select 1 from orders where ticket_type_id = $1)")
+        .bind(ticket_type_id)
+        .fetch_one(&mut *conn)
+        .await
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateEventRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub starts_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub ends_at: Option<DateTime<Utc>>,
+    /// `null` removes the cap.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    pub max_per_user: Option<Option<i32>>,
+}
+
+async fn lock_event(
+    conn: &mut PgConnection,
+    event_id: Uuid,
+) -> Result<Option<EventDto>, sqlx::Error> {
+    sqlx::query_as::<_, EventDto>(
+        r#"select id, name, starts_at, ends_at, max_per_user, archived_at
+           from events where id = $1 for update"#,
+    )
+    .bind(event_id)
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/admin/events/{event_id}",
+    params(("event_id" = Uuid, Path, description = "Event id")),
+    request_body = UpdateEventRequest,
+    responses((status=200, body=EventDto), (status=400), (status=401), (status=403), (status=404))
+)]
+pub async fn update_event(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(event_id): Path<Uuid>,
+    Json(req): Json<UpdateEventRequest>,
+) -> AppResult<Json<EventDto>> {
+    let mut tx = db.pool.begin().await?;
+
+    let Some(before) = lock_event(&mut tx, event_id).await? else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+
+    let name = req.name.unwrap_or_else(|| before.name.clone());
+    let starts_at = req.starts_at.unwrap_or(before.starts_at);
+    let ends_at = req.ends_at.unwrap_or(before.ends_at);
+    let max_per_user = req.max_per_user.unwrap_or(before.max_per_user);
+    if let Err(e) = validate_event(starts_at, ends_at, max_per_user) {
+        tx.rollback().await?;
+        return Err(e);
+    }
+
+    let after = sqlx::query_as::<_, EventDto>(
+        r#"update events set name = $2, starts_at = $3, ends_at = $4, max_per_user = $5
+           where id = $1
+           returning id, name, starts_at, ends_at, max_per_user, archived_at"#,
+    )
+    .bind(event_id)
+    .bind(name)
+    .bind(starts_at)
+    .bind(ends_at)
+    .bind(max_per_user)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "event.update",
+            entity_type: "event",
+            entity_id: event_id,
+            forced: false,
+            details: json!({ "before": before, "after": after }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(Json(after))
+}
+
+async fn set_event_archived(
+    db: &Db,
+    admin: &AuthUser,
+    event_id: Uuid,
+    archived: bool,
+) -> AppResult<EventDto> {
+    let mut tx = db.pool.begin().await?;
+
+    let event = sqlx::query_as::<_, EventDto>(
+        r#"update events
+           set archived_at = case when $2 then coalesce(archived_at, now()) else null end
+           where id = $1
+           returning id, name, starts_at, ends_at, max_per_user, archived_at"#,
+    )
+    .bind(event_id)
+    .bind(archived)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(event) = event else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: if archived {
+                "event.archive"
+            } else {
+                "event.unarchive"
+            },
+            entity_type: "event",
+            entity_id: event_id,
+            forced: false,
+            details: json!({}),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(event)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/events/{event_id}/archive",
+    params(("event_id" = Uuid, Path, description = "Event id")),
+    responses((status=200, body=EventDto, description="Hidden from /api/events; purchases are rejected"), (status=401), (status=403), (status=404))
+)]
+pub async fn archive_event(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(event_id): Path<Uuid>,
+) -> AppResult<Json<EventDto>> {
+    Ok(Json(set_event_archived(&db, &admin, event_id, true).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/events/{event_id}/unarchive",
+    params(("event_id" = Uuid, Path, description = "Event id")),
+    responses((status=200, body=EventDto), (status=401), (status=403), (status=404))
+)]
+pub async fn unarchive_event(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(event_id): Path<Uuid>,
+) -> AppResult<Json<EventDto>> {
+    Ok(Json(
+        set_event_archived(&db, &admin, event_id, false).await?,
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/events/{event_id}",
+    params(("event_id" = Uuid, Path, description = "Event id")),
+    responses((status=204), (status=401), (status=403), (status=404), (status=409, description="Event has orders; archive it instead"))
+)]
+pub async fn delete_event(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(event_id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    let mut tx = db.pool.begin().await?;
+
+    let Some(event) = lock_event(&mut tx, event_id).await? else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+    // Lock the ticket types too: a concurrent grab holds (or waits for) these rows while
+    // it inserts its order, so the check below can't miss one.
+    sqlx::query("select id from ticket_types where event_id = $1 for update")
+        .bind(event_id)
+        .fetch_all(&mut *tx)
+        .await?;
+
+    if has_orders_for_event(&mut tx, event_id).await? {
+        tx.rollback().await?;
+        return Err(AppError::Conflict(
+            "event has orders; archive it instead".into(),
+        ));
+    }
+
+    sqlx::query("delete from events where id = $1")
+        .bind(event_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "event.delete",
+            entity_type: "event",
+            entity_id: event_id,
+            forced: false,
+            details: json!({ "before": event }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdateTicketTypeRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Locked once the ticket type has orders, unless `force` is set.
+    #[serde(default)]
+    pub price_cents: Option<i64>,
+    /// Locked once the ticket type has orders, unless `force` is set.
+    #[serde(default)]
+    pub sale_starts_at: Option<DateTime<Utc>>,
+    /// Locked once the ticket type has orders, unless `force` is set.
+    #[serde(default)]
+    pub sale_ends_at: Option<DateTime<Utc>>,
+    /// Locked once the ticket type has orders, unless `force` is set. `null` removes the
+    /// presale.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<DateTime<Utc>>)]
+    pub presale_starts_at: Option<Option<DateTime<Utc>>>,
+    /// `null` removes the cap.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    pub max_qty_per_order: Option<Option<i32>>,
+    /// `null` removes the cap.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    pub max_per_user: Option<Option<i32>>,
+    /// `null` removes the payment deadline for new orders.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    pub payment_window_secs: Option<Option<i32>>,
+    #[serde(default)]
+    pub queue_enabled: Option<bool>,
+    #[serde(default)]
+    pub queue_max_active: Option<i32>,
+    /// Replaces the policy and its parameters: send `intent_reserved_pct` /
+    /// `intent_head_start_secs` along with it. Switching to `reserved_share` (or changing the
+    /// percent) resets the intent reserve from `inventory_total`, capped at the unsold stock.
+    #[serde(default)]
+    pub intent_policy: Option<String>,
+    #[serde(default)]
+    pub intent_reserved_pct: Option<i32>,
+    #[serde(default)]
+    pub intent_head_start_secs: Option<i32>,
+    /// Change price / sale window even though orders exist; recorded in the audit log.
+    #[serde(default)]
+    pub force: bool,
+}
+
+async fn lock_ticket_type(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+) -> Result<Option<TicketTypeDto>, sqlx::Error> {
+    sqlx::query_as::<_, TicketTypeDto>(&format!(
+        "select {TICKET_TYPE_COLUMNS} from ticket_types where id = $1 for update"
+    ))
+    .bind(ticket_type_id)
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/admin/ticket-types/{ticket_type_id}",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    request_body = UpdateTicketTypeRequest,
+    responses((status=200, body=TicketTypeDto), (status=400), (status=401), (status=403), (status=404), (status=409, description="Price / sale window locked by existing orders"))
+)]
+pub async fn update_ticket_type(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(ticket_type_id): Path<Uuid>,
+    Json(req): Json<UpdateTicketTypeRequest>,
+) -> AppResult<Json<TicketTypeDto>> {
+    let mut tx = db.pool.begin().await?;
+
+    // Same row lock as `reserve_stock`, so no order can slip in between the check and the update.
+    let Some(before) = lock_ticket_type(&mut tx, ticket_type_id).await? else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+
+    let name = req.name.unwrap_or_else(|| before.name.clone());
+    let terms = TicketTypeTerms {
+        price_cents: req.price_cents.unwrap_or(before.price_cents),
+        sale_starts_at: req.sale_starts_at.unwrap_or(before.sale_starts_at),
+        sale_ends_at: req.sale_ends_at.unwrap_or(before.sale_ends_at),
+        presale_starts_at: req.presale_starts_at.unwrap_or(before.presale_starts_at),
+        max_qty_per_order: req.max_qty_per_order.unwrap_or(before.max_qty_per_order),
+        max_per_user: req.max_per_user.unwrap_or(before.max_per_user),
+        payment_window_secs: req
+            .payment_window_secs
+            .unwrap_or(before.payment_window_secs),
+        queue_enabled: req.queue_enabled.unwrap_or(before.queue_enabled),
+        queue_max_active: req.queue_max_active.unwrap_or(before.queue_max_active),
+        intent_policy: req
+            .intent_policy
+            .clone()
+            .unwrap_or_else(|| before.intent_policy.clone()),
+        intent_reserved_pct: match req.intent_policy {
+            Some(_) => req.intent_reserved_pct,
+            None => req.intent_reserved_pct.or(before.intent_reserved_pct),
+        },
+        intent_head_start_secs: match req.intent_policy {
+            Some(_) => req.intent_head_start_secs,
+            None => req.intent_head_start_secs.or(before.intent_head_start_secs),
+        },
+    };
+    if let Err(e) = validate_ticket_type(&terms) {
+        tx.rollback().await?;
+        return Err(e);
+    }
+    if before.inventory_shards.is_some() && terms.intent_policy == "reserved_share" {
+        tx.rollback().await?;
+        return Err(AppError::BadRequest(
+            "reserved_share needs an unsharded ticket type".into(),
+        ));
+    }
+    let intent_reserved_remaining = if terms.intent_policy == before.intent_policy
+        && terms.intent_reserved_pct == before.intent_reserved_pct
+    {
+        before.intent_reserved_remaining
+    } else {
+        terms
+            .intent_reserved(before.inventory_total)
+            .min(before.inventory_remaining)
+    };
+
+    let guarded_change = terms.price_cents != before.price_cents
+        || terms.sale_starts_at != before.sale_starts_at
+        || terms.sale_ends_at != before.sale_ends_at
+        || terms.presale_starts_at != before.presale_starts_at;
+    let forced = guarded_change && has_orders_for_ticket_type(&mut tx, ticket_type_id).await?;
+    if forced && !req.force {
+        tx.rollback().await?;
+        return Err(AppError::Conflict(
+            "ticket type has orders; price and sale window can only change with force".into(),
+        ));
+    }
+
+    let after = sqlx::query_as::<_, TicketTypeDto>(&format!(
+        r#"update ticket_types
+           set name = $2, price_cents = $3, sale_starts_at = $4, sale_ends_at = $5,
+               max_qty_per_order = $6, max_per_user = $7, payment_window_secs = $8,
+               queue_enabled = $9, queue_max_active = $10, intent_policy = $11,
+               intent_reserved_pct = $12, intent_head_start_secs = $13,
+               intent_reserved_remaining = $14, presale_starts_at = $15
+           where id = $1
+           returning {TICKET_TYPE_COLUMNS}"#
+    ))
+    .bind(ticket_type_id)
+    .bind(name)
+    .bind(terms.price_cents)
+    .bind(terms.sale_starts_at)
+    .bind(terms.sale_ends_at)
+    .bind(terms.max_qty_per_order)
+    .bind(terms.max_per_user)
+    .bind(terms.payment_window_secs)
+    .bind(terms.queue_enabled)
+    .bind(terms.queue_max_active)
+    .bind(&terms.intent_policy)
+    .bind(terms.intent_reserved_pct)
+    .bind(terms.intent_head_start_secs)
+    .bind(intent_reserved_remaining)
+    .bind(terms.presale_starts_at)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "ticket_type.update",
+            entity_type: "ticket_type",
+            entity_id: ticket_type_id,
+            forced,
+            details: json!({ "before": before, "after": after }),
+        },
+    )
+    .await?;
+    // Waiting intents may be able to buy now (earlier opening, higher caps, a new reserve).
+    intent_wakeup::wake(&mut tx, ticket_type_id).await?;
+
+    tx.commit().await?;
+    Ok(Json(after))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/ticket-types/{ticket_type_id}",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    responses((status=204), (status=401), (status=403), (status=404), (status=409, description="Ticket type has orders"))
+)]
+pub async fn delete_ticket_type(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(ticket_type_id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    let mut tx = db.pool.begin().await?;
+
+    let Some(ticket_type) = lock_ticket_type(&mut tx, ticket_type_id).await? else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+    if has_orders_for_ticket_type(&mut tx, ticket_type_id).await? {
+        tx.rollback().await?;
+        return Err(AppError::Conflict("ticket type has orders".into()));
+    }
+
+    sqlx::query("delete from ticket_types where id = $1")
+        .bind(ticket_type_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "ticket_type.delete",
+            entity_type: "ticket_type",
+            entity_id: ticket_type_id,
+            forced: false,
+            details: json!({ "before": ticket_type }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct AuditLogQuery {
+    /// Only entries for this event / ticket type.
+    pub entity_id: Option<Uuid>,
+    /// Default 100, max 1000.
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct AuditEntryDto {
+    pub id: Uuid,
+    pub actor: String,
+    pub action: String,
+    pub entity_type: String,
+    pub entity_id: Uuid,
+    pub forced: bool,
+    #[schema(value_type = Object)]
+    pub details: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/audit-log",
+    params(AuditLogQuery),
+    responses((status=200, body=[AuditEntryDto]), (status=401), (status=403))
+)]
+pub async fn audit_log(
+    axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
+    Query(q): Query<AuditLogQuery>,
+) -> AppResult<Json<Vec<AuditEntryDto>>> {
+    let rows = sqlx::query_as::<_, AuditEntryDto>(
+        r#"select id, actor, action, entity_type, entity_id, forced, details, created_at
+           from admin_audit_log
+           where ($1::uuid is null or entity_id = $1)
+           order by created_at desc
+           limit $2"#,
+    )
+    .bind(q.entity_id)
+    .bind(q.limit.unwrap_or(100).clamp(1, 1000))
+    .fetch_all(&db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+pub fn router() -> Router<AppState> {
     Router::new()
         // required paths
         .route("/api/admin/events", post(create_event))
@@ -207,6 +1063,20 @@ pub fn router() -> Router<Db> {
         .route("/api/admin/events/:event_id/ticket_types", post(create_ticket_type))
         .route("/api/events/:event_id/ticket_types", get(list_ticket_types))
         .route("/api/admin/ticket-types", post(create_ticket_type_flat))
+        .route(
+            "/api/admin/events/:event_id",
+            patch(update_event).delete(delete_event),
+        )
+        .route("/api/admin/events/:event_id/archive", post(archive_event))
+        .route(
+            "/api/admin/events/:event_id/unarchive",
+            post(unarchive_event),
+        )
+        .route(
+            "/api/admin/ticket-types/:ticket_type_id",
+            patch(update_ticket_type).delete(delete_ticket_type),
+        )
+        .route("/api/admin/audit-log", get(audit_log))
         // compatibility (old, no /api prefix)
         .route("/admin/events", post(create_event))
         .route("/events", get(list_events))
@@ -1,30 +1,182 @@
-use axum::{routing::post, Json, Router};
+use std::net::SocketAddr;
+
+use axum::{extract::ConnectInfo, http::StatusCode, routing::post, Json, Router};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::{auth, db::Db, error::{AppError, AppResult}};
+use crate::{
+    abuse::{self, AbuseEvent},
+    auth::{self, Role},
+    config::Config,
+    error::{AbuseReason, AppError, AppResult},
+    state::AppState,
+};
+
+const MIN_PASSWORD_LEN: usize = 8;
+const MAX_USERNAME_LEN: usize = 64;
+
+#[derive(Deserialize, ToSchema)]
+pub struct RegisterRequest {
+    pub username: String,
+    pub password: String,
+}
 
 #[derive(Deserialize, ToSchema)]
 pub struct LoginRequest {
     pub username: String,
+    /// Required unless the server runs with `AUTH_PASSWORDLESS` (dev/test only).
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct LogoutRequest {
+    pub refresh_token: String,
+    /// Revoke every refresh token of the user, not just this session.
+    #[serde(default)]
+    pub all: bool,
 }
 
 #[derive(Serialize, ToSchema)]
 pub struct LoginResponse {
+    /// Access token (JWT) for the `Authorization: Bearer` header.
     pub token: String,
+    /// Access token lifetime in seconds.
+    pub expires_in: i64,
+    /// Single-use; exchange at `/api/auth/refresh` for a new pair.
+    pub refresh_token: String,
     pub user_id: Uuid,
     pub username: String,
+    /// `user` or `admin`.
+    pub role: String,
+}
+
+async fn hash_password(password: String) -> AppResult<String> {
+    // Argon2 is deliberately slow; keep it off the async workers.
+    let hash = tokio::task::spawn_blocking(move || auth::hash_password(&password))
+        .await
+        .map_err(anyhow::Error::from)??;
+    Ok(hash)
+}
+
+async fn verify_password(password: String, password_hash: String) -> AppResult<bool> {
+    let ok = tokio::task::spawn_blocking(move || auth::verify_password(&password, &password_hash))
+        .await
+        .map_err(anyhow::Error::from)?;
+    Ok(ok)
+}
+
+/// Issues an access token plus a refresh token in `family_id` (a new family for a fresh login).
+async fn issue_session(
+    conn: &mut PgConnection,
+    cfg: &Config,
+    user_id: Uuid,
+    username: String,
+    role: Role,
+    family_id: Option<Uuid>,
+) -> AppResult<(Uuid, LoginResponse)> {
+    let token = auth::issue_token(
+        &cfg.jwt_secret,
+        cfg.access_token_ttl_secs,
+        user_id,
+        &username,
+        role,
+    )
+    .map_err(AppError::Internal)?;
+
+    let (refresh_token, token_hash) = auth::new_refresh_token();
+    let refresh_id = Uuid::new_v4();
+    sqlx::query(
+        r#"insert into refresh_tokens (id, user_id, family_id, token_hash, expires_at)
+           values ($1,$2,$3,$4,$5)"#,
+    )
+    .bind(refresh_id)
+    .bind(user_id)
+    .bind(family_id.unwrap_or_else(Uuid::new_v4))
+    .bind(token_hash)
+    .bind(Utc::now() + chrono::Duration::seconds(cfg.refresh_token_ttl_secs))
+    .execute(&mut *conn)
+    .await?;
+
+    Ok((
+        refresh_id,
+        LoginResponse {
+            token,
+            expires_in: cfg.access_token_ttl_secs,
+            refresh_token,
+            user_id,
+            username,
+            role: role.as_str().to_string(),
+        },
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/register",
+    request_body = RegisterRequest,
+    responses((status=200, body=LoginResponse), (status=400), (status=409, description="Username taken"))
+)]
+pub async fn register(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Json(req): Json<RegisterRequest>,
+) -> AppResult<Json<LoginResponse>> {
+    let username = req.username.trim().to_string();
+    if username.is_empty() || username.chars().count() > MAX_USERNAME_LEN {
+        return Err(AppError::BadRequest(format!(
+            "username must be 1..={MAX_USERNAME_LEN} characters"
+        )));
+    }
+    if req.password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(AppError::BadRequest(format!(
+            "password must be at least {MIN_PASSWORD_LEN} characters"
+        )));
+    }
+
+    let password_hash = hash_password(req.password).await?;
+
+    let mut tx = state.db.pool.begin().await?;
+
+    let user_id = Uuid::new_v4();
+    let inserted =
+        sqlx::query("insert into users (id, username, password_hash) values ($1, $2, $3)")
+            .bind(user_id)
+            .bind(&username)
+            .bind(&password_hash)
+            .execute(&mut *tx)
+            .await;
+
+    if let Err(e) = inserted {
+        if e.as_database_error().and_then(|d| d.constraint()) == Some("users_username_key") {
+            tx.rollback().await?;
+            return Err(AppError::Conflict("username already taken".into()));
+        }
+        return Err(e.into());
+    }
+
+    let (_, session) =
+        issue_session(&mut tx, &state.cfg, user_id, username, Role::User, None).await?;
+    tx.commit().await?;
+    Ok(Json(session))
 }
 
 #[utoipa::path(
     post,
     path = "/api/auth/login",
     request_body = LoginRequest,
-    responses((status=200, body=LoginResponse))
+    responses((status=200, body=LoginResponse), (status=400), (status=401), (status=403, description="Account blocked"), (status=429, description="Too many accounts from this IP"))
 )]
 pub async fn login(
-    axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(state): axum::extract::State<AppState>,
+    peer: Option<ConnectInfo<SocketAddr>>,
     Json(req): Json<LoginRequest>,
 ) -> AppResult<Json<LoginResponse>> {
     let username = req.username.trim().to_string();
@@ -32,29 +184,185 @@ pub async fn login(
         return Err(AppError::BadRequest("username required".into()));
     }
 
-    let row = sqlx::query!(
-        r#"insert into users (id, username)
-           values ($1, $2)
-           on conflict (username) do update set username = excluded.username
-           returning id, username"#,
-        Uuid::new_v4(),
-        username
+    let existing: Option<(Uuid, String, String, Option<String>)> =
+        sqlx::query_as("select id, username, role, password_hash from users where username = $1")
+            .bind(&username)
+            .fetch_optional(&state.db.pool)
+            .await?;
+
+    let (user_id, username, role): (Uuid, String, String) = match (req.password, existing) {
+        (Some(password), Some((user_id, username, role, Some(password_hash)))) => {
+            if !verify_password(password, password_hash).await? {
+                return Err(AppError::Unauthorized);
+            }
+            (user_id, username, role)
+        }
+        (Some(password), _) => {
+            // Same work as a wrong password, so timing doesn't tell which usernames exist.
+            verify_password(password, auth::dummy_password_hash().to_string()).await?;
+            return Err(AppError::Unauthorized);
+        }
+        (None, _) if !state.cfg.auth_passwordless => {
+            return Err(AppError::BadRequest("password required".into()));
+        }
+        // Passwordless dev login never bypasses an account that has a password.
+        (None, Some((_, _, _, Some(_)))) => return Err(AppError::Unauthorized),
+        (None, _) => sqlx::query_as(
+            r#"insert into users (id, username)
+               values ($1, $2)
+               on conflict (username) do update set username = excluded.username
+                 where users.password_hash is null
+               returning id, username, role"#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(username)
+        .fetch_optional(&state.db.pool)
+        .await?
+        .ok_or(AppError::Unauthorized)?,
+    };
+
+    let ip = peer.map(|ConnectInfo(addr)| addr.ip().to_canonical());
+    if state.blocklist.is_user_blocked(user_id) {
+        abuse::report(
+            &state.db.pool,
+            AbuseEvent {
+                reason: AbuseReason::BlockedUser,
+                user_id: Some(user_id),
+                ip,
+                path: Some("/api/auth/login"),
+                details: serde_json::json!({}),
+            },
+        )
+        .await;
+        return Err(AppError::Abuse(AbuseReason::BlockedUser));
+    }
+    if let (Some(ip), max @ 1..) = (ip, state.cfg.login_max_accounts_per_ip) {
+        abuse::check_login_ip(
+            &state.db.pool,
+            ip,
+            user_id,
+            max,
+            state.cfg.login_ip_window_secs,
+        )
+        .await?;
+    }
+
+    let role = role.parse().map_err(AppError::Internal)?;
+    let mut tx = state.db.pool.begin().await?;
+    let (_, session) = issue_session(&mut tx, &state.cfg, user_id, username, role, None).await?;
+    tx.commit().await?;
+    Ok(Json(session))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/refresh",
+    request_body = RefreshRequest,
+    responses((status=200, body=LoginResponse), (status=401, description="Unknown, expired or already used refresh token"))
+)]
+pub async fn refresh(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Json(req): Json<RefreshRequest>,
+) -> AppResult<Json<LoginResponse>> {
+    let token_hash = auth::hash_refresh_token(req.refresh_token.trim());
+
+    let mut tx = state.db.pool.begin().await?;
+
+    let row: Option<(Uuid, Uuid, Uuid, bool, bool)> = sqlx::query_as(
+        r#"select id, user_id, family_id, revoked_at is not null, expires_at <= now()
+           from refresh_tokens where token_hash = $1 for update"#,
+    )
+    .bind(&token_hash)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some((token_id, user_id, family_id, revoked, expired)) = row else {
+        tx.rollback().await?;
+        return Err(AppError::Unauthorized);
+    };
+
+    if revoked {
+        // A rotated token came back: assume it leaked and end the whole session family.
+        sqlx::query(
+            "update refresh_tokens set revoked_at = now() where family_id = $1 and revoked_at is null",
+        )
+        .bind(family_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        return Err(AppError::Unauthorized);
+    }
+    if expired {
+        tx.rollback().await?;
+        return Err(AppError::Unauthorized);
+    }
+
+    // Role comes from the row, so promotions and demotions apply from the next refresh.
+    let (username, role): (String, String) =
+        sqlx::query_as("select username, role from users where id = $1")
+            .bind(user_id)
+            .fetch_one(&mut *tx)
+            .await?;
+    let role = role.parse().map_err(AppError::Internal)?;
+
+    let (new_id, session) = issue_session(
+        &mut tx,
+        &state.cfg,
+        user_id,
+        username,
+        role,
+        Some(family_id),
     )
-    .fetch_one(&db.pool)
     .await?;
+    sqlx::query("update refresh_tokens set revoked_at = now(), replaced_by = $2 where id = $1")
+        .bind(token_id)
+        .bind(new_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Json(session))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/auth/logout",
+    request_body = LogoutRequest,
+    responses((status=204, description="Refresh token revoked (also returned for unknown tokens)"))
+)]
+pub async fn logout(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Json(req): Json<LogoutRequest>,
+) -> AppResult<StatusCode> {
+    let token_hash = auth::hash_refresh_token(req.refresh_token.trim());
 
-    let token = auth::issue_token(row.id, &row.username).map_err(AppError::Internal)?;
+    if req.all {
+        sqlx::query(
+            r#"update refresh_tokens set revoked_at = now()
+               where revoked_at is null
+                 and user_id = (select user_id from refresh_tokens where token_hash = $1)"#,
+        )
+        .bind(&token_hash)
+        .execute(&state.db.pool)
+        .await?;
+    } else {
+        sqlx::query(
+            "update refresh_tokens set revoked_at = now() where token_hash = $1 and revoked_at is null",
+        )
+        .bind(&token_hash)
+        .execute(&state.db.pool)
+        .await?;
+    }
 
-    Ok(Json(LoginResponse {
-        token,
-        user_id: row.id,
-        username: row.username,
-    }))
+    Ok(StatusCode::NO_CONTENT)
 }
 
-pub fn router() -> Router<Db> {
+pub fn router() -> Router<AppState> {
     Router::new()
+        .route("/api/auth/register", post(register))
         .route("/api/auth/login", post(login))
+        .route("/api/auth/refresh", post(refresh))
+        .route("/api/auth/logout", post(logout))
         // compatibility
         .route("/auth/login", post(login))
 }
@@ -3,7 +3,7 @@ use axum::{Json, Router};
 use serde::Serialize;
 use utoipa::ToSchema;
 
-use crate::db::Db;
+use crate::state::AppState;
 
 #[derive(Serialize, ToSchema)]
 pub struct HealthzResponse {
@@ -19,6 +19,6 @@ pub async fn healthz() -> Json<HealthzResponse> {
     Json(HealthzResponse { ok: true })
 }
 
-pub fn router() -> Router<Db> {
+pub fn router() -> Router<AppState> {
     Router::new().route("/healthz", get(healthz))
 }
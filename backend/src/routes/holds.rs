@@ -0,0 +1,383 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    extract::{rejection::JsonRejection, ConnectInfo, Path},
+    http::HeaderMap,
+    routing::{delete, get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    db::Db,
+    error::{AppError, AppResult},
+    holds, order_state, promo, purchase,
+    routes::seckill::{self, Caller, GrabRequest, OrderDto},
+    seats,
+    state::AppState,
+    stock_gate::StockGate,
+};
+
+const HOLD_COLUMNS: &str = "id, user_id, ticket_type_id, qty, price_cents, status, expires_at, \
+     order_id, created_at, updated_at";
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateHoldRequest {
+    pub ticket_type_id: Uuid,
+    #[serde(default = "default_qty")]
+    #[schema(default = 1)]
+    pub qty: i32,
+    /// Seated ticket types only, as for grab.
+    #[serde(default)]
+    pub seat_ids: Vec<Uuid>,
+    /// As for grab: unlocks the presale for users not on the allow-list.
+    #[serde(default)]
+    pub presale_code: Option<String>,
+    /// Defaults to `HOLD_TTL_SECS`; at most `HOLD_MAX_TTL_SECS`.
+    #[serde(default)]
+    pub ttl_secs: Option<i64>,
+}
+
+fn default_qty() -> i32 {
+    1
+}
+
+#[derive(Deserialize, ToSchema, Default)]
+pub struct CheckoutRequest {
+    /// Discount code, as for grab; checkout fails (and the hold stays active) if it doesn't
+    /// apply or is used up.
+    #[serde(default)]
+    pub promo_code: Option<String>,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct HoldDto {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub ticket_type_id: Uuid,
+    pub qty: i32,
+    /// Unit price when the stock was taken; checkout charges it.
+    pub price_cents: i64,
+    /// `ACTIVE`, `CONVERTED` (checked out), `RELEASED` (given up) or `EXPIRED`.
+    pub status: String,
+    pub expires_at: DateTime<Utc>,
+    /// Set once checked out.
+    pub order_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Takes stock (and seats) exactly like a grab — same waiting room, challenge, limits and
+/// intent policy — but keeps it aside for `ttl_secs` instead of creating an order.
+#[utoipa::path(
+    post,
+    path = "/api/holds",
+    request_body = CreateHoldRequest,
+    params(
+        ("x-admission-token" = Option<String>, Header, description = "Waiting-room admission; required when the ticket type has queue_enabled"),
+        ("x-grab-challenge" = Option<String>, Header, description = "Challenge from GET /api/grab-challenge; required when proof of work is on"),
+        ("x-grab-solution" = Option<String>, Header, description = "Solution of the challenge")
+    ),
+    responses((status=200, body=HoldDto), (status=400, description="Invalid qty / ttl_secs / seat_ids"), (status=401), (status=403, description="As for grab"), (status=409, description="Sold out / not started / purchase limit reached / seats taken"))
+)]
+pub async fn create_hold(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    auth: AuthUser,
+    peer: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(req): Json<CreateHoldRequest>,
+) -> AppResult<Json<HoldDto>> {
+    let ttl_secs = req.ttl_secs.unwrap_or(state.cfg.hold_ttl_secs);
+    if !(1..=state.cfg.hold_max_ttl_secs).contains(&ttl_secs) {
+        return Err(AppError::BadRequest(format!(
+            "ttl_secs must be in 1..={}",
+            state.cfg.hold_max_ttl_secs
+        )));
+    }
+    let mut item = GrabRequest {
+        ticket_type_id: req.ticket_type_id,
+        qty: req.qty,
+        seat_ids: req.seat_ids,
+        promo_code: None,
+        presale_code: req.presale_code,
+    };
+    item.normalize()?;
+    let caller = Caller {
+        user_id: auth.user_id,
+        ip: peer.map(|ConnectInfo(addr)| addr.ip().to_canonical()),
+        path: "/api/holds",
+    };
+    let passes = seckill::read_passes(&state, &caller, &headers, item.ticket_type_id).await?;
+
+    let Some(permit) = state.stock_gate.try_admit(item.ticket_type_id, item.qty) else {
+        return Err(AppError::Conflict("sold out".into()));
+    };
+
+    let mut tx = state.db.pool.begin().await?;
+    let now = Utc::now();
+    let hold_id = Uuid::new_v4();
+    let reserved = match seckill::take_stock(
+        &mut tx,
+        &state,
+        &caller,
+        &item,
+        &passes,
+        purchase::Holder::Hold(hold_id),
+        now,
+    )
+    .await
+    {
+        Ok(reserved) => reserved,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e);
+        }
+    };
+
+    let rec = sqlx::query_as::<_, HoldDto>(&format!(
+        r#"insert into holds
+             (id, user_id, ticket_type_id, qty, price_cents, payment_window_secs, expires_at)
+           values ($1,$2,$3,$4,$5,$6,$7)
+           returning {HOLD_COLUMNS}"#
+    ))
+    .bind(hold_id)
+    .bind(auth.user_id)
+    .bind(item.ticket_type_id)
+    .bind(item.qty)
+    .bind(reserved.price_cents)
+    .bind(reserved.payment_window_secs)
+    .bind(now + chrono::Duration::seconds(ttl_secs))
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    permit.consume();
+    Ok(Json(rec))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/holds/me",
+    responses((status=200, body=[HoldDto]), (status=401))
+)]
+pub async fn my_holds(
+    axum::extract::State(db): axum::extract::State<Db>,
+    auth: AuthUser,
+) -> AppResult<Json<Vec<HoldDto>>> {
+    let rows = sqlx::query_as::<_, HoldDto>(&format!(
+        r#"select {HOLD_COLUMNS}
+           from holds where user_id=$1 order by created_at desc"#
+    ))
+    .bind(auth.user_id)
+    .fetch_all(&db.pool)
+    .await?;
+
+    Ok(Json(rows))
+}
+
+#[derive(sqlx::FromRow)]
+struct LockedHold {
+    ticket_type_id: Uuid,
+    qty: i32,
+    price_cents: i64,
+    payment_window_secs: Option<i32>,
+    status: String,
+    order_id: Option<Uuid>,
+    expired: bool,
+}
+
+async fn lock_hold(
+    conn: &mut sqlx::PgConnection,
+    hold_id: Uuid,
+    user_id: Uuid,
+) -> AppResult<LockedHold> {
+    sqlx::query_as::<_, LockedHold>(
+        r#"select ticket_type_id, qty, price_cents, payment_window_secs, status, order_id,
+                  expires_at <= now() as expired
+           from holds where id = $1 and user_id = $2
+           for update"#,
+    )
+    .bind(hold_id)
+    .bind(user_id)
+    .fetch_optional(&mut *conn)
+    .await?
+    .ok_or(AppError::NotFound)
+}
+
+/// Turns an active hold into a `CREATED` order at the held price, less any promo code,
+/// without competing for stock again. Checking out twice returns the same order; an expired
+/// hold is released. The body is optional.
+#[utoipa::path(
+    post,
+    path = "/api/holds/{hold_id}/checkout",
+    params(("hold_id" = Uuid, Path, description = "Hold id")),
+    request_body = Option<CheckoutRequest>,
+    responses((status=200, body=OrderDto), (status=400, description="Promo code unknown or not applicable"), (status=401), (status=404), (status=409, description="Hold expired or released / promo code used up"))
+)]
+pub async fn checkout(
+    axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(stock_gate): axum::extract::State<Arc<StockGate>>,
+    auth: AuthUser,
+    Path(hold_id): Path<Uuid>,
+    req: Result<Json<CheckoutRequest>, JsonRejection>,
+) -> AppResult<Json<OrderDto>> {
+    let req = match req {
+        Ok(Json(req)) => req,
+        // No JSON body at all: plain checkout.
+        Err(JsonRejection::MissingJsonContentType(_)) => CheckoutRequest::default(),
+        Err(e) => return Err(AppError::BadRequest(e.body_text())),
+    };
+    let mut tx = db.pool.begin().await?;
+    let hold = match lock_hold(&mut tx, hold_id, auth.user_id).await {
+        Ok(hold) => hold,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e);
+        }
+    };
+
+    match hold.status.as_str() {
+        "CONVERTED" => {
+            let order = sqlx::query_as::<_, OrderDto>(
+                r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
+                   from orders where id = $1"#,
+            )
+            .bind(hold.order_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            return Ok(Json(order));
+        }
+        "ACTIVE" => {}
+        status => {
+            tx.rollback().await?;
+            return Err(AppError::Conflict(format!("hold is {status}")));
+        }
+    }
+    if hold.expired {
+        // The sweeper hasn't got to it yet.
+        holds::end(&mut tx, hold_id, hold.ticket_type_id, hold.qty, "EXPIRED").await?;
+        tx.commit().await?;
+        stock_gate.release(hold.ticket_type_id, hold.qty);
+        return Err(AppError::Conflict("hold is EXPIRED".into()));
+    }
+
+    let now = Utc::now();
+    let terms = purchase::Reserved {
+        price_cents: hold.price_cents,
+        payment_window_secs: hold.payment_window_secs,
+        intent_reserved_qty: 0,
+    };
+    let order_id = Uuid::new_v4();
+    let mut amount_cents = hold.price_cents * hold.qty as i64;
+    if let Some(code) = &req.promo_code {
+        // `once_per_user` is checked under the user's row lock, as at grab.
+        sqlx::query("select id from users where id = $1 for update")
+            .bind(auth.user_id)
+            .execute(&mut *tx)
+            .await?;
+        match promo::redeem(
+            &mut tx,
+            code,
+            auth.user_id,
+            hold.ticket_type_id,
+            order_id,
+            amount_cents,
+            now,
+        )
+        .await
+        {
+            Ok(discount) => amount_cents -= discount,
+            Err(e) => {
+                tx.rollback().await?;
+                return Err(e);
+            }
+        }
+    }
+
+    let order = sqlx::query_as::<_, OrderDto>(
+        r#"insert into orders (id, user_id, ticket_type_id, qty, amount_cents, status, idempotency_key, expires_at)
+           values ($1,$2,$3,$4,$5,'CREATED', $6, $7)
+           returning id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at"#,
+    )
+    .bind(order_id)
+    .bind(auth.user_id)
+    .bind(hold.ticket_type_id)
+    .bind(hold.qty)
+    .bind(amount_cents)
+    .bind(format!("hold:{hold_id}"))
+    .bind(terms.payment_deadline(now))
+    .fetch_one(&mut *tx)
+    .await?;
+    order_state::record_created(&mut tx, order.id, &format!("hold:{hold_id}")).await?;
+    seats::transfer(&mut tx, hold_id, order.id).await?;
+
+    sqlx::query(
+        "update holds set status = 'CONVERTED', order_id = $2, updated_at = now() where id = $1",
+    )
+    .bind(hold_id)
+    .bind(order.id)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Json(order))
+}
+
+/// Gives the stock back before the hold expires. Releasing twice is a no-op.
+#[utoipa::path(
+    delete,
+    path = "/api/holds/{hold_id}",
+    params(("hold_id" = Uuid, Path, description = "Hold id")),
+    responses((status=200, body=HoldDto), (status=401), (status=404), (status=409, description="Already checked out"))
+)]
+pub async fn release_hold(
+    axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(stock_gate): axum::extract::State<Arc<StockGate>>,
+    auth: AuthUser,
+    Path(hold_id): Path<Uuid>,
+) -> AppResult<Json<HoldDto>> {
+    let mut tx = db.pool.begin().await?;
+    let hold = match lock_hold(&mut tx, hold_id, auth.user_id).await {
+        Ok(hold) => hold,
+        Err(e) => {
+            tx.rollback().await?;
+            return Err(e);
+        }
+    };
+    let released = match hold.status.as_str() {
+        "ACTIVE" => {
+            holds::end(&mut tx, hold_id, hold.ticket_type_id, hold.qty, "RELEASED").await?;
+            true
+        }
+        "RELEASED" | "EXPIRED" => false,
+        status => {
+            tx.rollback().await?;
+            return Err(AppError::Conflict(format!("hold is {status}")));
+        }
+    };
+
+    let rec =
+        sqlx::query_as::<_, HoldDto>(&format!("select {HOLD_COLUMNS} from holds where id = $1"))
+            .bind(hold_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    tx.commit().await?;
+    if released {
+        stock_gate.release(hold.ticket_type_id, hold.qty);
+    }
+    Ok(Json(rec))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/holds", post(create_hold))
+        .route("/api/holds/me", get(my_holds))
+        .route("/api/holds/:hold_id", delete(release_hold))
+        .route("/api/holds/:hold_id/checkout", post(checkout))
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::Path,
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    auth::AdminUser,
+    db::Db,
+    error::{AppError, AppResult},
+    intent_wakeup,
+    inventory::{self, Discrepancy, Movement, MovementKind},
+    routes::admin::{TicketTypeDto, TICKET_TYPE_COLUMNS},
+    state::AppState,
+    stock_gate::StockGate,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct AdjustInventoryRequest {
+    /// Seats to add (positive) or remove (negative); applied to total and remaining alike.
+    pub delta: i32,
+    pub reason: String,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct MovementDto {
+    pub id: Uuid,
+    pub ticket_type_id: Uuid,
+    /// `INITIAL`, `RESERVE`, `RELEASE` or `ADJUST`.
+    pub kind: String,
+    pub total_delta: i32,
+    pub remaining_delta: i32,
+    pub order_id: Option<Uuid>,
+    pub hold_id: Option<Uuid>,
+    pub actor: Option<String>,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReconcileReport {
+    /// `true` when every ticket type's ledger sums to its stock columns.
+    pub consistent: bool,
+    pub discrepancies: Vec<Discrepancy>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/ticket-types/{ticket_type_id}/inventory",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    request_body = AdjustInventoryRequest,
+    responses((status=200, body=TicketTypeDto), (status=400), (status=401), (status=403), (status=404), (status=409, description="Would make remaining stock negative"))
+)]
+pub async fn adjust_inventory(
+    axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(stock_gate): axum::extract::State<Arc<StockGate>>,
+    AdminUser(admin): AdminUser,
+    Path(ticket_type_id): Path<Uuid>,
+    Json(req): Json<AdjustInventoryRequest>,
+) -> AppResult<Json<TicketTypeDto>> {
+    let reason = req.reason.trim().to_string();
+    if req.delta == 0 {
+        return Err(AppError::BadRequest("delta must not be 0".into()));
+    }
+    if reason.is_empty() {
+        return Err(AppError::BadRequest("reason required".into()));
+    }
+
+    let mut tx = db.pool.begin().await?;
+
+    // Locks out grabs of this ticket type for the duration (sharded ones hold `for key share`).
+    let row: Option<(Option<i32>, bool)> = sqlx::query_as(
+        "select inventory_shards, seated from ticket_types where id = $1 for update",
+    )
+    .bind(ticket_type_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some((shards, seated)) = row else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+    // Stock of seated ticket types is the seat map.
+    if seated {
+        tx.rollback().await?;
+        return Err(AppError::BadRequest(
+            "seated ticket types can't be adjusted".into(),
+        ));
+    }
+
+    // Removing seats can only take unsold ones; the intent reserve shrinks with them if needed.
+    let applied = match shards {
+        None => {
+            sqlx::query(
+                r#"update ticket_types
+                   set inventory_remaining = inventory_remaining + $2,
+                       intent_reserved_remaining = least(intent_reserved_remaining, inventory_remaining + $2)
+                   where id = $1 and inventory_remaining + $2 >= 0"#,
+            )
+            .bind(ticket_type_id)
+            .bind(req.delta)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+                == 1
+        }
+        Some(shards) if req.delta > 0 => {
+            inventory::spread_into_buckets(&mut tx, ticket_type_id, shards, req.delta).await?;
+            true
+        }
+        Some(_) => inventory::take_spread(&mut tx, ticket_type_id, -req.delta).await?,
+    };
+    if !applied {
+        tx.rollback().await?;
+        return Err(AppError::Conflict(
+            "not enough unsold seats to remove".into(),
+        ));
+    }
+
+    let ticket_type = sqlx::query_as::<_, TicketTypeDto>(&format!(
+        r#"update ticket_types set inventory_total = inventory_total + $2
+           where id = $1
+           returning {TICKET_TYPE_COLUMNS}"#
+    ))
+    .bind(ticket_type_id)
+    .bind(req.delta)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    inventory::record(
+        &mut tx,
+        Movement {
+            ticket_type_id,
+            kind: MovementKind::Adjust,
+            total_delta: req.delta,
+            remaining_delta: req.delta,
+            order_id: None,
+            hold_id: None,
+            actor: Some(&admin.username),
+            reason: Some(&reason),
+        },
+    )
+    .await?;
+    if req.delta > 0 {
+        intent_wakeup::wake(&mut tx, ticket_type_id).await?;
+    }
+
+    tx.commit().await?;
+    stock_gate.observe(ticket_type_id, ticket_type.grabbable());
+    Ok(Json(ticket_type))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/ticket-types/{ticket_type_id}/inventory",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    responses((status=200, body=[MovementDto]), (status=401), (status=403))
+)]
+pub async fn list_movements(
+    axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
+    Path(ticket_type_id): Path<Uuid>,
+) -> AppResult<Json<Vec<MovementDto>>> {
+    let rows = sqlx::query_as::<_, MovementDto>(
+        r#"select id, ticket_type_id, kind, total_delta, remaining_delta, order_id, hold_id, actor, reason, created_at
+           from inventory_movements where ticket_type_id = $1 order by created_at asc"#,
+    )
+    .bind(ticket_type_id)
+    .fetch_all(&db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/inventory/reconcile",
+    responses((status=200, body=ReconcileReport), (status=401), (status=403))
+)]
+pub async fn reconcile(
+    axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
+) -> AppResult<Json<ReconcileReport>> {
+    let discrepancies = inventory::reconcile(&db.pool).await?;
+    Ok(Json(ReconcileReport {
+        consistent: discrepancies.is_empty(),
+        discrepancies,
+    }))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/admin/ticket-types/:ticket_type_id/inventory",
+            post(adjust_inventory).get(list_movements),
+        )
+        .route("/api/admin/inventory/reconcile", get(reconcile))
+}
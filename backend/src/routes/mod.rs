@@ -1,6 +1,15 @@
+pub mod abuse;
 pub mod admin;
 pub mod auth;
 pub mod health;
+pub mod holds;
+pub mod inventory;
 pub mod orders;
+pub mod payments;
+pub mod presale;
+pub mod promo_codes;
 pub mod purchase_intents;
+pub mod queue;
+pub mod refunds;
+pub mod seats;
 pub mod seckill;
@@ -1,9 +1,18 @@
 use axum::{extract::Path, routing::{get, post}, Json, Router};
 use chrono::{DateTime, Utc};
 use serde::Serialize;
+use tracing::warn;
 use uuid::Uuid;
 
-use crate::{auth::AuthUser, db::Db, error::{AppError, AppResult}};
+use crate::{
+    auth::AuthUser,
+    db::Db,
+    error::{AppError, AppResult},
+    order_state::{self, OrderStatus},
+    payments, purchase,
+    routes::payments::PaymentDto,
+    state::AppState,
+};
 use utoipa::ToSchema;
 
 #[derive(Serialize, ToSchema, sqlx::FromRow)]
@@ -13,8 +22,11 @@ pub struct OrderDto {
     pub ticket_type_id: Uuid,
     pub qty: i32,
     pub amount_cents: i64,
-    pub status: String,
+    pub status: OrderStatus,
+    pub refunded_cents: i64,
     pub created_at: DateTime<Utc>,
+    /// Payment deadline for `CREATED` orders; unpaid orders are canceled after it.
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 #[utoipa::path(
@@ -27,7 +39,7 @@ pub async fn my_orders(
     auth: AuthUser,
 ) -> AppResult<Json<Vec<OrderDto>>> {
     let rows = sqlx::query_as::<_, OrderDto>(
-        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, created_at
+        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
            from orders
            where user_id = $1
            order by created_at desc"#,
@@ -51,7 +63,7 @@ pub async fn get_order(
     Path(order_id): Path<Uuid>,
 ) -> AppResult<Json<OrderDto>> {
     let order = sqlx::query_as::<_, OrderDto>(
-        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, created_at
+        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
            from orders where id = $1 and user_id = $2"#,
     )
     .bind(order_id)
@@ -69,17 +81,17 @@ pub async fn get_order(
     post,
     path = "/api/orders/{order_id}/pay",
     params(("order_id" = Uuid, Path, description = "Order id")),
-    responses((status=200, body=OrderDto), (status=404), (status=409), (status=401))
+    responses((status=200, body=PaymentDto, description="Payment session; the order becomes PAID once the provider confirms"), (status=404), (status=409), (status=401))
 )]
 pub async fn pay_order(
-    axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(state): axum::extract::State<AppState>,
     auth: AuthUser,
     Path(order_id): Path<Uuid>,
-) -> AppResult<Json<OrderDto>> {
-    let mut tx = db.pool.begin().await?;
+) -> AppResult<Json<PaymentDto>> {
+    let mut tx = state.db.pool.begin().await?;
 
     let order = sqlx::query_as::<_, OrderDto>(
-        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, created_at
+        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
            from orders where id = $1 and user_id = $2 for update"#,
     )
     .bind(order_id)
@@ -92,30 +104,234 @@ pub async fn pay_order(
         return Err(AppError::NotFound);
     };
 
-    if order.status != "CREATED" {
+    if let Err(e) = order.status.ensure_transition(OrderStatus::Paid) {
+        tx.rollback().await?;
+        return Err(e);
+    }
+
+    // The reaper may not have run yet; an order past its deadline is no longer payable.
+    if order.expires_at.is_some_and(|t| t <= Utc::now()) {
         tx.rollback().await?;
-        return Err(AppError::Conflict("order not payable".into()));
+        return Err(AppError::Conflict("order expired".into()));
+    }
+
+    // An attempt whose provider call never came back (e.g. the process died) is given up
+    // on, so it can't block the order.
+    sqlx::query(
+        r#"update payments set status = 'FAILED', failure_reason = $2, updated_at = now()
+           where order_id = $1 and status = 'PENDING' and provider_ref is null
+             and created_at < now() - interval '1 minute'"#,
+    )
+    .bind(order_id)
+    .bind(SESSION_NOT_OPENED)
+    .execute(&mut *tx)
+    .await?;
+
+    // Paying again while an attempt is open returns the same session.
+    if let Some(existing) = sqlx::query_as::<_, PaymentDto>(
+        r#"select id, order_id, provider, provider_ref, status, amount_cents, checkout_url, failure_reason, created_at, updated_at
+           from payments where order_id = $1 and status = 'PENDING'"#,
+    )
+    .bind(order_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    {
+        tx.commit().await?;
+        return Ok(Json(existing));
+    }
+
+    // Record the attempt and let go of the order before calling the provider, so a slow
+    // provider holds up neither cancel, the reaper nor callbacks.
+    let payment_id = Uuid::new_v4();
+    sqlx::query(
+        r#"insert into payments (id, order_id, provider, status, amount_cents)
+           values ($1,$2,$3,'PENDING',$4)"#,
+    )
+    .bind(payment_id)
+    .bind(order_id)
+    .bind(state.payments.name())
+    .bind(order.amount_cents)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let session = match state
+        .payments
+        .create_payment(payment_id, order_id, order.amount_cents)
+        .await
+    {
+        Ok(session) => session,
+        Err(e) => {
+            fail_attempt(&state.db, payment_id, SESSION_NOT_OPENED).await;
+            return Err(e.into());
+        }
+    };
+
+    // Only still-open attempts get their session; one closed meanwhile (order canceled or
+    // expired) has nothing to capture against, so its session is voided.
+    let stored = sqlx::query_as::<_, PaymentDto>(
+        r#"update payments set provider_ref = $2, checkout_url = $3, updated_at = now()
+           where id = $1 and status = 'PENDING'
+           returning id, order_id, provider, provider_ref, status, amount_cents, checkout_url, failure_reason, created_at, updated_at"#,
+    )
+    .bind(payment_id)
+    .bind(&session.provider_ref)
+    .bind(&session.checkout_url)
+    .fetch_optional(&state.db.pool)
+    .await;
+    match stored {
+        Ok(Some(payment)) => Ok(Json(payment)),
+        Ok(None) => {
+            void_session(&state, &session.provider_ref).await;
+            Err(AppError::Conflict("order is no longer payable".into()))
+        }
+        Err(e) => {
+            void_session(&state, &session.provider_ref).await;
+            fail_attempt(&state.db, payment_id, SESSION_NOT_OPENED).await;
+            Err(e.into())
+        }
+    }
+}
+
+/// `failure_reason` of attempts whose provider session couldn't be opened or stored.
+const SESSION_NOT_OPENED: &str = "payment session not opened";
+
+/// Closes an attempt `pay_order` couldn't finish. Best effort: a leftover is given up on by
+/// the next `pay_order` anyway.
+async fn fail_attempt(db: &Db, payment_id: Uuid, reason: &str) {
+    let closed = sqlx::query(
+        r#"update payments set status = 'FAILED', failure_reason = $2, updated_at = now()
+           where id = $1 and status = 'PENDING'"#,
+    )
+    .bind(payment_id)
+    .bind(reason)
+    .execute(&db.pool)
+    .await;
+    if let Err(e) = closed {
+        warn!(err = ?e, %payment_id, "closing an unfinished payment attempt failed");
+    }
+}
+
+async fn void_session(state: &AppState, provider_ref: &str) {
+    if let Err(e) = state.payments.cancel(provider_ref).await {
+        warn!(err = ?e, %provider_ref, "voiding an unstored payment session failed");
     }
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/orders/{order_id}/cancel",
+    params(("order_id" = Uuid, Path, description = "Order id")),
+    responses((status=200, body=OrderDto), (status=404), (status=409, description="Order already paid / refunded"), (status=401))
+)]
+pub async fn cancel_order(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    auth: AuthUser,
+    Path(order_id): Path<Uuid>,
+) -> AppResult<Json<OrderDto>> {
+    let mut tx = state.db.pool.begin().await?;
 
-    let updated = sqlx::query_as::<_, OrderDto>(
-        r#"update orders set status = 'PAID', paid_at = now()
-           where id = $1
-           returning id, user_id, ticket_type_id, qty, amount_cents, status, created_at"#,
+    let order = sqlx::query_as::<_, OrderDto>(
+        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
+           from orders where id = $1 and user_id = $2 for update"#,
     )
     .bind(order_id)
-    .fetch_one(&mut *tx)
+    .bind(auth.user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(order) = order else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+
+    // Cancelling twice (or after the reaper got there first) returns the canceled order.
+    if order.status == OrderStatus::Canceled {
+        tx.rollback().await?;
+        return Ok(Json(order));
+    }
+
+    order_state::transition(
+        &mut tx,
+        order_id,
+        order.status,
+        OrderStatus::Canceled,
+        &format!("user:{}", auth.user_id),
+        None,
+    )
+    .await?;
+    let grabbable = purchase::release_stock(
+        &mut tx,
+        order.ticket_type_id,
+        order.qty,
+        purchase::Holder::Order(order.id),
+    )
     .await?;
+    let closed = payments::close_pending(&mut tx, order_id).await?;
 
     tx.commit().await?;
-    Ok(Json(updated))
+    state.stock_gate.release(order.ticket_type_id, grabbable);
+    payments::void_closed(state.payments.as_ref(), closed.as_slice()).await;
+    Ok(Json(OrderDto {
+        status: OrderStatus::Canceled,
+        ..order
+    }))
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct OrderEventDto {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    /// `null` for the creation event.
+    pub from_status: Option<OrderStatus>,
+    pub to_status: OrderStatus,
+    /// Who made the change, e.g. `user:<id>`, `system:reaper`, `provider:mock`, `admin:<name>`.
+    pub actor: String,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/orders/{order_id}/history",
+    params(("order_id" = Uuid, Path, description = "Order id")),
+    responses((status=200, body=[OrderEventDto]), (status=404), (status=401))
+)]
+pub async fn order_history(
+    axum::extract::State(db): axum::extract::State<Db>,
+    auth: AuthUser,
+    Path(order_id): Path<Uuid>,
+) -> AppResult<Json<Vec<OrderEventDto>>> {
+    let owned: bool =
+        sqlx::query_scalar("select exists(select 1 from orders where id = $1 and user_id = $2)")
+            .bind(order_id)
+            .bind(auth.user_id)
+            .fetch_one(&db.pool)
+            .await?;
+    if !owned {
+        return Err(AppError::NotFound);
+    }
+
+    let rows = sqlx::query_as::<_, OrderEventDto>(
+        r#"select id, order_id, from_status, to_status, actor, reason, created_at
+           from order_events where order_id = $1
+           order by created_at asc, seq asc"#,
+    )
+    .bind(order_id)
+    .fetch_all(&db.pool)
+    .await?;
+    Ok(Json(rows))
 }
 
-pub fn router() -> Router<Db> {
+pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/orders/me", get(my_orders))
         .route("/api/orders/:order_id", get(get_order))
         .route("/api/orders/:order_id/pay", post(pay_order))
+        .route("/api/orders/:order_id/cancel", post(cancel_order))
+        .route("/api/orders/:order_id/history", get(order_history))
         // compatibility
         .route("/orders/:order_id", get(get_order))
         .route("/orders/:order_id/pay", post(pay_order))
+        .route("/orders/:order_id/cancel", post(cancel_order))
 }
@@ -0,0 +1,245 @@
+use axum::{
+    body::Bytes,
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    error::{AppError, AppResult},
+    payments::{self, PaymentOutcome},
+    state::AppState,
+};
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct PaymentDto {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub provider: String,
+    /// `null` while the provider is still opening the session.
+    pub provider_ref: Option<String>,
+    /// `PENDING`, `SUCCEEDED`, `FAILED`, or `SUCCEEDED_ORPHANED` (paid after the order was
+    /// canceled or expired; to be refunded).
+    pub status: String,
+    pub amount_cents: i64,
+    /// Where the buyer completes the payment (mock: the simulated callback endpoint).
+    pub checkout_url: Option<String>,
+    pub failure_reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+async fn load_payment(state: &AppState, payment_id: Uuid) -> AppResult<PaymentDto> {
+    sqlx::query_as::<_, PaymentDto>(
+        r#"select id, order_id, provider, provider_ref, status, amount_cents, checkout_url, failure_reason, created_at, updated_at
+           from payments where id = $1"#,
+    )
+    .bind(payment_id)
+    .fetch_optional(&state.db.pool)
+    .await?
+    .ok_or(AppError::NotFound)
+}
+
+async fn apply(state: &AppState, payment_id: Uuid, outcome: &PaymentOutcome) -> AppResult<()> {
+    let mut tx = state.db.pool.begin().await?;
+    payments::apply_outcome(&mut tx, payment_id, outcome).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/payments/{payment_id}",
+    params(("payment_id" = Uuid, Path, description = "Payment id")),
+    responses((status=200, body=PaymentDto), (status=404), (status=401))
+)]
+pub async fn get_payment(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    auth: AuthUser,
+    Path(payment_id): Path<Uuid>,
+) -> AppResult<Json<PaymentDto>> {
+    let owned: bool = sqlx::query_scalar(
+        r#"select exists(
+             select 1 from payments p join orders o on o.id = p.order_id
+             where p.id = $1 and o.user_id = $2)"#,
+    )
+    .bind(payment_id)
+    .bind(auth.user_id)
+    .fetch_one(&state.db.pool)
+    .await?;
+    if !owned {
+        return Err(AppError::NotFound);
+    }
+
+    // Pending attempts are refreshed from the provider in case a callback was missed.
+    let payment = load_payment(&state, payment_id).await?;
+    if payment.status == "PENDING" && payment.provider == state.payments.name() {
+        if let Some(provider_ref) = &payment.provider_ref {
+            let outcome = state.payments.query(provider_ref).await?;
+            apply(&state, payment_id, &outcome).await?;
+            return Ok(Json(load_payment(&state, payment_id).await?));
+        }
+    }
+
+    Ok(Json(payment))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/payments/mock/{provider_ref}/complete",
+    params(("provider_ref" = String, Path, description = "Mock provider reference")),
+    responses((status=200, body=PaymentDto), (status=202, body=PaymentDto, description="Provider did not answer (timeout mode)"), (status=404))
+)]
+pub async fn mock_complete(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    Path(provider_ref): Path<String>,
+) -> AppResult<(StatusCode, Json<PaymentDto>)> {
+    let payment_id: Uuid =
+        sqlx::query_scalar("select id from payments where provider = 'mock' and provider_ref = $1")
+            .bind(&provider_ref)
+            .fetch_optional(&state.db.pool)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+    let outcome = state.payments.confirm(&provider_ref).await?;
+    apply(&state, payment_id, &outcome).await?;
+
+    let status = match outcome {
+        PaymentOutcome::Pending => StatusCode::ACCEPTED,
+        _ => StatusCode::OK,
+    };
+    Ok((status, Json(load_payment(&state, payment_id).await?)))
+}
+
+pub const WEBHOOK_TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+#[derive(Deserialize, ToSchema)]
+pub struct WebhookEvent {
+    /// Provider-assigned id; redelivered events carry the same id.
+    pub event_id: String,
+    pub provider: String,
+    pub provider_ref: String,
+    /// `payment.succeeded` or `payment.failed`.
+    #[serde(rename = "type")]
+    pub event_type: String,
+    #[serde(default)]
+    pub failure_reason: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct WebhookAck {
+    /// `applied`, `ignored` (payment already settled) or `duplicate`.
+    pub status: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/payments/webhook",
+    request_body = WebhookEvent,
+    params(
+        ("x-webhook-timestamp" = i64, Header, description = "Unix seconds; stale timestamps are rejected"),
+        ("x-webhook-signature" = String, Header, description = "hex HMAC-SHA256 of `{timestamp}.{body}`")
+    ),
+    responses((status=200, body=WebhookAck), (status=400), (status=401, description="Bad signature / stale timestamp"), (status=404, description="Unknown payment"))
+)]
+pub async fn webhook(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> AppResult<Json<WebhookAck>> {
+    let Some(secret) = state.cfg.payment_webhook_secret.as_deref() else {
+        return Err(AppError::Unauthorized);
+    };
+
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let timestamp: i64 = header(WEBHOOK_TIMESTAMP_HEADER)
+        .and_then(|v| v.parse().ok())
+        .ok_or(AppError::Unauthorized)?;
+    if (Utc::now().timestamp() - timestamp).abs() > state.cfg.payment_webhook_tolerance_secs {
+        return Err(AppError::Unauthorized);
+    }
+    let signature = header(WEBHOOK_SIGNATURE_HEADER).ok_or(AppError::Unauthorized)?;
+    if !payments::verify_webhook(secret, timestamp, &body, signature) {
+        return Err(AppError::Unauthorized);
+    }
+
+    let event: WebhookEvent = serde_json::from_slice(&body)
+        .map_err(|e| AppError::BadRequest(format!("invalid webhook body: {e}")))?;
+    let outcome = match event.event_type.as_str() {
+        "payment.succeeded" => PaymentOutcome::Succeeded,
+        "payment.failed" => PaymentOutcome::Failed(
+            event
+                .failure_reason
+                .clone()
+                .unwrap_or_else(|| "payment failed".into()),
+        ),
+        other => return Err(AppError::BadRequest(format!("unknown event type: {other}"))),
+    };
+
+    let mut tx = state.db.pool.begin().await?;
+
+    let payment_id: Uuid =
+        sqlx::query_scalar("select id from payments where provider = $1 and provider_ref = $2")
+            .bind(&event.provider)
+            .bind(&event.provider_ref)
+            .fetch_optional(&mut *tx)
+            .await?
+            .ok_or(AppError::NotFound)?;
+
+    // Dedupe: a concurrent delivery of the same event waits here and then sees the conflict.
+    let recorded = sqlx::query(
+        r#"insert into payment_webhook_events (provider, event_id, payment_id, event_type, applied)
+           values ($1,$2,$3,$4,false)
+           on conflict (provider, event_id) do nothing"#,
+    )
+    .bind(&event.provider)
+    .bind(&event.event_id)
+    .bind(payment_id)
+    .bind(&event.event_type)
+    .execute(&mut *tx)
+    .await?;
+    if recorded.rows_affected() == 0 {
+        tx.commit().await?;
+        return Ok(Json(WebhookAck {
+            status: "duplicate".into(),
+        }));
+    }
+
+    let applied = payments::apply_outcome(&mut tx, payment_id, &outcome).await?;
+    sqlx::query(
+        "update payment_webhook_events set applied = $3 where provider = $1 and event_id = $2",
+    )
+    .bind(&event.provider)
+    .bind(&event.event_id)
+    .bind(applied)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Json(WebhookAck {
+        status: if applied { "applied" } else { "ignored" }.into(),
+    }))
+}
+
+/// `mock` mounts the unauthenticated mock completion route; only when the mock provider is
+/// active.
+pub fn router(mock: bool) -> Router<AppState> {
+    let router = Router::new()
+        .route("/api/payments/webhook", post(webhook))
+        .route("/api/payments/:payment_id", get(get_payment));
+    if mock {
+        router.route(
+            "/api/payments/mock/:provider_ref/complete",
+            post(mock_complete),
+        )
+    } else {
+        router
+    }
+}
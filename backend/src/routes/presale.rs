@@ -0,0 +1,342 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    routing::{delete, get},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgConnection;
+use uuid::Uuid;
+
+use crate::{
+    audit::{self, AuditEntry},
+    auth::AdminUser,
+    db::Db,
+    error::{AppError, AppResult},
+    presale,
+    state::AppState,
+};
+use utoipa::ToSchema;
+
+/// Most usernames one upload may carry.
+const MAX_ALLOWLIST_UPLOAD: usize = 10_000;
+/// Most codes one request may generate.
+const MAX_CODES_PER_REQUEST: i32 = 1_000;
+
+#[derive(Deserialize, ToSchema)]
+pub struct UploadAllowlistRequest {
+    pub usernames: Vec<String>,
+    /// Drop the current list first instead of adding to it.
+    #[serde(default)]
+    pub replace: bool,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AllowlistUploadResult {
+    /// Users newly put on the list.
+    pub added: u64,
+    /// Size of the list after the upload.
+    pub total: i64,
+    /// Usernames with no account; skipped.
+    pub unknown_usernames: Vec<String>,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct AllowlistEntryDto {
+    pub user_id: Uuid,
+    pub username: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct GenerateCodesRequest {
+    /// 1..=1000.
+    pub count: i32,
+    /// Presale purchases each code can unlock; defaults to 1.
+    #[serde(default = "default_max_uses")]
+    #[schema(default = 1)]
+    pub max_uses: i32,
+}
+
+fn default_max_uses() -> i32 {
+    1
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct PresaleCodeDto {
+    pub id: Uuid,
+    pub ticket_type_id: Uuid,
+    pub code: String,
+    pub max_uses: i32,
+    pub uses: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+async fn ensure_ticket_type(conn: &mut PgConnection, ticket_type_id: Uuid) -> AppResult<()> {
+    let exists: bool =
+        sqlx::query_scalar("select exists(select 1 from ticket_types where id = $1)")
+            .bind(ticket_type_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    if !exists {
+        return Err(AppError::NotFound);
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/ticket-types/{ticket_type_id}/presale/allowlist",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    request_body = UploadAllowlistRequest,
+    responses((status=200, body=AllowlistUploadResult), (status=400), (status=401), (status=403), (status=404))
+)]
+pub async fn upload_allowlist(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(ticket_type_id): Path<Uuid>,
+    Json(req): Json<UploadAllowlistRequest>,
+) -> AppResult<Json<AllowlistUploadResult>> {
+    if req.usernames.len() > MAX_ALLOWLIST_UPLOAD {
+        return Err(AppError::BadRequest(format!(
+            "at most {MAX_ALLOWLIST_UPLOAD} usernames per upload"
+        )));
+    }
+    let mut usernames: Vec<String> = req
+        .usernames
+        .iter()
+        .map(|u| u.trim().to_string())
+        .filter(|u| !u.is_empty())
+        .collect();
+    usernames.sort();
+    usernames.dedup();
+
+    let mut tx = db.pool.begin().await?;
+    if let Err(e) = ensure_ticket_type(&mut tx, ticket_type_id).await {
+        tx.rollback().await?;
+        return Err(e);
+    }
+
+    let users: Vec<(Uuid, String)> =
+        sqlx::query_as("select id, username from users where username = any($1)")
+            .bind(&usernames)
+            .fetch_all(&mut *tx)
+            .await?;
+    let unknown_usernames = usernames
+        .iter()
+        .filter(|u| !users.iter().any(|(_, name)| name == *u))
+        .cloned()
+        .collect();
+    let user_ids: Vec<Uuid> = users.iter().map(|(id, _)| *id).collect();
+
+    if req.replace {
+        sqlx::query("delete from presale_allowlist where ticket_type_id = $1")
+            .bind(ticket_type_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let added = sqlx::query(
+        r#"insert into presale_allowlist (ticket_type_id, user_id)
+           select $1, unnest($2::uuid[])
+           on conflict do nothing"#,
+    )
+    .bind(ticket_type_id)
+    .bind(&user_ids)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    let total: i64 =
+        sqlx::query_scalar("select count(*) from presale_allowlist where ticket_type_id = $1")
+            .bind(ticket_type_id)
+            .fetch_one(&mut *tx)
+            .await?;
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "presale.allowlist_upload",
+            entity_type: "ticket_type",
+            entity_id: ticket_type_id,
+            forced: false,
+            details: json!({ "replace": req.replace, "added": added, "total": total }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(Json(AllowlistUploadResult {
+        added,
+        total,
+        unknown_usernames,
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/ticket-types/{ticket_type_id}/presale/allowlist",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    responses((status=200, body=[AllowlistEntryDto]), (status=401), (status=403))
+)]
+pub async fn list_allowlist(
+    axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
+    Path(ticket_type_id): Path<Uuid>,
+) -> AppResult<Json<Vec<AllowlistEntryDto>>> {
+    let rows = sqlx::query_as::<_, AllowlistEntryDto>(
+        r#"select a.user_id, u.username, a.created_at
+           from presale_allowlist a join users u on u.id = a.user_id
+           where a.ticket_type_id = $1
+           order by u.username"#,
+    )
+    .bind(ticket_type_id)
+    .fetch_all(&db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/ticket-types/{ticket_type_id}/presale/allowlist/{user_id}",
+    params(
+        ("ticket_type_id" = Uuid, Path, description = "Ticket type id"),
+        ("user_id" = Uuid, Path, description = "User id")
+    ),
+    responses((status=204), (status=401), (status=403), (status=404))
+)]
+pub async fn remove_from_allowlist(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path((ticket_type_id, user_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    let mut tx = db.pool.begin().await?;
+    let removed =
+        sqlx::query("delete from presale_allowlist where ticket_type_id = $1 and user_id = $2")
+            .bind(ticket_type_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+    if removed.rows_affected() == 0 {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    }
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "presale.allowlist_remove",
+            entity_type: "ticket_type",
+            entity_id: ticket_type_id,
+            forced: false,
+            details: json!({ "user_id": user_id }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/ticket-types/{ticket_type_id}/presale/codes",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    request_body = GenerateCodesRequest,
+    responses((status=200, body=[PresaleCodeDto], description="The new codes"), (status=400), (status=401), (status=403), (status=404))
+)]
+pub async fn generate_codes(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(ticket_type_id): Path<Uuid>,
+    Json(req): Json<GenerateCodesRequest>,
+) -> AppResult<Json<Vec<PresaleCodeDto>>> {
+    if !(1..=MAX_CODES_PER_REQUEST).contains(&req.count) {
+        return Err(AppError::BadRequest(format!(
+            "count must be in 1..={MAX_CODES_PER_REQUEST}"
+        )));
+    }
+    if req.max_uses <= 0 {
+        return Err(AppError::BadRequest("max_uses must be > 0".into()));
+    }
+
+    let mut tx = db.pool.begin().await?;
+    if let Err(e) = ensure_ticket_type(&mut tx, ticket_type_id).await {
+        tx.rollback().await?;
+        return Err(e);
+    }
+
+    let mut codes = Vec::with_capacity(req.count as usize);
+    while codes.len() < req.count as usize {
+        // A collision just draws again.
+        let code = sqlx::query_as::<_, PresaleCodeDto>(
+            r#"insert into presale_codes (id, ticket_type_id, code, max_uses)
+               values ($1,$2,$3,$4)
+               on conflict (code) do nothing
+               returning id, ticket_type_id, code, max_uses, uses, created_at"#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(ticket_type_id)
+        .bind(presale::generate_code())
+        .bind(req.max_uses)
+        .fetch_optional(&mut *tx)
+        .await?;
+        codes.extend(code);
+    }
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "presale.codes_generate",
+            entity_type: "ticket_type",
+            entity_id: ticket_type_id,
+            forced: false,
+            details: json!({ "count": req.count, "max_uses": req.max_uses }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(Json(codes))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/ticket-types/{ticket_type_id}/presale/codes",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    responses((status=200, body=[PresaleCodeDto]), (status=401), (status=403))
+)]
+pub async fn list_codes(
+    axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
+    Path(ticket_type_id): Path<Uuid>,
+) -> AppResult<Json<Vec<PresaleCodeDto>>> {
+    let rows = sqlx::query_as::<_, PresaleCodeDto>(
+        r#"select id, ticket_type_id, code, max_uses, uses, created_at
+           from presale_codes where ticket_type_id = $1
+           order by created_at, code"#,
+    )
+    .bind(ticket_type_id)
+    .fetch_all(&db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/admin/ticket-types/:ticket_type_id/presale/allowlist",
+            get(list_allowlist).post(upload_allowlist),
+        )
+        .route(
+            "/api/admin/ticket-types/:ticket_type_id/presale/allowlist/:user_id",
+            delete(remove_from_allowlist),
+        )
+        .route(
+            "/api/admin/ticket-types/:ticket_type_id/presale/codes",
+            get(list_codes).post(generate_codes),
+        )
+}
@@ -0,0 +1,387 @@
+use axum::{
+    extract::Path,
+    http::StatusCode,
+    routing::{get, patch},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::{
+    audit::{self, AuditEntry},
+    auth::AdminUser,
+    db::Db,
+    error::{AppError, AppResult},
+    routes::admin::present,
+    state::AppState,
+};
+use utoipa::ToSchema;
+
+/// Longest accepted code.
+const MAX_CODE_LEN: usize = 64;
+
+const PROMO_CODE_COLUMNS: &str = "id, code, kind, percent_off, amount_off_cents, max_uses, uses, \
+     once_per_user, valid_from, valid_until, event_ids, ticket_type_ids, active, created_at, \
+     updated_at";
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreatePromoCodeRequest {
+    /// Matched case-insensitively; unique.
+    pub code: String,
+    /// `percent` (with `percent_off`) or `fixed` (with `amount_off_cents`, per order).
+    pub kind: String,
+    #[serde(default)]
+    pub percent_off: Option<i32>,
+    #[serde(default)]
+    pub amount_off_cents: Option<i64>,
+    /// Omit for unlimited uses.
+    #[serde(default)]
+    pub max_uses: Option<i32>,
+    /// Each user may have at most one order (not canceled) with the code.
+    #[serde(default)]
+    pub once_per_user: bool,
+    #[serde(default)]
+    pub valid_from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub valid_until: Option<DateTime<Utc>>,
+    /// Only these events; empty for any.
+    #[serde(default)]
+    pub event_ids: Vec<Uuid>,
+    /// Only these ticket types; empty for any.
+    #[serde(default)]
+    pub ticket_type_ids: Vec<Uuid>,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct PromoCodeDto {
+    pub id: Uuid,
+    pub code: String,
+    pub kind: String,
+    pub percent_off: Option<i32>,
+    pub amount_off_cents: Option<i64>,
+    pub max_uses: Option<i32>,
+    /// Orders using the code, not counting canceled ones.
+    pub uses: i32,
+    pub once_per_user: bool,
+    pub valid_from: Option<DateTime<Utc>>,
+    pub valid_until: Option<DateTime<Utc>>,
+    pub event_ids: Vec<Uuid>,
+    pub ticket_type_ids: Vec<Uuid>,
+    pub active: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Everything about a code an admin can change after creating it.
+struct PromoTerms {
+    percent_off: Option<i32>,
+    amount_off_cents: Option<i64>,
+    max_uses: Option<i32>,
+    once_per_user: bool,
+    valid_from: Option<DateTime<Utc>>,
+    valid_until: Option<DateTime<Utc>>,
+    event_ids: Vec<Uuid>,
+    ticket_type_ids: Vec<Uuid>,
+    active: bool,
+}
+
+fn validate_promo_code(kind: &str, t: &PromoTerms) -> AppResult<()> {
+    match (kind, t.percent_off, t.amount_off_cents) {
+        ("percent", Some(pct), None) if (1..=100).contains(&pct) => {}
+        ("percent", ..) => {
+            return Err(AppError::BadRequest(
+                "percent codes need percent_off in 1..=100 and no amount_off_cents".into(),
+            ))
+        }
+        ("fixed", None, Some(amount)) if amount > 0 => {}
+        ("fixed", ..) => {
+            return Err(AppError::BadRequest(
+                "fixed codes need amount_off_cents > 0 and no percent_off".into(),
+            ))
+        }
+        _ => return Err(AppError::BadRequest("kind must be percent or fixed".into())),
+    }
+    if t.max_uses.is_some_and(|m| m <= 0) {
+        return Err(AppError::BadRequest("max_uses must be > 0".into()));
+    }
+    if let (Some(from), Some(until)) = (t.valid_from, t.valid_until) {
+        if from >= until {
+            return Err(AppError::BadRequest(
+                "valid_from must be before valid_until".into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn map_code_conflict(e: sqlx::Error) -> AppError {
+    if let Some(db_err) = e.as_database_error() {
+        if db_err.constraint() == Some("uq_promo_codes_code") {
+            return AppError::Conflict("promo code already exists".into());
+        }
+    }
+    AppError::Db(e)
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/promo-codes",
+    request_body = CreatePromoCodeRequest,
+    responses((status=200, body=PromoCodeDto), (status=400), (status=401), (status=403), (status=409, description="Code already exists"))
+)]
+pub async fn create_promo_code(
+    axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
+    Json(req): Json<CreatePromoCodeRequest>,
+) -> AppResult<Json<PromoCodeDto>> {
+    let code = req.code.trim();
+    if code.is_empty() || code.len() > MAX_CODE_LEN || code.contains(char::is_whitespace) {
+        return Err(AppError::BadRequest(format!(
+            "code must be 1..={MAX_CODE_LEN} characters without spaces"
+        )));
+    }
+    let terms = PromoTerms {
+        percent_off: req.percent_off,
+        amount_off_cents: req.amount_off_cents,
+        max_uses: req.max_uses,
+        once_per_user: req.once_per_user,
+        valid_from: req.valid_from,
+        valid_until: req.valid_until,
+        event_ids: req.event_ids,
+        ticket_type_ids: req.ticket_type_ids,
+        active: true,
+    };
+    validate_promo_code(&req.kind, &terms)?;
+
+    let rec = sqlx::query_as::<_, PromoCodeDto>(&format!(
+        r#"insert into promo_codes
+             (id, code, kind, percent_off, amount_off_cents, max_uses, once_per_user,
+              valid_from, valid_until, event_ids, ticket_type_ids)
+           values ($1,$2,$3,$4,$5,$6,$7,$8,$9,$10,$11)
+           returning {PROMO_CODE_COLUMNS}"#
+    ))
+    .bind(Uuid::new_v4())
+    .bind(code)
+    .bind(&req.kind)
+    .bind(terms.percent_off)
+    .bind(terms.amount_off_cents)
+    .bind(terms.max_uses)
+    .bind(terms.once_per_user)
+    .bind(terms.valid_from)
+    .bind(terms.valid_until)
+    .bind(&terms.event_ids)
+    .bind(&terms.ticket_type_ids)
+    .fetch_one(&db.pool)
+    .await
+    .map_err(map_code_conflict)?;
+
+    Ok(Json(rec))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/promo-codes",
+    responses((status=200, body=[PromoCodeDto]), (status=401), (status=403))
+)]
+pub async fn list_promo_codes(
+    axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
+) -> AppResult<Json<Vec<PromoCodeDto>>> {
+    let rows = sqlx::query_as::<_, PromoCodeDto>(&format!(
+        "select {PROMO_CODE_COLUMNS} from promo_codes order by created_at desc"
+    ))
+    .fetch_all(&db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct UpdatePromoCodeRequest {
+    #[serde(default)]
+    pub percent_off: Option<i32>,
+    #[serde(default)]
+    pub amount_off_cents: Option<i64>,
+    /// `null` removes the cap; can't go below the current uses.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<i32>)]
+    pub max_uses: Option<Option<i32>>,
+    #[serde(default)]
+    pub once_per_user: Option<bool>,
+    /// `null` removes the bound.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<DateTime<Utc>>)]
+    pub valid_from: Option<Option<DateTime<Utc>>>,
+    /// `null` removes the bound.
+    #[serde(default, deserialize_with = "present")]
+    #[schema(value_type = Option<DateTime<Utc>>)]
+    pub valid_until: Option<Option<DateTime<Utc>>>,
+    #[serde(default)]
+    pub event_ids: Option<Vec<Uuid>>,
+    #[serde(default)]
+    pub ticket_type_ids: Option<Vec<Uuid>>,
+    /// `false` stops new redemptions; orders already placed keep their discount.
+    #[serde(default)]
+    pub active: Option<bool>,
+}
+
+async fn lock_promo_code(
+    conn: &mut sqlx::PgConnection,
+    promo_code_id: Uuid,
+) -> Result<Option<PromoCodeDto>, sqlx::Error> {
+    sqlx::query_as::<_, PromoCodeDto>(&format!(
+        "select {PROMO_CODE_COLUMNS} from promo_codes where id = $1 for update"
+    ))
+    .bind(promo_code_id)
+    .fetch_optional(&mut *conn)
+    .await
+}
+
+#[utoipa::path(
+    patch,
+    path = "/api/admin/promo-codes/{promo_code_id}",
+    params(("promo_code_id" = Uuid, Path, description = "Promo code id")),
+    request_body = UpdatePromoCodeRequest,
+    responses((status=200, body=PromoCodeDto), (status=400), (status=401), (status=403), (status=404), (status=409, description="max_uses below current uses"))
+)]
+pub async fn update_promo_code(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(promo_code_id): Path<Uuid>,
+    Json(req): Json<UpdatePromoCodeRequest>,
+) -> AppResult<Json<PromoCodeDto>> {
+    let mut tx = db.pool.begin().await?;
+
+    // Same row lock as a redemption, so `uses` can't move under the max_uses check.
+    let Some(before) = lock_promo_code(&mut tx, promo_code_id).await? else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+
+    let terms = PromoTerms {
+        percent_off: req.percent_off.or(before.percent_off),
+        amount_off_cents: req.amount_off_cents.or(before.amount_off_cents),
+        max_uses: req.max_uses.unwrap_or(before.max_uses),
+        once_per_user: req.once_per_user.unwrap_or(before.once_per_user),
+        valid_from: req.valid_from.unwrap_or(before.valid_from),
+        valid_until: req.valid_until.unwrap_or(before.valid_until),
+        event_ids: req.event_ids.unwrap_or_else(|| before.event_ids.clone()),
+        ticket_type_ids: req
+            .ticket_type_ids
+            .unwrap_or_else(|| before.ticket_type_ids.clone()),
+        active: req.active.unwrap_or(before.active),
+    };
+    if let Err(e) = validate_promo_code(&before.kind, &terms) {
+        tx.rollback().await?;
+        return Err(e);
+    }
+    if terms.max_uses.is_some_and(|m| m < before.uses) {
+        tx.rollback().await?;
+        return Err(AppError::Conflict(format!(
+            "code already has {} uses",
+            before.uses
+        )));
+    }
+
+    let after = sqlx::query_as::<_, PromoCodeDto>(&format!(
+        r#"update promo_codes
+           set percent_off = $2, amount_off_cents = $3, max_uses = $4, once_per_user = $5,
+               valid_from = $6, valid_until = $7, event_ids = $8, ticket_type_ids = $9,
+               active = $10, updated_at = now()
+           where id = $1
+           returning {PROMO_CODE_COLUMNS}"#
+    ))
+    .bind(promo_code_id)
+    .bind(terms.percent_off)
+    .bind(terms.amount_off_cents)
+    .bind(terms.max_uses)
+    .bind(terms.once_per_user)
+    .bind(terms.valid_from)
+    .bind(terms.valid_until)
+    .bind(&terms.event_ids)
+    .bind(&terms.ticket_type_ids)
+    .bind(terms.active)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "promo_code.update",
+            entity_type: "promo_code",
+            entity_id: promo_code_id,
+            forced: false,
+            details: json!({ "before": before, "after": after }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(Json(after))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/admin/promo-codes/{promo_code_id}",
+    params(("promo_code_id" = Uuid, Path, description = "Promo code id")),
+    responses((status=204), (status=401), (status=403), (status=404), (status=409, description="Code was redeemed; deactivate it instead"))
+)]
+pub async fn delete_promo_code(
+    axum::extract::State(db): axum::extract::State<Db>,
+    AdminUser(admin): AdminUser,
+    Path(promo_code_id): Path<Uuid>,
+) -> AppResult<StatusCode> {
+    let mut tx = db.pool.begin().await?;
+
+    let Some(promo_code) = lock_promo_code(&mut tx, promo_code_id).await? else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+    let redeemed: bool = sqlx::query_scalar(
+        "select exists(select 1 from promo_redemptions where promo_code_id = $1)",
+    )
+    .bind(promo_code_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if redeemed {
+        tx.rollback().await?;
+        return Err(AppError::Conflict(
+            "promo code was redeemed; deactivate it instead".into(),
+        ));
+    }
+
+    sqlx::query("delete from promo_codes where id = $1")
+        .bind(promo_code_id)
+        .execute(&mut *tx)
+        .await?;
+
+    audit::record(
+        &mut tx,
+        AuditEntry {
+            actor: &admin.username,
+            action: "promo_code.delete",
+            entity_type: "promo_code",
+            entity_id: promo_code_id,
+            forced: false,
+            details: json!({ "before": promo_code }),
+        },
+    )
+    .await?;
+
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/api/admin/promo-codes",
+            get(list_promo_codes).post(create_promo_code),
+        )
+        .route(
+            "/api/admin/promo-codes/:promo_code_id",
+            patch(update_promo_code).delete(delete_promo_code),
+        )
+}
@@ -1,14 +1,48 @@
-use axum::{routing::{get, post}, Json, Router};
+use axum::{
+    extract::Path,
+    routing::{get, post},
+    Json, Router,
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::{auth::AuthUser, db::Db, error::{AppError, AppResult}};
+use crate::{
+    auth::AuthUser,
+    db::Db,
+    error::{AppError, AppResult},
+    intent_wakeup, purchase,
+    state::AppState,
+    waiting_room,
+};
+
+/// Most fallback ticket types one intent may list.
+const MAX_FALLBACKS: usize = 5;
+
+const INTENT_COLUMNS: &str = "id, user_id, ticket_type_id, qty, status, order_id, last_error, \
+     attempts, next_attempt_at, expires_at, max_price_cents, fallback_ticket_type_ids, \
+     created_at, updated_at";
 
 #[derive(Deserialize, ToSchema)]
 pub struct CreateIntentRequest {
     pub ticket_type_id: Uuid,
+    #[serde(default = "default_qty")]
+    #[schema(default = 1)]
+    pub qty: i32,
+    /// Give up (status `EXPIRED`) if nothing was bought by then.
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    /// Don't buy a ticket type whose unit price is higher.
+    #[serde(default)]
+    pub max_price_cents: Option<i64>,
+    /// Tried in order when `ticket_type_id` can't be bought (sold out, too expensive, ...).
+    #[serde(default)]
+    pub fallback_ticket_type_ids: Vec<Uuid>,
+}
+
+fn default_qty() -> i32 {
+    1
 }
 
 #[derive(Serialize, ToSchema, sqlx::FromRow)]
@@ -16,9 +50,20 @@ pub struct IntentDto {
     pub id: Uuid,
     pub user_id: Uuid,
     pub ticket_type_id: Uuid,
+    pub qty: i32,
+    /// `ACTIVE`, `FULFILLED`, `CANCELED`, `EXPIRED` or `FAILED` (gave up after
+    /// `INTENT_MAX_ATTEMPTS`).
     pub status: String,
+    /// The order's ticket type may be one of the fallbacks.
     pub order_id: Option<Uuid>,
     pub last_error: Option<String>,
+    /// Failed attempts so far.
+    pub attempts: i32,
+    /// When the worker tries again while `ACTIVE`.
+    pub next_attempt_at: DateTime<Utc>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub max_price_cents: Option<i64>,
+    pub fallback_ticket_type_ids: Vec<Uuid>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -27,26 +72,63 @@ pub struct IntentDto {
     post,
     path = "/api/purchase-intents",
     request_body = CreateIntentRequest,
-    responses((status=200, body=IntentDto), (status=401), (status=409))
+    responses((status=200, body=IntentDto), (status=400), (status=401), (status=404), (status=409))
 )]
 pub async fn create_intent(
     axum::extract::State(db): axum::extract::State<Db>,
     auth: AuthUser,
     Json(req): Json<CreateIntentRequest>,
 ) -> AppResult<Json<IntentDto>> {
+    if req.expires_at.is_some_and(|t| t <= Utc::now()) {
+        return Err(AppError::BadRequest(
+            "expires_at must be in the future".into(),
+        ));
+    }
+    if req.max_price_cents.is_some_and(|p| p < 0) {
+        return Err(AppError::BadRequest("max_price_cents must be >= 0".into()));
+    }
+    let mut fallbacks = Vec::with_capacity(req.fallback_ticket_type_ids.len());
+    for id in req.fallback_ticket_type_ids {
+        if id != req.ticket_type_id && !fallbacks.contains(&id) {
+            fallbacks.push(id);
+        }
+    }
+    if fallbacks.len() > MAX_FALLBACKS {
+        return Err(AppError::BadRequest(format!(
+            "at most {MAX_FALLBACKS} fallback ticket types"
+        )));
+    }
+
+    let mut conn = db.pool.acquire().await?;
+    for ticket_type_id in std::iter::once(req.ticket_type_id).chain(fallbacks.iter().copied()) {
+        purchase::check_order_qty(&mut conn, ticket_type_id, req.qty).await?;
+        // The worker would buy past everyone in line.
+        if waiting_room::is_queued(&mut conn, ticket_type_id).await? {
+            return Err(AppError::Conflict(
+                "ticket type sells through the waiting room".into(),
+            ));
+        }
+    }
+
     let id = Uuid::new_v4();
     let idem = format!("intent:{}", id);
 
-    let rec = sqlx::query_as::<_, IntentDto>(
-        r#"insert into purchase_intents (id, user_id, ticket_type_id, status, idempotency_key)
-           values ($1,$2,$3,'ACTIVE',$4)
-           returning id, user_id, ticket_type_id, status, order_id, last_error, created_at, updated_at"#,
-    )
+    let rec = sqlx::query_as::<_, IntentDto>(&format!(
+        r#"insert into purchase_intents
+             (id, user_id, ticket_type_id, qty, status, idempotency_key,
+              expires_at, max_price_cents, fallback_ticket_type_ids)
+           values ($1,$2,$3,$4,'ACTIVE',$5,$6,$7,$8)
+           returning {INTENT_COLUMNS}"#
+    ))
     .bind(id)
     .bind(auth.user_id)
     .bind(req.ticket_type_id)
+    .bind(req.qty)
     .bind(idem)
-    .fetch_one(&db.pool)
+    .bind(req.expires_at)
+    .bind(req.max_price_cents)
+    .bind(&fallbacks)
+    .fetch_one(&mut *conn)
     .await
     .map_err(|e| {
         if let Some(db_err) = e.as_database_error() {
@@ -56,6 +138,7 @@ pub async fn create_intent(
         }
         AppError::Db(e)
     })?;
+    intent_wakeup::wake(&mut conn, req.ticket_type_id).await?;
 
     Ok(Json(rec))
 }
@@ -69,10 +152,10 @@ pub async fn my_intents(
     axum::extract::State(db): axum::extract::State<Db>,
     auth: AuthUser,
 ) -> AppResult<Json<Vec<IntentDto>>> {
-    let rows = sqlx::query_as::<_, IntentDto>(
-        r#"select id, user_id, ticket_type_id, status, order_id, last_error, created_at, updated_at
-           from purchase_intents where user_id=$1 order by created_at desc"#,
-    )
+    let rows = sqlx::query_as::<_, IntentDto>(&format!(
+        r#"select {INTENT_COLUMNS}
+           from purchase_intents where user_id=$1 order by created_at desc"#
+    ))
     .bind(auth.user_id)
     .fetch_all(&db.pool)
     .await?;
@@ -80,8 +163,59 @@ pub async fn my_intents(
     Ok(Json(rows))
 }
 
-pub fn router() -> Router<Db> {
+/// Stops the worker from buying. Canceling twice is a no-op; an intent that already bought
+/// (or gave up) can't be canceled — cancel its order instead.
+#[utoipa::path(
+    post,
+    path = "/api/purchase-intents/{intent_id}/cancel",
+    params(("intent_id" = Uuid, Path, description = "Intent id")),
+    responses((status=200, body=IntentDto), (status=401), (status=404), (status=409, description="Already fulfilled, expired or failed"))
+)]
+pub async fn cancel_intent(
+    axum::extract::State(db): axum::extract::State<Db>,
+    auth: AuthUser,
+    Path(intent_id): Path<Uuid>,
+) -> AppResult<Json<IntentDto>> {
+    let mut tx = db.pool.begin().await?;
+
+    // Waits for an attempt in progress, so the outcome is final either way.
+    let status: Option<String> = sqlx::query_scalar(
+        "select status from purchase_intents where id = $1 and user_id = $2 for update",
+    )
+    .bind(intent_id)
+    .bind(auth.user_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(status) = status else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+    if status != "ACTIVE" && status != "CANCELED" {
+        tx.rollback().await?;
+        return Err(AppError::Conflict(format!("intent is {status}")));
+    }
+
+    let rec = sqlx::query_as::<_, IntentDto>(&format!(
+        r#"update purchase_intents
+           set status = 'CANCELED', locked_by = null, locked_until = null,
+               updated_at = case when status = 'CANCELED' then updated_at else now() end
+           where id = $1
+           returning {INTENT_COLUMNS}"#
+    ))
+    .bind(intent_id)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Json(rec))
+}
+
+pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/purchase-intents", post(create_intent))
         .route("/api/purchase-intents/me", get(my_intents))
+        .route(
+            "/api/purchase-intents/:intent_id/cancel",
+            post(cancel_intent),
+        )
 }
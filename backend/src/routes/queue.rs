@@ -0,0 +1,148 @@
+use std::{convert::Infallible, time::Duration};
+
+use axum::{
+    extract::Path,
+    response::sse::{Event, KeepAlive, Sse},
+    routing::{get, post},
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use futures_util::{stream, Stream};
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    error::{AppError, AppResult},
+    state::AppState,
+    waiting_room::{self, QueueState, QueueStatus},
+};
+
+/// How often `/stream` re-reads the queue.
+const STREAM_INTERVAL: Duration = Duration::from_secs(1);
+
+#[utoipa::path(
+    post,
+    path = "/api/queue/{ticket_type_id}/join",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    responses((status=200, body=QueueStatus), (status=401), (status=404), (status=409, description="No waiting room / sale ended"))
+)]
+pub async fn join_queue(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    auth: AuthUser,
+    Path(ticket_type_id): Path<Uuid>,
+) -> AppResult<Json<QueueStatus>> {
+    let mut tx = state.db.pool.begin().await?;
+
+    let row: Option<(bool, DateTime<Utc>)> =
+        sqlx::query_as("select queue_enabled, sale_ends_at from ticket_types where id = $1")
+            .bind(ticket_type_id)
+            .fetch_optional(&mut *tx)
+            .await?;
+    let Some((queue_enabled, sale_ends_at)) = row else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+    if !queue_enabled {
+        tx.rollback().await?;
+        return Err(AppError::Conflict("ticket type has no waiting room".into()));
+    }
+    if sale_ends_at <= Utc::now() {
+        tx.rollback().await?;
+        return Err(AppError::Conflict("sale has ended".into()));
+    }
+
+    waiting_room::join(&mut tx, auth.user_id, ticket_type_id).await?;
+    tx.commit().await?;
+
+    let status = waiting_room::status(
+        &state.db.pool,
+        &state.cfg.jwt_secret,
+        auth.user_id,
+        ticket_type_id,
+    )
+    .await?
+    .ok_or(AppError::NotFound)?;
+    Ok(Json(status))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/queue/{ticket_type_id}",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    responses((status=200, body=QueueStatus), (status=401), (status=404, description="Not in this queue"))
+)]
+pub async fn queue_status(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    auth: AuthUser,
+    Path(ticket_type_id): Path<Uuid>,
+) -> AppResult<Json<QueueStatus>> {
+    let status = waiting_room::status(
+        &state.db.pool,
+        &state.cfg.jwt_secret,
+        auth.user_id,
+        ticket_type_id,
+    )
+    .await?
+    .ok_or(AppError::NotFound)?;
+    Ok(Json(status))
+}
+
+/// Same status as `GET /api/queue/{ticket_type_id}`, pushed as server-sent events every
+/// second. The stream ends after the first `admitted` (or other final) status.
+#[utoipa::path(
+    get,
+    path = "/api/queue/{ticket_type_id}/stream",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    responses((status=200, description="`text/event-stream` of QueueStatus"), (status=401), (status=404, description="Not in this queue"))
+)]
+pub async fn queue_stream(
+    axum::extract::State(state): axum::extract::State<AppState>,
+    auth: AuthUser,
+    Path(ticket_type_id): Path<Uuid>,
+) -> AppResult<Sse<impl Stream<Item = Result<Event, Infallible>>>> {
+    // Fail fast with a 404 rather than an empty stream.
+    waiting_room::status(
+        &state.db.pool,
+        &state.cfg.jwt_secret,
+        auth.user_id,
+        ticket_type_id,
+    )
+    .await?
+    .ok_or(AppError::NotFound)?;
+
+    // `None` once a final status has been sent; the flag skips the wait before the first one.
+    let events = stream::unfold((Some(state), true), move |(state, first)| async move {
+        let state = state?;
+        if !first {
+            tokio::time::sleep(STREAM_INTERVAL).await;
+        }
+        let status = match waiting_room::status(
+            &state.db.pool,
+            &state.cfg.jwt_secret,
+            auth.user_id,
+            ticket_type_id,
+        )
+        .await
+        {
+            Ok(Some(status)) => status,
+            Ok(None) => return None,
+            Err(e) => {
+                tracing::warn!(err = ?e, "queue stream read failed");
+                return None;
+            }
+        };
+        let event = Event::default()
+            .json_data(&status)
+            .expect("queue status serializes");
+        let next = (status.state == QueueState::Waiting).then_some(state);
+        Some((Ok(event), (next, false)))
+    });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/queue/:ticket_type_id", get(queue_status))
+        .route("/api/queue/:ticket_type_id/join", post(join_queue))
+        .route("/api/queue/:ticket_type_id/stream", get(queue_stream))
+}
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use axum::{extract::Path, routing::post, Json, Router};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    auth::AdminUser,
+    db::Db,
+    error::{AppError, AppResult},
+    order_state::{self, OrderStatus},
+    purchase,
+    routes::orders::OrderDto,
+    state::AppState,
+    stock_gate::StockGate,
+};
+
+#[derive(Deserialize, ToSchema)]
+pub struct CreateRefundRequest {
+    /// Amount to refund; omit to refund everything not yet refunded. Orders paid at 0 are
+    /// refunded for 0.
+    #[serde(default)]
+    pub amount_cents: Option<i64>,
+    pub reason: String,
+    /// Return the order's tickets to inventory. Only allowed when the refund completes the order.
+    #[serde(default)]
+    pub restock: bool,
+}
+
+#[derive(Serialize, ToSchema, sqlx::FromRow)]
+pub struct RefundDto {
+    pub id: Uuid,
+    pub order_id: Uuid,
+    pub amount_cents: i64,
+    pub reason: String,
+    pub actor: String,
+    pub restocked_qty: i32,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RefundResponse {
+    pub refund: RefundDto,
+    pub order: OrderDto,
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/admin/orders/{order_id}/refunds",
+    params(("order_id" = Uuid, Path, description = "Order id")),
+    request_body = CreateRefundRequest,
+    responses((status=200, body=RefundResponse), (status=400), (status=401), (status=403), (status=404), (status=409, description="Order not paid / already refunded"))
+)]
+pub async fn create_refund(
+    axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(stock_gate): axum::extract::State<Arc<StockGate>>,
+    AdminUser(admin): AdminUser,
+    Path(order_id): Path<Uuid>,
+    Json(req): Json<CreateRefundRequest>,
+) -> AppResult<Json<RefundResponse>> {
+    let reason = req.reason.trim().to_string();
+    // The refund is attributed to the authenticated admin.
+    let actor = admin.username;
+    if reason.is_empty() {
+        return Err(AppError::BadRequest("reason required".into()));
+    }
+
+    let mut tx = db.pool.begin().await?;
+
+    // Same row lock as pay/cancel, so refunds and cancellations on one order serialize.
+    let order = sqlx::query_as::<_, OrderDto>(
+        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
+           from orders where id = $1 for update"#,
+    )
+    .bind(order_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let Some(order) = order else {
+        tx.rollback().await?;
+        return Err(AppError::NotFound);
+    };
+
+    match order.status {
+        OrderStatus::Paid => {}
+        OrderStatus::Refunded => {
+            tx.rollback().await?;
+            return Err(AppError::Conflict("order already fully refunded".into()));
+        }
+        from => {
+            tx.rollback().await?;
+            return Err(AppError::InvalidTransition {
+                from,
+                to: OrderStatus::Refunded,
+            });
+        }
+    }
+
+    let refundable = order.amount_cents - order.refunded_cents;
+    let amount = req.amount_cents.unwrap_or(refundable);
+    // A paid order that cost nothing is refunded for 0, so it can still be closed and restocked.
+    let min = refundable.min(1);
+    if amount < min || amount > refundable {
+        tx.rollback().await?;
+        return Err(AppError::BadRequest(format!(
+            "amount_cents must be in {min}..={refundable}"
+        )));
+    }
+
+    let completes = amount == refundable;
+    if req.restock && !completes {
+        tx.rollback().await?;
+        return Err(AppError::BadRequest(
+            "restock requires a full refund".into(),
+        ));
+    }
+    let restocked_qty = if req.restock { order.qty } else { 0 };
+
+    let refund = sqlx::query_as::<_, RefundDto>(
+        r#"insert into refunds (id, order_id, amount_cents, reason, actor, restocked_qty)
+           values ($1,$2,$3,$4,$5,$6)
+           returning id, order_id, amount_cents, reason, actor, restocked_qty, created_at"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(order_id)
+    .bind(amount)
+    .bind(&reason)
+    .bind(&actor)
+    .bind(restocked_qty)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if completes {
+        order_state::transition(
+            &mut tx,
+            order_id,
+            OrderStatus::Paid,
+            OrderStatus::Refunded,
+            &format!("admin:{actor}"),
+            Some(&reason),
+        )
+        .await?;
+    }
+
+    let order = sqlx::query_as::<_, OrderDto>(
+        r#"update orders
+           set refunded_cents = refunded_cents + $2
+           where id = $1
+           returning id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at"#,
+    )
+    .bind(order_id)
+    .bind(amount)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    let mut grabbable = 0;
+    if restocked_qty > 0 {
+        grabbable = purchase::release_stock(
+            &mut tx,
+            order.ticket_type_id,
+            restocked_qty,
+            purchase::Holder::Order(order_id),
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+    stock_gate.release(order.ticket_type_id, grabbable);
+    Ok(Json(RefundResponse { refund, order }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/orders/{order_id}/refunds",
+    params(("order_id" = Uuid, Path, description = "Order id")),
+    responses((status=200, body=[RefundDto]), (status=401), (status=403))
+)]
+pub async fn list_refunds(
+    axum::extract::State(db): axum::extract::State<Db>,
+    _admin: AdminUser,
+    Path(order_id): Path<Uuid>,
+) -> AppResult<Json<Vec<RefundDto>>> {
+    let rows = sqlx::query_as::<_, RefundDto>(
+        r#"select id, order_id, amount_cents, reason, actor, restocked_qty, created_at
+           from refunds where order_id = $1 order by created_at asc"#,
+    )
+    .bind(order_id)
+    .fetch_all(&db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new().route(
+        "/api/admin/orders/:order_id/refunds",
+        post(create_refund).get(list_refunds),
+    )
+}
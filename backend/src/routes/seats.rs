@@ -0,0 +1,70 @@
+use axum::{extract::Path, routing::get, Json, Router};
+use uuid::Uuid;
+
+use crate::{
+    auth::AuthUser,
+    db::Db,
+    error::{AppError, AppResult},
+    seats::{self, SeatDto},
+    state::AppState,
+};
+
+#[utoipa::path(
+    get,
+    path = "/api/ticket-types/{ticket_type_id}/seats",
+    params(("ticket_type_id" = Uuid, Path, description = "Ticket type id")),
+    responses((status=200, body=[SeatDto], description="Seat map; empty for general admission"), (status=404))
+)]
+pub async fn seat_map(
+    axum::extract::State(db): axum::extract::State<Db>,
+    Path(ticket_type_id): Path<Uuid>,
+) -> AppResult<Json<Vec<SeatDto>>> {
+    let exists: bool =
+        sqlx::query_scalar("select exists(select 1 from ticket_types where id = $1)")
+            .bind(ticket_type_id)
+            .fetch_one(&db.pool)
+            .await?;
+    if !exists {
+        return Err(AppError::NotFound);
+    }
+
+    let rows = sqlx::query_as::<_, SeatDto>(
+        r#"select id, section, row_label, number, status from seats
+           where ticket_type_id = $1
+           order by section, row_label, number"#,
+    )
+    .bind(ticket_type_id)
+    .fetch_all(&db.pool)
+    .await?;
+    Ok(Json(rows))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/orders/{order_id}/seats",
+    params(("order_id" = Uuid, Path, description = "Order id")),
+    responses((status=200, body=[SeatDto], description="Seats held or sold by the order"), (status=401), (status=404))
+)]
+pub async fn order_seats(
+    axum::extract::State(db): axum::extract::State<Db>,
+    auth: AuthUser,
+    Path(order_id): Path<Uuid>,
+) -> AppResult<Json<Vec<SeatDto>>> {
+    let mut conn = db.pool.acquire().await?;
+    let owned: bool =
+        sqlx::query_scalar("select exists(select 1 from orders where id = $1 and user_id = $2)")
+            .bind(order_id)
+            .bind(auth.user_id)
+            .fetch_one(&mut *conn)
+            .await?;
+    if !owned {
+        return Err(AppError::NotFound);
+    }
+    Ok(Json(seats::for_order(&mut conn, order_id).await?))
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/api/ticket-types/:ticket_type_id/seats", get(seat_map))
+        .route("/api/orders/:order_id/seats", get(order_seats))
+}
@@ -1,9 +1,20 @@
-use axum::{http::HeaderMap, routing::post, Json, Router};
+use std::net::{IpAddr, SocketAddr};
+
+use axum::{extract::ConnectInfo, http::HeaderMap, routing::post, Json, Router};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
 use uuid::Uuid;
 
-use crate::{auth::AuthUser, db::Db, error::{AppError, AppResult}};
+use crate::{
+    abuse::{self, AbuseEvent, SolvedChallenge},
+    auth::AuthUser,
+    error::{AbuseReason, AppError, AppResult},
+    order_state::{self, OrderStatus},
+    presale, promo, purchase, seats,
+    state::AppState,
+    waiting_room,
+};
 use utoipa::ToSchema;
 
 pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
@@ -11,8 +22,24 @@ pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
 #[derive(Deserialize, ToSchema)]
 pub struct GrabRequest {
     pub ticket_type_id: Uuid,
+    #[serde(default = "default_qty")]
     #[schema(default = 1)]
     pub qty: i32,
+    /// Seated ticket types only: these exact seats (`qty` is then their number). Omit to get
+    /// the best available `qty` adjacent seats.
+    #[serde(default)]
+    pub seat_ids: Vec<Uuid>,
+    /// Discount code; rejected (and nothing bought) if it doesn't apply or is used up.
+    #[serde(default)]
+    pub promo_code: Option<String>,
+    /// Unlocks the presale for users not on the allow-list; one use is spent per
+    /// successful presale purchase.
+    #[serde(default)]
+    pub presale_code: Option<String>,
+}
+
+fn default_qty() -> i32 {
+    1
 }
 
 #[derive(Serialize, ToSchema, sqlx::FromRow)]
@@ -22,8 +49,152 @@ pub struct OrderDto {
     pub ticket_type_id: Uuid,
     pub qty: i32,
     pub amount_cents: i64,
-    pub status: String,
+    pub status: OrderStatus,
+    pub refunded_cents: i64,
     pub created_at: DateTime<Utc>,
+    /// Payment deadline for `CREATED` orders; unpaid orders are canceled after it.
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Who is asking, for abuse reports.
+pub(crate) struct Caller {
+    pub user_id: Uuid,
+    pub ip: Option<IpAddr>,
+    pub path: &'static str,
+}
+
+impl Caller {
+    /// Records the rejection and returns the error to answer with.
+    async fn reject(
+        &self,
+        state: &AppState,
+        ticket_type_id: Uuid,
+        reason: AbuseReason,
+    ) -> AppError {
+        abuse::report(
+            &state.db.pool,
+            AbuseEvent {
+                reason,
+                user_id: Some(self.user_id),
+                ip: self.ip,
+                path: Some(self.path),
+                details: serde_json::json!({ "ticket_type_id": ticket_type_id }),
+            },
+        )
+        .await;
+        AppError::Abuse(reason)
+    }
+}
+
+impl GrabRequest {
+    /// With `seat_ids`, `qty` is their number.
+    pub(crate) fn normalize(&mut self) -> AppResult<()> {
+        if !self.seat_ids.is_empty() {
+            let distinct: std::collections::HashSet<_> = self.seat_ids.iter().collect();
+            if distinct.len() != self.seat_ids.len() {
+                return Err(AppError::BadRequest("duplicate seat_ids".into()));
+            }
+            self.qty = self.seat_ids.len() as i32;
+        }
+        if self.qty < 1 {
+            return Err(AppError::BadRequest("qty must be >= 1".into()));
+        }
+        Ok(())
+    }
+}
+
+/// What the request brought to get past the waiting room and proof of work.
+pub(crate) struct Passes<'a> {
+    admission_token: Option<&'a str>,
+    /// `None` while proof of work is off.
+    challenge: Option<SolvedChallenge>,
+}
+
+/// Reads the admission token and checks the grab challenge. Called before anything else so
+/// unsolved requests cost no stock-gate or database work.
+pub(crate) async fn read_passes<'a>(
+    state: &AppState,
+    caller: &Caller,
+    headers: &'a HeaderMap,
+    ticket_type_id: Uuid,
+) -> AppResult<Passes<'a>> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let challenge = match state.cfg.grab_pow_difficulty {
+        0 => None,
+        difficulty => match abuse::verify_challenge(
+            &state.cfg.jwt_secret,
+            caller.user_id,
+            difficulty,
+            header(abuse::CHALLENGE_HEADER),
+            header(abuse::SOLUTION_HEADER),
+        ) {
+            Ok(solved) => Some(solved),
+            Err(reason) => return Err(caller.reject(state, ticket_type_id, reason).await),
+        },
+    };
+    Ok(Passes {
+        admission_token: header(waiting_room::ADMISSION_HEADER),
+        challenge,
+    })
+}
+
+/// The competitive part of a purchase, shared by grab and holds: spends the waiting-room
+/// admission and the challenge, checks qty, per-user caps and presale access, then takes stock
+/// and seats for `holder`, all in the caller's transaction.
+pub(crate) async fn take_stock(
+    conn: &mut PgConnection,
+    state: &AppState,
+    caller: &Caller,
+    req: &GrabRequest,
+    passes: &Passes<'_>,
+    holder: purchase::Holder,
+    now: DateTime<Utc>,
+) -> AppResult<purchase::Reserved> {
+    waiting_room::check_admission(
+        conn,
+        &state.cfg.jwt_secret,
+        caller.user_id,
+        req.ticket_type_id,
+        passes.admission_token,
+    )
+    .await?;
+    if let Some(solved) = &passes.challenge {
+        if !abuse::redeem_challenge(conn, caller.user_id, solved).await? {
+            return Err(caller
+                .reject(state, req.ticket_type_id, AbuseReason::ChallengeReused)
+                .await);
+        }
+    }
+    purchase::check_order_qty(conn, req.ticket_type_id, req.qty).await?;
+    // Per-user caps; holds the user's row lock until commit.
+    purchase::check_purchase_limits(conn, caller.user_id, req.ticket_type_id, req.qty).await?;
+    let presale = presale::admit(
+        conn,
+        caller.user_id,
+        req.ticket_type_id,
+        req.presale_code.as_deref(),
+        now,
+    )
+    .await?;
+
+    // Atomic inventory decrement in Postgres (no oversell): single UPDATE guarded by remaining>=qty + time window
+    // + the ticket type's intent policy.
+    let reserved = purchase::reserve_stock(
+        conn,
+        req.ticket_type_id,
+        req.qty,
+        holder,
+        now,
+        if presale {
+            purchase::Buyer::Presale
+        } else {
+            purchase::Buyer::Grab
+        },
+    )
+    .await?
+    .ok_or_else(|| AppError::Conflict("out of stock or not in sale window".into()))?;
+    seats::assign(conn, req.ticket_type_id, holder, req.qty, &req.seat_ids).await?;
+    Ok(reserved)
 }
 
 #[utoipa::path(
@@ -31,31 +202,60 @@ pub struct OrderDto {
     path = "/api/tickets/grab",
     request_body = GrabRequest,
     params(
-        ("idempotency-key" = String, Header, description = "Idempotency key (per user). Recommended.")
+        ("idempotency-key" = String, Header, description = "Idempotency key (per user). Recommended."),
+        ("x-admission-token" = Option<String>, Header, description = "Waiting-room admission; required when the ticket type has queue_enabled"),
+        ("x-grab-challenge" = Option<String>, Header, description = "Challenge from GET /api/grab-challenge; required when proof of work is on"),
+        ("x-grab-solution" = Option<String>, Header, description = "String s such that sha256(challenge || s) has `difficulty` leading zero bits")
     ),
-    responses((status=200, body=OrderDto), (status=409, description="Out of stock / not started / already grabbed"), (status=401))
+    responses((status=200, body=OrderDto, description="Seats of the order: GET /api/orders/{order_id}/seats"), (status=400, description="Invalid qty / above max_qty_per_order / bad seat_ids / promo code unknown or not applicable"), (status=403, description="Waiting-room admission or grab challenge missing, invalid or already used; presale access required; account or IP blocked"), (status=409, description="Sold out / not started / purchase limit reached / seats taken / promo code used up"), (status=401))
 )]
 pub async fn grab(
-    axum::extract::State(db): axum::extract::State<Db>,
+    axum::extract::State(state): axum::extract::State<AppState>,
     auth: AuthUser,
+    peer: Option<ConnectInfo<SocketAddr>>,
     headers: HeaderMap,
-    Json(req): Json<GrabRequest>,
+    Json(mut req): Json<GrabRequest>,
 ) -> AppResult<Json<OrderDto>> {
-    if req.qty != 1 {
-        return Err(AppError::BadRequest("only qty=1 supported in MVP".into()));
-    }
+    req.normalize()?;
+    let caller = Caller {
+        user_id: auth.user_id,
+        ip: peer.map(|ConnectInfo(addr)| addr.ip().to_canonical()),
+        path: "/api/tickets/grab",
+    };
+    let passes = read_passes(&state, &caller, &headers, req.ticket_type_id).await?;
 
     let idempotency_key = headers
         .get(IDEMPOTENCY_HEADER)
         .and_then(|v| v.to_str().ok())
         .map(|s| s.to_string());
+    let db = &state.db;
+
+    // Sold out as far as this process knows: answer without a transaction. Only a retry
+    // of an earlier successful grab still gets its order back.
+    let Some(permit) = state.stock_gate.try_admit(req.ticket_type_id, req.qty) else {
+        if let Some(key) = &idempotency_key {
+            let existing = sqlx::query_as::<_, OrderDto>(
+                r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
+                   from orders
+                   where user_id = $1 and idempotency_key = $2"#,
+            )
+            .bind(auth.user_id)
+            .bind(key)
+            .fetch_optional(&db.pool)
+            .await?;
+            if let Some(order) = existing {
+                return Ok(Json(order));
+            }
+        }
+        return Err(AppError::Conflict("sold out".into()));
+    };
 
     let mut tx = db.pool.begin().await?;
 
     // If idempotency key matches an existing order, return it.
     if let Some(key) = &idempotency_key {
         let existing = sqlx::query_as::<_, OrderDto>(
-            r#"select id, user_id, ticket_type_id, qty, amount_cents, status, created_at
+            r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
                from orders
                where user_id = $1 and idempotency_key = $2"#,
         )
@@ -70,81 +270,81 @@ pub async fn grab(
         }
     }
 
-    // Quick check: if user already has an active order (CREATED/PAID) for this ticket type, return it.
-    if let Some(order) = sqlx::query_as::<_, OrderDto>(
-        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, created_at
-           from orders
-           where user_id = $1 and ticket_type_id = $2 and status in ('CREATED','PAID')"#,
-    )
-    .bind(auth.user_id)
-    .bind(req.ticket_type_id)
-    .fetch_optional(&mut *tx)
-    .await?
-    {
-        tx.commit().await?;
-        return Ok(Json(order));
-    }
-
-    // Atomic inventory decrement in Postgres (no oversell): single UPDATE guarded by remaining>=1 + time window.
+    // After the idempotency lookup, so retries don't need a fresh admission.
     let now = Utc::now();
-    let updated: Option<(i64,)> = sqlx::query_as(
-        r#"update ticket_types
-           set inventory_remaining = inventory_remaining - 1
-           where id = $1
-             and inventory_remaining >= 1
-             and sale_starts_at <= $2
-             and sale_ends_at > $2
-           returning price_cents"#,
+    let order_id = Uuid::new_v4();
+    let reserved = match take_stock(
+        &mut tx,
+        &state,
+        &caller,
+        &req,
+        &passes,
+        purchase::Holder::Order(order_id),
+        now,
     )
-    .bind(req.ticket_type_id)
-    .bind(now)
-    .fetch_optional(&mut *tx)
-    .await?;
-
-    let price_cents = match updated {
-        Some((price_cents,)) => price_cents,
-        None => {
+    .await
+    {
+        Ok(reserved) => reserved,
+        Err(e) => {
             tx.rollback().await?;
-            return Err(AppError::Conflict("out of stock or not in sale window".into()));
+            return Err(e);
         }
     };
 
-    let order_id = Uuid::new_v4();
+    let mut amount_cents = reserved.price_cents * req.qty as i64;
+    if let Some(code) = &req.promo_code {
+        match promo::redeem(
+            &mut tx,
+            code,
+            auth.user_id,
+            req.ticket_type_id,
+            order_id,
+            amount_cents,
+            now,
+        )
+        .await
+        {
+            Ok(discount) => amount_cents -= discount,
+            Err(e) => {
+                tx.rollback().await?;
+                return Err(e);
+            }
+        }
+    }
+
     let inserted = sqlx::query_as::<_, OrderDto>(
-        r#"insert into orders (id, user_id, ticket_type_id, qty, amount_cents, status, idempotency_key)
-           values ($1,$2,$3,$4,$5,'CREATED', $6)
-           returning id, user_id, ticket_type_id, qty, amount_cents, status, created_at"#,
+        r#"insert into orders (id, user_id, ticket_type_id, qty, amount_cents, status, idempotency_key, expires_at)
+           values ($1,$2,$3,$4,$5,'CREATED', $6, $7)
+           returning id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at"#,
     )
     .bind(order_id)
     .bind(auth.user_id)
     .bind(req.ticket_type_id)
     .bind(req.qty)
-    .bind(price_cents)
-    .bind(idempotency_key)
+    .bind(amount_cents)
+    .bind(&idempotency_key)
+    .bind(reserved.payment_deadline(now))
     .fetch_one(&mut *tx)
     .await;
 
     let rec = match inserted {
         Ok(v) => v,
         Err(e) => {
-            // If we lost a race on unique constraints, return the existing order.
+            // If we lost a race with a retry of the same request, return the existing order.
+            // The failed insert aborted this transaction, so roll back (returning the stock) first.
             if let Some(db_err) = e.as_database_error() {
-                if db_err.constraint() == Some("uq_orders_user_ticket_type_active")
-                    || db_err.constraint() == Some("uq_orders_user_idempotency")
-                {
+                if db_err.constraint() == Some("uq_orders_user_idempotency") {
+                    tx.rollback().await?;
                     let existing = sqlx::query_as::<_, OrderDto>(
-                        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, created_at
+                        r#"select id, user_id, ticket_type_id, qty, amount_cents, status, refunded_cents, created_at, expires_at
                            from orders
-                           where user_id = $1 and ticket_type_id = $2 and status in ('CREATED','PAID')
-                           order by created_at desc
-                           limit 1"#,
+                           where user_id = $1 and idempotency_key = $2"#,
                     )
                     .bind(auth.user_id)
-                    .bind(req.ticket_type_id)
-                    .fetch_one(&mut *tx)
+                    .bind(&idempotency_key)
+                    .fetch_one(&db.pool)
                     .await?;
 
-                    tx.commit().await?;
                     return Ok(Json(existing));
                 }
             }
@@ -152,11 +352,14 @@ pub async fn grab(
         }
     };
 
+    order_state::record_created(&mut tx, rec.id, &format!("user:{}", auth.user_id)).await?;
+
     tx.commit().await?;
+    permit.consume();
     Ok(Json(rec))
 }
 
-pub fn router() -> Router<Db> {
+pub fn router() -> Router<AppState> {
     Router::new()
         .route("/api/tickets/grab", post(grab))
         // compatibility
@@ -0,0 +1,228 @@
+//! Assigned seating for ticket types created with `seat_rows`.
+//!
+//! A seated ticket type still keeps its stock counter (one unit per seat), so limits, the
+//! stock gate and the inventory ledger work as for general admission. On top of that, every
+//! order (or hold) has specific rows in `seats`: [`assign`] runs after
+//! `purchase::reserve_stock` in the same transaction, whose `for update` on the ticket type
+//! already serializes buyers of that ticket type; the seat UPDATE additionally only takes
+//! `AVAILABLE` seats, so a seat can never be held twice. Seats are `HELD` while a hold or a
+//! `CREATED` order has them, `SOLD` once paid ([`mark_sold`]) and `AVAILABLE` again when the
+//! stock is released ([`release`]).
+
+use std::collections::HashSet;
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult},
+    purchase::Holder,
+};
+
+/// Most seats one row of a layout may have.
+pub const MAX_SEATS_PER_ROW: i32 = 500;
+
+/// One row of a seat layout, numbered `1..=seats`.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct SeatRow {
+    pub section: String,
+    pub row: String,
+    pub seats: i32,
+}
+
+#[derive(Debug, Serialize, ToSchema, sqlx::FromRow)]
+pub struct SeatDto {
+    pub id: Uuid,
+    pub section: String,
+    #[serde(rename = "row")]
+    pub row_label: String,
+    pub number: i32,
+    /// `AVAILABLE`, `HELD` (by an unpaid order) or `SOLD`.
+    pub status: String,
+}
+
+/// Checks a layout and returns its seat count.
+pub fn validate_layout(rows: &[SeatRow]) -> AppResult<i32> {
+    let mut seen = HashSet::new();
+    let mut total = 0i32;
+    for r in rows {
+        if r.section.trim().is_empty() || r.row.trim().is_empty() {
+            return Err(AppError::BadRequest(
+                "seat rows need a section and a row".into(),
+            ));
+        }
+        if !(1..=MAX_SEATS_PER_ROW).contains(&r.seats) {
+            return Err(AppError::BadRequest(format!(
+                "seats per row must be in 1..={MAX_SEATS_PER_ROW}"
+            )));
+        }
+        if !seen.insert((r.section.trim(), r.row.trim())) {
+            return Err(AppError::BadRequest(format!(
+                "duplicate seat row {} / {}",
+                r.section.trim(),
+                r.row.trim()
+            )));
+        }
+        total = total
+            .checked_add(r.seats)
+            .ok_or_else(|| AppError::BadRequest("too many seats".into()))?;
+    }
+    Ok(total)
+}
+
+/// Inserts the seats of a new ticket type.
+pub async fn create(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    rows: &[SeatRow],
+) -> Result<(), sqlx::Error> {
+    for r in rows {
+        sqlx::query(
+            r#"insert into seats (id, ticket_type_id, section, row_label, number)
+               select gen_random_uuid(), $1, $2, $3, n from generate_series(1, $4) n"#,
+        )
+        .bind(ticket_type_id)
+        .bind(r.section.trim())
+        .bind(r.row.trim())
+        .bind(r.seats)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Holds seats for `holder`: exactly `seat_ids` when given, otherwise the best available
+/// `qty` adjacent seats (lowest section, row and number first). No-op for general admission.
+///
+/// Call after `purchase::reserve_stock` has taken the stock, inside the same transaction.
+pub async fn assign(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    holder: Holder,
+    qty: i32,
+    seat_ids: &[Uuid],
+) -> AppResult<()> {
+    let seated: Option<bool> = sqlx::query_scalar("select seated from ticket_types where id = $1")
+        .bind(ticket_type_id)
+        .fetch_optional(&mut *conn)
+        .await?;
+    match seated {
+        None => return Err(AppError::NotFound),
+        Some(false) if seat_ids.is_empty() => return Ok(()),
+        Some(false) => {
+            return Err(AppError::BadRequest(
+                "ticket type is general admission; seat_ids not allowed".into(),
+            ))
+        }
+        Some(true) => {}
+    }
+
+    let ids = if seat_ids.is_empty() {
+        best_adjacent(conn, ticket_type_id, qty).await?
+    } else {
+        seat_ids.to_vec()
+    };
+    if ids.len() != qty as usize {
+        return Err(AppError::Conflict(format!(
+            "no {qty} adjacent seats available"
+        )));
+    }
+
+    let held = sqlx::query(
+        r#"update seats set status = 'HELD', order_id = $3, hold_id = $4
+           where id = any($1) and ticket_type_id = $2 and status = 'AVAILABLE'"#,
+    )
+    .bind(&ids)
+    .bind(ticket_type_id)
+    .bind(holder.order_id())
+    .bind(holder.hold_id())
+    .execute(&mut *conn)
+    .await?;
+    if held.rows_affected() != ids.len() as u64 {
+        return Err(AppError::Conflict("seat not available".into()));
+    }
+    Ok(())
+}
+
+/// First run of `qty` consecutive available seats in one row; empty if there is none.
+async fn best_adjacent(
+    conn: &mut PgConnection,
+    ticket_type_id: Uuid,
+    qty: i32,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    sqlx::query_scalar(
+        r#"with free as (
+             select id, section, row_label, number,
+                    number - row_number() over (partition by section, row_label order by number) as run
+             from seats
+             where ticket_type_id = $1 and status = 'AVAILABLE'
+           ), best as (
+             select section, row_label, run
+             from free
+             group by section, row_label, run
+             having count(*) >= $2
+             order by section, row_label, min(number)
+             limit 1
+           )
+           select f.id from free f join best b using (section, row_label, run)
+           order by f.number
+           limit $2"#,
+    )
+    .bind(ticket_type_id)
+    .bind(qty as i64)
+    .fetch_all(&mut *conn)
+    .await
+}
+
+/// The order was paid: its seats are sold.
+pub async fn mark_sold(conn: &mut PgConnection, order_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("update seats set status = 'SOLD' where order_id = $1")
+        .bind(order_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// The holder's stock went back on sale: so do its seats.
+pub async fn release(conn: &mut PgConnection, holder: Holder) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"update seats set status = 'AVAILABLE', order_id = null, hold_id = null
+           where order_id = $1 or hold_id = $2"#,
+    )
+    .bind(holder.order_id())
+    .bind(holder.hold_id())
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Checkout: the hold's seats now belong to the order.
+pub async fn transfer(
+    conn: &mut PgConnection,
+    hold_id: Uuid,
+    order_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("update seats set order_id = $2, hold_id = null where hold_id = $1")
+        .bind(hold_id)
+        .bind(order_id)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Seats held or sold by the order, in seat order.
+pub async fn for_order(
+    conn: &mut PgConnection,
+    order_id: Uuid,
+) -> Result<Vec<SeatDto>, sqlx::Error> {
+    sqlx::query_as::<_, SeatDto>(
+        r#"select id, section, row_label, number, status from seats
+           where order_id = $1
+           order by section, row_label, number"#,
+    )
+    .bind(order_id)
+    .fetch_all(&mut *conn)
+    .await
+}
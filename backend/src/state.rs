@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use axum::extract::FromRef;
+
+use crate::{
+    abuse::Blocklist, config::Config, db::Db, payments::PaymentProvider, stock_gate::StockGate,
+};
+
+/// Router state. Handlers that only need the pool keep extracting `State<Db>`.
+#[derive(Clone)]
+pub struct AppState {
+    pub cfg: Config,
+    pub db: Db,
+    pub payments: Arc<dyn PaymentProvider>,
+    pub stock_gate: Arc<StockGate>,
+    pub blocklist: Arc<Blocklist>,
+}
+
+impl FromRef<AppState> for Db {
+    fn from_ref(state: &AppState) -> Self {
+        state.db.clone()
+    }
+}
+
+impl FromRef<AppState> for Arc<StockGate> {
+    fn from_ref(state: &AppState) -> Self {
+        state.stock_gate.clone()
+    }
+}
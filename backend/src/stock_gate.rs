@@ -0,0 +1,134 @@
+//! Per-process stock cache in front of `grab`.
+//!
+//! One counter per ticket type on sale, loaded from Postgres by [`StockGate::refresh`] and
+//! decremented as grabs are admitted. Once a counter can't cover a request it is rejected
+//! without touching the database. Postgres stays the source of truth: the counters are only an
+//! estimate (other processes and the intent worker sell too), corrected on every refresh, and
+//! an admitted grab can still fail on the real stock check.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct StockGate {
+    disabled: bool,
+    counters: RwLock<HashMap<Uuid, Arc<AtomicI64>>>,
+}
+
+/// Stock taken from the gate by an admitted grab. Dropping it gives the stock back; call
+/// [`Permit::consume`] once the order is committed.
+pub struct Permit {
+    counter: Option<Arc<AtomicI64>>,
+    qty: i64,
+}
+
+impl Permit {
+    pub fn consume(mut self) {
+        self.counter = None;
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(counter) = &self.counter {
+            counter.fetch_add(self.qty, Ordering::SeqCst);
+        }
+    }
+}
+
+impl StockGate {
+    /// A gate that admits everything (`STOCK_GATE_REFRESH_MS=0`).
+    pub fn disabled() -> Self {
+        Self {
+            disabled: true,
+            ..Self::default()
+        }
+    }
+
+    fn counter(&self, ticket_type_id: Uuid) -> Option<Arc<AtomicI64>> {
+        self.counters
+            .read()
+            .expect("stock gate lock poisoned")
+            .get(&ticket_type_id)
+            .cloned()
+    }
+
+    /// Takes `qty` from the ticket type's counter; `None` means sold out as far as this
+    /// process knows. Ticket types not loaded yet (e.g. created since the last refresh) are
+    /// always admitted.
+    pub fn try_admit(&self, ticket_type_id: Uuid, qty: i32) -> Option<Permit> {
+        let qty = qty as i64;
+        let Some(counter) = self.counter(ticket_type_id) else {
+            return Some(Permit { counter: None, qty });
+        };
+        counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                (left >= qty).then_some(left - qty)
+            })
+            .ok()
+            .map(|_| Permit {
+                counter: Some(counter),
+                qty,
+            })
+    }
+
+    /// Stock returned to the database (cancel, expiry, restock); re-opens the gate right away
+    /// instead of at the next refresh.
+    pub fn release(&self, ticket_type_id: Uuid, qty: i32) {
+        if let Some(counter) = self.counter(ticket_type_id) {
+            counter.fetch_add(qty as i64, Ordering::SeqCst);
+        }
+    }
+
+    /// Sets the counter to a remaining stock just read from the database (new ticket types,
+    /// admin adjustments).
+    pub fn observe(&self, ticket_type_id: Uuid, remaining: i32) {
+        if self.disabled {
+            return;
+        }
+        if let Some(counter) = self.counter(ticket_type_id) {
+            counter.store(remaining as i64, Ordering::SeqCst);
+            return;
+        }
+        self.counters
+            .write()
+            .expect("stock gate lock poisoned")
+            .entry(ticket_type_id)
+            .or_default()
+            .store(remaining as i64, Ordering::SeqCst);
+    }
+
+    /// Reloads every ticket type whose sale hasn't ended, minus the stock held for intents;
+    /// drops the others.
+    pub async fn refresh(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        if self.disabled {
+            return Ok(());
+        }
+        let rows: Vec<(Uuid, i32)> = sqlx::query_as(
+            r#"select id, ticket_type_remaining(id, inventory_shards, inventory_remaining)
+                        - intent_reserved_remaining
+               from ticket_types where sale_ends_at > now()"#,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        let mut counters = self.counters.write().expect("stock gate lock poisoned");
+        let mut fresh = HashMap::with_capacity(rows.len());
+        for (ticket_type_id, remaining) in rows {
+            // Keep the same counter so outstanding permits give back to the live one.
+            let counter = counters.remove(&ticket_type_id).unwrap_or_default();
+            counter.store(remaining as i64, Ordering::SeqCst);
+            fresh.insert(ticket_type_id, counter);
+        }
+        *counters = fresh;
+        Ok(())
+    }
+}
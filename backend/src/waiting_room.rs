@@ -0,0 +1,320 @@
+//! Virtual waiting room for ticket types with `queue_enabled`.
+//!
+//! Users join a per-ticket-type queue (`queue_entries`). Everyone who joined before
+//! `sale_starts_at` is drawn into a random order when the sale opens; later joiners line up
+//! behind them in join order. [`admit_tick`] lets the next users in, keeping at most
+//! `queue_max_active` admissions outstanding, and `grab` requires the signed admission token
+//! they are handed (see [`check_admission`]). Everything lives in Postgres, so any number of
+//! processes can serve the queue.
+
+use chrono::{DateTime, Utc};
+use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::{AppError, AppResult};
+
+/// Request header carrying the admission token on `grab`.
+pub const ADMISSION_HEADER: &str = "x-admission-token";
+
+/// Keeps admission tokens from being accepted as access tokens and vice versa.
+const ADMISSION_AUDIENCE: &str = "waiting-room";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AdmissionClaims {
+    /// User id.
+    sub: String,
+    /// Ticket type id.
+    tt: String,
+    /// Queue entry id.
+    jti: String,
+    aud: String,
+    exp: usize,
+}
+
+fn sign_admission(
+    secret: &str,
+    entry_id: Uuid,
+    user_id: Uuid,
+    ticket_type_id: Uuid,
+    expires_at: DateTime<Utc>,
+) -> anyhow::Result<String> {
+    let claims = AdmissionClaims {
+        sub: user_id.to_string(),
+        tt: ticket_type_id.to_string(),
+        jti: entry_id.to_string(),
+        aud: ADMISSION_AUDIENCE.to_string(),
+        exp: expires_at.timestamp() as usize,
+    };
+    Ok(jsonwebtoken::encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(secret.as_bytes()),
+    )?)
+}
+
+/// Returns the queue entry id if `token` admits `user_id` to `ticket_type_id`.
+fn verify_admission(
+    secret: &str,
+    token: &str,
+    user_id: Uuid,
+    ticket_type_id: Uuid,
+) -> Option<Uuid> {
+    let mut validation = Validation::default();
+    validation.set_audience(&[ADMISSION_AUDIENCE]);
+    let claims = jsonwebtoken::decode::<AdmissionClaims>(
+        token,
+        &DecodingKey::from_secret(secret.as_bytes()),
+        &validation,
+    )
+    .ok()?
+    .claims;
+    if claims.sub != user_id.to_string() || claims.tt != ticket_type_id.to_string() {
+        return None;
+    }
+    Uuid::parse_str(&claims.jti).ok()
+}
+
+/// Spends the caller's admission if the ticket type is queued; a no-op otherwise.
+///
+/// Runs in the grab's transaction, so a grab that fails afterwards (sold out, limits) leaves
+/// the admission usable until it expires. Unknown ticket types pass; the caller reports them.
+pub async fn check_admission(
+    conn: &mut PgConnection,
+    secret: &str,
+    user_id: Uuid,
+    ticket_type_id: Uuid,
+    token: Option<&str>,
+) -> AppResult<()> {
+    if !is_queued(conn, ticket_type_id).await? {
+        return Ok(());
+    }
+    let entry_id = token
+        .and_then(|t| verify_admission(secret, t, user_id, ticket_type_id))
+        .ok_or(AppError::AdmissionRequired)?;
+
+    let spent = sqlx::query(
+        r#"update queue_entries set used_at = now()
+           where id = $1 and user_id = $2 and ticket_type_id = $3
+             and used_at is null and admission_expires_at > now()"#,
+    )
+    .bind(entry_id)
+    .bind(user_id)
+    .bind(ticket_type_id)
+    .execute(&mut *conn)
+    .await?;
+    if spent.rows_affected() != 1 {
+        return Err(AppError::AdmissionRequired);
+    }
+    Ok(())
+}
+
+pub async fn is_queued(conn: &mut PgConnection, ticket_type_id: Uuid) -> Result<bool, sqlx::Error> {
+    let enabled: Option<bool> =
+        sqlx::query_scalar("select queue_enabled from ticket_types where id = $1")
+            .bind(ticket_type_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+    Ok(enabled == Some(true))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueState {
+    /// In line; `position` is assigned once the sale opens.
+    Waiting,
+    /// May grab now with `admission_token`.
+    Admitted,
+    /// The admission ran out unused; join again to line up at the back.
+    Expired,
+    /// The admission was spent on a grab.
+    Done,
+    /// No stock left for those still waiting.
+    SoldOut,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QueueStatus {
+    pub ticket_type_id: Uuid,
+    pub state: QueueState,
+    pub position: Option<i64>,
+    /// Users still waiting in front of this one.
+    pub ahead: Option<i64>,
+    pub sale_starts_at: DateTime<Utc>,
+    /// Send as the `x-admission-token` header on `grab`; only while `admitted`.
+    pub admission_token: Option<String>,
+    pub admission_expires_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+struct EntryRow {
+    id: Uuid,
+    position: Option<i64>,
+    admission_expires_at: Option<DateTime<Utc>>,
+    used_at: Option<DateTime<Utc>>,
+    ahead: Option<i64>,
+    sale_starts_at: DateTime<Utc>,
+    remaining: i32,
+}
+
+/// The user's place in the ticket type's queue; `None` if they haven't joined.
+pub async fn status(
+    pool: &PgPool,
+    secret: &str,
+    user_id: Uuid,
+    ticket_type_id: Uuid,
+) -> AppResult<Option<QueueStatus>> {
+    let row = sqlx::query_as::<_, EntryRow>(
+        r#"select q.id, q.position, q.admission_expires_at, q.used_at,
+                  (select count(*) from queue_entries w
+                    where w.ticket_type_id = q.ticket_type_id and w.admitted_at is null
+                      and w.position < q.position) as ahead,
+                  t.sale_starts_at,
+                  ticket_type_remaining(t.id, t.inventory_shards, t.inventory_remaining) as remaining
+           from queue_entries q join ticket_types t on t.id = q.ticket_type_id
+           where q.ticket_type_id = $1 and q.user_id = $2"#,
+    )
+    .bind(ticket_type_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let now = Utc::now();
+    let state = match (row.admission_expires_at, row.used_at) {
+        (_, Some(_)) => QueueState::Done,
+        (Some(exp), None) if exp > now => QueueState::Admitted,
+        (Some(_), None) => QueueState::Expired,
+        (None, None) if row.remaining <= 0 => QueueState::SoldOut,
+        (None, None) => QueueState::Waiting,
+    };
+    let admission_token = match (state, row.admission_expires_at) {
+        (QueueState::Admitted, Some(exp)) => Some(sign_admission(
+            secret,
+            row.id,
+            user_id,
+            ticket_type_id,
+            exp,
+        )?),
+        _ => None,
+    };
+    Ok(Some(QueueStatus {
+        ticket_type_id,
+        state,
+        position: row.position,
+        ahead: row.position.and(row.ahead),
+        sale_starts_at: row.sale_starts_at,
+        admission_token,
+        admission_expires_at: row
+            .admission_expires_at
+            .filter(|_| state == QueueState::Admitted),
+    }))
+}
+
+/// Adds the user to the queue. Joining again is a no-op while the entry is live; after an
+/// expired or spent admission it starts over at the back.
+pub async fn join(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    ticket_type_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"insert into queue_entries (id, ticket_type_id, user_id)
+           values ($1, $2, $3)
+           on conflict (ticket_type_id, user_id) do update
+             set joined_at = now(), lottery_key = random(), position = null,
+                 admitted_at = null, admission_expires_at = null, used_at = null
+             where queue_entries.used_at is not null
+                or queue_entries.admission_expires_at <= now()"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(ticket_type_id)
+    .bind(user_id)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// One round of admissions for every queued ticket type on sale; returns how many users
+/// were let in. Each ticket type is handled under an advisory lock, so concurrent processes
+/// don't admit twice.
+pub async fn admit_tick(pool: &PgPool, admission_ttl_secs: i64) -> anyhow::Result<u64> {
+    let queued: Vec<(Uuid, i32, i32)> = sqlx::query_as(
+        r#"select id, queue_max_active,
+                  ticket_type_remaining(id, inventory_shards, inventory_remaining)
+           from ticket_types
+           where queue_enabled and sale_starts_at <= now() and sale_ends_at > now()"#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut admitted = 0;
+    for (ticket_type_id, max_active, remaining) in queued {
+        let mut tx = pool.begin().await?;
+        let locked: bool =
+            sqlx::query_scalar("select pg_try_advisory_xact_lock(hashtextextended($1::text, 0))")
+                .bind(ticket_type_id)
+                .fetch_one(&mut *tx)
+                .await?;
+        if !locked {
+            tx.rollback().await?;
+            continue;
+        }
+
+        // Line up everyone without a position: the pre-open draw first, then join order.
+        sqlx::query(
+            r#"with base as (
+                 select coalesce(max(position), 0) as p from queue_entries where ticket_type_id = $1
+               ), next as (
+                 select q.id,
+                        row_number() over (
+                          order by q.joined_at >= t.sale_starts_at,
+                                   case when q.joined_at < t.sale_starts_at then q.lottery_key end,
+                                   q.joined_at, q.id) as n
+                 from queue_entries q join ticket_types t on t.id = q.ticket_type_id
+                 where q.ticket_type_id = $1 and q.position is null
+               )
+               update queue_entries q set position = base.p + next.n
+               from base, next where q.id = next.id"#,
+        )
+        .bind(ticket_type_id)
+        .execute(&mut *tx)
+        .await?;
+
+        // Outstanding admissions may each still turn into an order, so they count against
+        // both the cap and the remaining stock.
+        let active: i64 = sqlx::query_scalar(
+            r#"select count(*) from queue_entries
+               where ticket_type_id = $1 and used_at is null and admission_expires_at > now()"#,
+        )
+        .bind(ticket_type_id)
+        .fetch_one(&mut *tx)
+        .await?;
+        let slots = (max_active.min(remaining) as i64 - active).max(0);
+
+        if slots > 0 {
+            let n = sqlx::query(
+                r#"update queue_entries
+                   set admitted_at = now(), admission_expires_at = now() + make_interval(secs => $3)
+                   where id in (
+                     select id from queue_entries
+                     where ticket_type_id = $1 and position is not null and admitted_at is null
+                     order by position
+                     limit $2)"#,
+            )
+            .bind(ticket_type_id)
+            .bind(slots)
+            .bind(admission_ttl_secs as f64)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+            admitted += n;
+        }
+        tx.commit().await?;
+    }
+    Ok(admitted)
+}
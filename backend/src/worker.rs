@@ -1,5 +1,19 @@
-use crate::{db::Db, error::AppError};
-use chrono::Utc;
+use crate::{
+    abuse::Blocklist,
+    config::IntentWorkerConfig,
+    db::Db,
+    error::AppError,
+    holds,
+    intent_wakeup::Wakeups,
+    order_state::{self, OrderStatus},
+    payments::{self, PaymentProvider},
+    purchase, seats,
+    stock_gate::StockGate,
+    waiting_room,
+};
+use chrono::{DateTime, Utc};
+use sqlx::{Connection, PgConnection};
+use std::sync::Arc;
 use tracing::{debug, error, info};
 use uuid::Uuid;
 
@@ -8,79 +22,316 @@ struct IntentRow {
     id: Uuid,
     user_id: Uuid,
     ticket_type_id: Uuid,
+    qty: i32,
     idempotency_key: String,
+    max_price_cents: Option<i64>,
+    fallback_ticket_type_ids: Vec<Uuid>,
 }
 
-pub fn spawn_intent_worker(db: Db) {
+impl IntentRow {
+    /// Ticket types to try, in priority order.
+    fn candidates(&self) -> impl Iterator<Item = Uuid> + '_ {
+        std::iter::once(self.ticket_type_id).chain(self.fallback_ticket_type_ids.iter().copied())
+    }
+}
+
+/// Runs [`run_intent_worker`] in the background of the HTTP server.
+pub fn spawn_intent_worker(db: Db, cfg: IntentWorkerConfig) {
+    tokio::spawn(run_intent_worker(db, cfg));
+}
+
+/// Fulfils purchase intents until the task is dropped.
+///
+/// Each round leases a batch of due intents (`FOR UPDATE SKIP LOCKED`, so any number of
+/// workers can share the table) and tries each once. Failures are retried with exponential
+/// backoff until `max_attempts`, then the intent is `FAILED`. Between rounds the worker sleeps
+/// until woken (see [`crate::intent_wakeup`]).
+pub async fn run_intent_worker(db: Db, cfg: IntentWorkerConfig) {
+    let worker_id = format!(
+        "{}:{}:{}",
+        std::env::var("HOSTNAME").unwrap_or_else(|_| "worker".into()),
+        std::process::id(),
+        Uuid::new_v4()
+    );
+    info!(%worker_id, "purchase_intents worker started");
+    let mut wakeups = Wakeups::new(
+        db.pool.clone(),
+        std::time::Duration::from_millis(cfg.poll_ms),
+    );
+    loop {
+        match tick(&db, &cfg, &worker_id).await {
+            Ok(0) => {}
+            // A full batch: there's probably more due, go again right away.
+            Ok(n) if n as i64 >= cfg.batch => continue,
+            Ok(n) => debug!(claimed = n, "purchase_intents round"),
+            Err(e) => error!(err = ?e, "purchase_intents worker tick failed"),
+        }
+        wakeups.wait().await;
+    }
+}
+
+/// Cancels `CREATED` orders past their payment deadline and returns their stock.
+pub fn spawn_order_reaper(
+    db: Db,
+    stock_gate: Arc<StockGate>,
+    provider: Arc<dyn PaymentProvider>,
+    interval: std::time::Duration,
+) {
     tokio::spawn(async move {
-        info!("purchase_intents worker started");
+        info!("order reaper started");
         loop {
-            if let Err(e) = tick(&db).await {
-                error!(err = ?e, "purchase_intents worker tick failed");
+            match reap_expired_orders(&db, &stock_gate, provider.as_ref()).await {
+                Ok(0) => {}
+                Ok(n) => info!(canceled = n, "expired orders canceled"),
+                Err(e) => error!(err = ?e, "order reaper tick failed"),
             }
-            tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+            tokio::time::sleep(interval).await;
         }
     });
 }
 
-async fn tick(db: &Db) -> anyhow::Result<()> {
-    // Claim a small batch of ACTIVE intents.
-    let intents: Vec<IntentRow> = sqlx::query_as(
-        r#"select id, user_id, ticket_type_id, idempotency_key
-           from purchase_intents
-           where status='ACTIVE'
-           order by created_at asc
-           limit 50"#,
+/// Expires `ACTIVE` holds past their `expires_at` and returns their stock.
+pub fn spawn_hold_sweeper(db: Db, stock_gate: Arc<StockGate>, interval: std::time::Duration) {
+    tokio::spawn(async move {
+        info!("hold sweeper started");
+        loop {
+            match sweep_expired_holds(&db, &stock_gate).await {
+                Ok(0) => {}
+                Ok(n) => info!(expired = n, "expired holds released"),
+                Err(e) => error!(err = ?e, "hold sweeper tick failed"),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+/// Keeps the stock gate's counters in line with Postgres.
+pub fn spawn_stock_gate_refresh(db: Db, stock_gate: Arc<StockGate>, interval: std::time::Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = stock_gate.refresh(&db.pool).await {
+                error!(err = ?e, "stock gate refresh failed");
+            }
+        }
+    });
+}
+
+/// Picks up blocklist changes made by other processes and expiries.
+pub fn spawn_blocklist_refresh(db: Db, blocklist: Arc<Blocklist>, interval: std::time::Duration) {
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = blocklist.refresh(&db.pool).await {
+                error!(err = ?e, "blocklist refresh failed");
+            }
+        }
+    });
+}
+
+/// Lets the next users out of the waiting rooms.
+pub fn spawn_queue_admitter(db: Db, admission_ttl_secs: i64, interval: std::time::Duration) {
+    tokio::spawn(async move {
+        info!("waiting room admitter started");
+        loop {
+            match waiting_room::admit_tick(&db.pool, admission_ttl_secs).await {
+                Ok(0) => {}
+                Ok(n) => debug!(admitted = n, "waiting room admissions"),
+                Err(e) => error!(err = ?e, "waiting room tick failed"),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+async fn reap_expired_orders(
+    db: &Db,
+    stock_gate: &StockGate,
+    provider: &dyn PaymentProvider,
+) -> anyhow::Result<usize> {
+    let mut tx = db.pool.begin().await?;
+
+    // SKIP LOCKED: orders being paid right now are left for the next round.
+    let expired: Vec<(Uuid, Uuid, i32)> = sqlx::query_as(
+        r#"select id, ticket_type_id, qty
+           from orders
+           where status='CREATED' and expires_at <= now()
+           order by expires_at asc
+           limit 100
+           for update skip locked"#,
     )
-    .fetch_all(&db.pool)
+    .fetch_all(&mut *tx)
     .await?;
 
-    if intents.is_empty() {
-        return Ok(());
+    let mut closed = Vec::new();
+    let mut released = Vec::with_capacity(expired.len());
+    for (order_id, ticket_type_id, qty) in &expired {
+        order_state::transition(
+            &mut tx,
+            *order_id,
+            OrderStatus::Created,
+            OrderStatus::Canceled,
+            "system:reaper",
+            Some("payment deadline passed"),
+        )
+        .await?;
+        let grabbable = purchase::release_stock(
+            &mut tx,
+            *ticket_type_id,
+            *qty,
+            purchase::Holder::Order(*order_id),
+        )
+        .await?;
+        released.push((*ticket_type_id, grabbable));
+        closed.extend(payments::close_pending(&mut tx, *order_id).await?);
+    }
+
+    tx.commit().await?;
+    for (ticket_type_id, qty) in released {
+        stock_gate.release(ticket_type_id, qty);
+    }
+    payments::void_closed(provider, &closed).await;
+    Ok(expired.len())
+}
+
+async fn sweep_expired_holds(db: &Db, stock_gate: &StockGate) -> anyhow::Result<usize> {
+    let mut tx = db.pool.begin().await?;
+
+    // SKIP LOCKED: holds being checked out right now are left to the checkout.
+    let expired: Vec<(Uuid, Uuid, i32)> = sqlx::query_as(
+        r#"select id, ticket_type_id, qty
+           from holds
+           where status='ACTIVE' and expires_at <= now()
+           order by expires_at asc
+           limit 100
+           for update skip locked"#,
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for (hold_id, ticket_type_id, qty) in &expired {
+        holds::end(&mut tx, *hold_id, *ticket_type_id, *qty, "EXPIRED").await?;
+    }
+
+    tx.commit().await?;
+    for (_, ticket_type_id, qty) in &expired {
+        stock_gate.release(*ticket_type_id, *qty);
     }
+    Ok(expired.len())
+}
+
+async fn tick(db: &Db, cfg: &IntentWorkerConfig, worker_id: &str) -> anyhow::Result<usize> {
+    // Intents being attempted right now are expired by that attempt instead.
+    sqlx::query(
+        r#"update purchase_intents
+           set status='EXPIRED', locked_by=null, locked_until=null, updated_at=now()
+           where id in (
+             select id from purchase_intents
+             where status='ACTIVE' and expires_at <= now()
+             for update skip locked)"#,
+    )
+    .execute(&db.pool)
+    .await?;
+
+    // Expired leases are fair game: their worker died or stalled mid-attempt.
+    let intents: Vec<IntentRow> = sqlx::query_as(
+        r#"update purchase_intents
+           set locked_by = $1, locked_until = now() + make_interval(secs => $2)
+           where id in (
+             select id from purchase_intents
+             where status='ACTIVE' and next_attempt_at <= now()
+               and (locked_until is null or locked_until < now())
+             order by next_attempt_at asc
+             limit $3
+             for update skip locked)
+           returning id, user_id, ticket_type_id, qty, idempotency_key,
+                     max_price_cents, fallback_ticket_type_ids"#,
+    )
+    .bind(worker_id)
+    .bind(cfg.lease_secs as f64)
+    .bind(cfg.batch)
+    .fetch_all(&db.pool)
+    .await?;
 
-    for intent in intents {
-        match try_fulfill_intent(db, &intent).await {
-            Ok(()) => {}
-            Err(AppError::Conflict(msg)) => {
-                // not started / out of stock - keep ACTIVE but record last_error
-                let _ = sqlx::query(
-                    r#"update purchase_intents set last_error=$2, updated_at=now() where id=$1"#,
-                )
-                .bind(intent.id)
-                .bind(msg)
-                .execute(&db.pool)
-                .await;
+    for intent in &intents {
+        let error = match try_fulfill_intent(db, intent, worker_id).await {
+            Ok(()) => continue,
+            // not started / out of stock / too expensive / qty rejected
+            Err(AppError::Conflict(msg)) | Err(AppError::BadRequest(msg)) => msg,
+            Err(AppError::NotFound) => "ticket type not found".into(),
+            // user is at a cap; may clear once another order is canceled
+            Err(AppError::PurchaseLimit(reason)) => {
+                format!("purchase limit exceeded: {}", reason.as_str())
             }
             Err(e) => {
                 error!(intent_id=%intent.id, err=?e, "intent fulfill error");
-                let _ = sqlx::query(
-                    r#"update purchase_intents set last_error=$2, updated_at=now() where id=$1"#,
-                )
-                .bind(intent.id)
-                .bind(format!("{e}"))
-                .execute(&db.pool)
-                .await;
+                format!("{e}")
             }
+        };
+        if let Err(e) = schedule_retry(db, cfg, intent.id, worker_id, &error).await {
+            error!(intent_id=%intent.id, err=?e, "intent retry scheduling failed");
         }
     }
 
+    Ok(intents.len())
+}
+
+/// Records a failed attempt and releases the lease. Before any of the intent's ticket types
+/// opens the attempt is free and the next one waits for the first opening; otherwise the
+/// delay doubles per attempt.
+async fn schedule_retry(
+    db: &Db,
+    cfg: &IntentWorkerConfig,
+    intent_id: Uuid,
+    worker_id: &str,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"with s as (
+             select coalesce(bool_and(t.sale_starts_at > now()), false) as early,
+                    min(t.sale_starts_at) as opens_at
+             from purchase_intents c
+             join ticket_types t
+               on t.id = any(array_prepend(c.ticket_type_id, c.fallback_ticket_type_ids))
+             where c.id = $1
+           )
+           update purchase_intents i
+           set attempts = i.attempts + case when s.early then 0 else 1 end,
+               status = case when not s.early and i.attempts + 1 >= $4 then 'FAILED' else i.status end,
+               next_attempt_at = case
+                 when s.early then s.opens_at
+                 else now() + make_interval(secs => least($5 * power(2, least(i.attempts, 30)), $6) / 1000.0)
+               end,
+               last_error = $3, locked_by = null, locked_until = null, updated_at = now()
+           from s
+           where i.id = $1 and i.locked_by = $2 and i.status = 'ACTIVE'"#,
+    )
+    .bind(intent_id)
+    .bind(worker_id)
+    .bind(error)
+    .bind(cfg.max_attempts)
+    .bind(cfg.backoff_base_ms as f64)
+    .bind(cfg.backoff_max_ms as f64)
+    .execute(&db.pool)
+    .await?;
     Ok(())
 }
 
-async fn try_fulfill_intent(db: &Db, intent: &IntentRow) -> Result<(), AppError> {
+async fn try_fulfill_intent(db: &Db, intent: &IntentRow, worker_id: &str) -> Result<(), AppError> {
     let mut tx = db.pool.begin().await.map_err(AppError::Db)?;
 
-    // If already fulfilled by another tick, stop.
-    let status: Option<(String, Option<Uuid>)> = sqlx::query_as(
-        r#"select status, order_id from purchase_intents where id=$1 for update"#,
+    // Stop if the intent was canceled, or our lease ran out and another worker took over.
+    let status: Option<(String, Option<Uuid>, bool)> = sqlx::query_as(
+        r#"select status, order_id, coalesce(expires_at <= now(), false)
+           from purchase_intents where id=$1 and locked_by=$2 for update"#,
     )
     .bind(intent.id)
+    .bind(worker_id)
     .fetch_optional(&mut *tx)
     .await
     .map_err(AppError::Db)?;
 
-    let Some((status, order_id)) = status else {
+    let Some((status, order_id, expired)) = status else {
         tx.rollback().await.map_err(AppError::Db)?;
         return Ok(());
     };
@@ -90,6 +341,18 @@ async fn try_fulfill_intent(db: &Db, intent: &IntentRow) -> Result<(), AppError>
         return Ok(());
     }
 
+    if expired {
+        sqlx::query(
+            r#"update purchase_intents set status='EXPIRED', locked_by=null, locked_until=null, updated_at=now() where id=$1"#,
+        )
+        .bind(intent.id)
+        .execute(&mut *tx)
+        .await
+        .map_err(AppError::Db)?;
+        tx.commit().await.map_err(AppError::Db)?;
+        return Ok(());
+    }
+
     if let Some(oid) = order_id {
         // double-check order exists
         let ok: Option<(Uuid,)> = sqlx::query_as("select id from orders where id=$1")
@@ -99,7 +362,7 @@ async fn try_fulfill_intent(db: &Db, intent: &IntentRow) -> Result<(), AppError>
             .map_err(AppError::Db)?;
         if ok.is_some() {
             sqlx::query(
-                r#"update purchase_intents set status='FULFILLED', updated_at=now() where id=$1"#,
+                r#"update purchase_intents set status='FULFILLED', locked_by=null, locked_until=null, updated_at=now() where id=$1"#,
             )
             .bind(intent.id)
             .execute(&mut *tx)
@@ -113,18 +376,18 @@ async fn try_fulfill_intent(db: &Db, intent: &IntentRow) -> Result<(), AppError>
     // Try create order using same atomic decrement strategy.
     let now = Utc::now();
 
-    // 1) If user already has active order, attach it and mark intent fulfilled.
+    // 1) If an order was already placed under this intent's idempotency key, attach it.
     if let Some((oid,)) = sqlx::query_as::<_, (Uuid,)>(
-        r#"select id from orders where user_id=$1 and ticket_type_id=$2 and status in ('CREATED','PAID') order by created_at desc limit 1"#,
+        r#"select id from orders where user_id=$1 and idempotency_key=$2"#,
     )
     .bind(intent.user_id)
-    .bind(intent.ticket_type_id)
+    .bind(&intent.idempotency_key)
     .fetch_optional(&mut *tx)
     .await
     .map_err(AppError::Db)?
     {
         sqlx::query(
-            r#"update purchase_intents set status='FULFILLED', order_id=$2, updated_at=now() where id=$1"#,
+            r#"update purchase_intents set status='FULFILLED', order_id=$2, locked_by=null, locked_until=null, updated_at=now() where id=$1"#,
         )
         .bind(intent.id)
         .bind(oid)
@@ -135,71 +398,65 @@ async fn try_fulfill_intent(db: &Db, intent: &IntentRow) -> Result<(), AppError>
         return Ok(());
     }
 
-    // 2) Atomic decrement
-    let price: Option<(i64,)> = sqlx::query_as(
-        r#"update ticket_types
-           set inventory_remaining = inventory_remaining - 1
-           where id = $1
-             and inventory_remaining >= 1
-             and sale_starts_at <= $2
-             and sale_ends_at > $2
-           returning price_cents"#,
-    )
-    .bind(intent.ticket_type_id)
-    .bind(now)
-    .fetch_optional(&mut *tx)
-    .await
-    .map_err(AppError::Db)?;
-
-    let Some((price_cents,)) = price else {
+    // 2) First ticket type that passes the same qty rules and per-user caps as grab, is within
+    //    the price ceiling and has stock.
+    let order_id = Uuid::new_v4();
+    let mut bought = None;
+    let mut last_error = AppError::NotFound;
+    for ticket_type_id in intent.candidates() {
+        match reserve_candidate(&mut tx, intent, ticket_type_id, order_id, now).await {
+            Ok(reserved) => {
+                bought = Some((ticket_type_id, reserved));
+                break;
+            }
+            Err(
+                e @ (AppError::Conflict(_)
+                | AppError::BadRequest(_)
+                | AppError::NotFound
+                | AppError::PurchaseLimit(_)),
+            ) => last_error = e,
+            Err(e) => return Err(e),
+        }
+    }
+    let Some((ticket_type_id, reserved)) = bought else {
         tx.rollback().await.map_err(AppError::Db)?;
-        return Err(AppError::Conflict("out of stock or not in sale window".into()));
+        return Err(last_error);
     };
+    // The price can change between the check and the reservation.
+    if intent
+        .max_price_cents
+        .is_some_and(|max| reserved.price_cents > max)
+    {
+        tx.rollback().await.map_err(AppError::Db)?;
+        return Err(AppError::Conflict("price above max_price_cents".into()));
+    }
 
-    // 3) Insert order, idempotency_key fixed per intent.
-    let order_id = Uuid::new_v4();
-    let inserted = sqlx::query_as::<_, (Uuid,)>(
-        r#"insert into orders (id, user_id, ticket_type_id, qty, amount_cents, status, idempotency_key)
-           values ($1,$2,$3,1,$4,'CREATED',$5)
+    // 3) Insert order, idempotency_key fixed per intent (the intent row lock rules out races).
+    let (oid,) = sqlx::query_as::<_, (Uuid,)>(
+        r#"insert into orders (id, user_id, ticket_type_id, qty, amount_cents, status, idempotency_key, expires_at, intent_reserved_qty)
+           values ($1,$2,$3,$4,$5,'CREATED',$6,$7,$8)
            returning id"#,
     )
     .bind(order_id)
     .bind(intent.user_id)
-    .bind(intent.ticket_type_id)
-    .bind(price_cents)
+    .bind(ticket_type_id)
+    .bind(intent.qty)
+    .bind(reserved.price_cents * intent.qty as i64)
     .bind(&intent.idempotency_key)
+    .bind(reserved.payment_deadline(now))
+    .bind(reserved.intent_reserved_qty)
     .fetch_one(&mut *tx)
-    .await;
-
-    let oid = match inserted {
-        Ok((oid,)) => oid,
-        Err(e) => {
-            if let Some(db_err) = e.as_database_error() {
-                if db_err.constraint() == Some("uq_orders_user_ticket_type_active")
-                    || db_err.constraint() == Some("uq_orders_user_idempotency")
-                {
-                    let (oid,) = sqlx::query_as::<_, (Uuid,)>(
-                        r#"select id from orders where user_id=$1 and ticket_type_id=$2 and status in ('CREATED','PAID') order by created_at desc limit 1"#,
-                    )
-                    .bind(intent.user_id)
-                    .bind(intent.ticket_type_id)
-                    .fetch_one(&mut *tx)
-                    .await
-                    .map_err(AppError::Db)?;
-                    oid
-                } else {
-                    return Err(AppError::Db(e));
-                }
-            } else {
-                return Err(AppError::Db(e));
-            }
-        }
-    };
+    .await
+    .map_err(AppError::Db)?;
+
+    order_state::record_created(&mut tx, oid, &format!("intent:{}", intent.id)).await?;
 
     debug!(intent_id=%intent.id, order_id=%oid, "intent fulfilled");
 
     sqlx::query(
-        r#"update purchase_intents set status='FULFILLED', order_id=$2, last_error=null, updated_at=now() where id=$1"#,
+        r#"update purchase_intents
+           set status='FULFILLED', order_id=$2, last_error=null, locked_by=null, locked_until=null, updated_at=now()
+           where id=$1"#,
     )
     .bind(intent.id)
     .bind(oid)
@@ -210,3 +467,48 @@ async fn try_fulfill_intent(db: &Db, intent: &IntentRow) -> Result<(), AppError>
     tx.commit().await.map_err(AppError::Db)?;
     Ok(())
 }
+
+/// Checks one of the intent's ticket types and takes stock (and, if seated, the best adjacent
+/// seats) from it. The writes happen under a savepoint, so a rejected candidate leaves the
+/// transaction usable for the next.
+async fn reserve_candidate(
+    conn: &mut PgConnection,
+    intent: &IntentRow,
+    ticket_type_id: Uuid,
+    order_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<purchase::Reserved, AppError> {
+    purchase::check_order_qty(conn, ticket_type_id, intent.qty).await?;
+    if let Some(max) = intent.max_price_cents {
+        let price: i64 = sqlx::query_scalar("select price_cents from ticket_types where id=$1")
+            .bind(ticket_type_id)
+            .fetch_one(&mut *conn)
+            .await?;
+        if price > max {
+            return Err(AppError::Conflict("price above max_price_cents".into()));
+        }
+    }
+    purchase::check_purchase_limits(conn, intent.user_id, ticket_type_id, intent.qty).await?;
+
+    let mut savepoint = conn.begin().await?;
+    let reserved = purchase::reserve_stock(
+        &mut savepoint,
+        ticket_type_id,
+        intent.qty,
+        purchase::Holder::Order(order_id),
+        now,
+        purchase::Buyer::Intent,
+    )
+    .await?
+    .ok_or_else(|| AppError::Conflict("out of stock or not in sale window".into()))?;
+    seats::assign(
+        &mut savepoint,
+        ticket_type_id,
+        purchase::Holder::Order(order_id),
+        intent.qty,
+        &[],
+    )
+    .await?;
+    savepoint.commit().await?;
+    Ok(reserved)
+}
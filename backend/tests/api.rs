@@ -2,26 +2,98 @@ use chrono::{Duration, Utc};
 use reqwest::Client;
 use serde_json::json;
 use sqlx::PgPool;
-use ticket_seckill_backend::{app, config::Config, db::Db};
+use ticket_seckill_backend::{
+    abuse, app, auth,
+    config::{self, Config, IntentWorkerConfig, RateLimitPolicy, RateLimits},
+    db::Db,
+    payments, worker,
+};
+use tokio::sync::{Mutex, MutexGuard};
 
-async fn setup() -> (String, PgPool) {
+// Tests share one database and truncate it in `setup`, so they run one at a time.
+static DB_LOCK: Mutex<()> = Mutex::const_new(());
+
+const WEBHOOK_SECRET: &str = "test-webhook-secret";
+const ADMIN_USERNAME: &str = "admin";
+const ADMIN_PASSWORD: &str = "test-admin-password";
+
+fn unlimited_rate_limits() -> RateLimits {
+    let unlimited = RateLimitPolicy {
+        per_second: 10_000.0,
+        burst: 10_000,
+    };
+    RateLimits {
+        default: unlimited,
+        read: unlimited,
+        grab: unlimited,
+        auth: unlimited,
+    }
+}
+
+fn test_config(database_url: String) -> Config {
+    Config {
+        app_env: "test".into(),
+        server_addr: "127.0.0.1:0".parse().unwrap(),
+        database_url,
+        rate_limits: unlimited_rate_limits(),
+        intent_worker: IntentWorkerConfig {
+            poll_ms: 50,
+            batch: 50,
+            lease_secs: 30,
+            max_attempts: 10,
+            backoff_base_ms: 50,
+            backoff_max_ms: 1000,
+        },
+        intent_worker_embedded: true,
+        order_reaper_interval_ms: 100,
+        hold_ttl_secs: 600,
+        hold_max_ttl_secs: 1800,
+        hold_sweep_interval_ms: 100,
+        // Effectively never: a refresh racing a grab or cancel makes the gate briefly
+        // inaccurate, which tests asserting exact outcomes can't tolerate.
+        stock_gate_refresh_ms: 60_000,
+        queue_tick_ms: 100,
+        queue_admission_ttl_secs: 60,
+        grab_pow_difficulty: 0,
+        grab_challenge_ttl_secs: 120,
+        // Every test client logs in from 127.0.0.1.
+        login_max_accounts_per_ip: 0,
+        login_ip_window_secs: 3600,
+        blocklist_refresh_ms: 60_000,
+        payment_provider: "mock".into(),
+        mock_payment_mode: "succeed".into(),
+        payment_webhook_secret: Some(WEBHOOK_SECRET.into()),
+        payment_webhook_tolerance_secs: 300,
+        jwt_secret: "test-jwt-secret".into(),
+        access_token_ttl_secs: 900,
+        refresh_token_ttl_secs: 3600,
+        // `login` below relies on passwordless dev login.
+        auth_passwordless: true,
+        admin_bootstrap_username: Some(ADMIN_USERNAME.into()),
+        admin_bootstrap_password: Some(ADMIN_PASSWORD.into()),
+    }
+}
+
+async fn setup() -> (String, PgPool, MutexGuard<'static, ()>) {
+    setup_with(|_| {}).await
+}
+
+async fn setup_with(
+    configure: impl FnOnce(&mut Config),
+) -> (String, PgPool, MutexGuard<'static, ()>) {
+    let guard = DB_LOCK.lock().await;
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set for tests");
     let db = Db::connect(&database_url).await.unwrap();
     db.migrate().await.unwrap();
 
     // Clean between tests.
-    sqlx::query("truncate table purchase_intents, orders, users, ticket_types, events restart identity cascade")
+    sqlx::query("truncate table abuse_events, blocklist, grab_challenge_redemptions, login_ips, queue_entries, inventory_movements, admin_audit_log, refresh_tokens, order_events, payment_webhook_events, payments, refunds, purchase_intents, promo_codes, orders, users, ticket_types, events restart identity cascade")
         .execute(&db.pool)
         .await
         .unwrap();
 
-    let cfg = Config {
-        app_env: "test".into(),
-        server_addr: "127.0.0.1:0".parse().unwrap(),
-        database_url,
-        rate_limit_rps: 10_000,
-        rate_limit_burst: 10_000,
-    };
+    let mut cfg = test_config(database_url);
+    configure(&mut cfg);
 
     let listener = tokio::net::TcpListener::bind(cfg.server_addr).await.unwrap();
     let addr = listener.local_addr().unwrap();
@@ -36,7 +108,7 @@ async fn setup() -> (String, PgPool) {
         .unwrap();
     });
 
-    (format!("http://{}", addr), db.pool)
+    (format!("http://{}", addr), db.pool, guard)
 }
 
 async fn login(client: &Client, base: &str, username: &str) -> String {
@@ -54,19 +126,36 @@ async fn login(client: &Client, base: &str, username: &str) -> String {
     res["token"].as_str().unwrap().to_string()
 }
 
+async fn admin_token(client: &Client, base: &str) -> String {
+    let res = client
+        .post(format!("{}/api/auth/login", base))
+        .json(&json!({"username": ADMIN_USERNAME, "password": ADMIN_PASSWORD}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    res["token"].as_str().unwrap().to_string()
+}
+
 #[tokio::test]
 async fn grab_is_idempotent_and_atomic() {
-    let (base, pool) = setup().await;
+    let (base, pool, _guard) = setup().await;
     let client = Client::new();
 
     let token_u1 = login(&client, &base, "u1").await;
     let token_u2 = login(&client, &base, "u2").await;
+    let admin = admin_token(&client, &base).await;
 
     // Create event
     let starts_at = Utc::now();
     let ends_at = starts_at + Duration::hours(2);
     let ev = client
         .post(format!("{}/api/admin/events", base))
+        .bearer_auth(&admin)
         .json(&json!({"name":"concert","starts_at":starts_at,"ends_at":ends_at}))
         .send()
         .await
@@ -83,6 +172,7 @@ async fn grab_is_idempotent_and_atomic() {
     let sale_ends_at = Utc::now() + Duration::minutes(30);
     let tt = client
         .post(format!("{}/api/admin/events/{}/ticket_types", base, event_id))
+        .bearer_auth(&admin)
         .json(&json!({
             "name":"A",
             "price_cents":100,
@@ -152,3 +242,3895 @@ async fn grab_is_idempotent_and_atomic() {
         .unwrap();
     assert_eq!(remaining, 0);
 }
+
+fn merge(mut base: serde_json::Value, extra: serde_json::Value) -> serde_json::Value {
+    base.as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    base
+}
+
+async fn create_event(client: &Client, base: &str, event: serde_json::Value) -> String {
+    let starts_at = Utc::now();
+    let body = merge(
+        json!({"name":"concert","starts_at":starts_at,"ends_at":starts_at + Duration::hours(2)}),
+        event,
+    );
+    let ev = client
+        .post(format!("{}/api/admin/events", base))
+        .bearer_auth(admin_token(client, base).await)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    ev["id"].as_str().unwrap().to_string()
+}
+
+async fn create_ticket_type(
+    client: &Client,
+    base: &str,
+    event_id: &str,
+    ticket_type: serde_json::Value,
+) -> String {
+    let body = merge(
+        json!({
+            "name":"A",
+            "price_cents":100,
+            "inventory_total":10,
+            "sale_starts_at": Utc::now() - Duration::minutes(1),
+            "sale_ends_at": Utc::now() + Duration::minutes(30)
+        }),
+        ticket_type,
+    );
+    let tt = client
+        .post(format!(
+            "{}/api/admin/events/{}/ticket_types",
+            base, event_id
+        ))
+        .bearer_auth(admin_token(client, base).await)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    tt["id"].as_str().unwrap().to_string()
+}
+
+async fn create_on_sale_ticket_type(
+    client: &Client,
+    base: &str,
+    ticket_type: serde_json::Value,
+) -> String {
+    let event_id = create_event(client, base, json!({})).await;
+    create_ticket_type(client, base, &event_id, ticket_type).await
+}
+
+async fn grab(
+    client: &Client,
+    base: &str,
+    token: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    client
+        .post(format!("{}/api/tickets/grab", base))
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Opens a payment for the order and completes the mock checkout.
+async fn pay(client: &Client, base: &str, token: &str, order_id: &str) -> reqwest::Response {
+    let session = client
+        .post(format!("{}/api/orders/{}/pay", base, order_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    client
+        .post(format!(
+            "{}{}",
+            base,
+            session["checkout_url"].as_str().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Runs the requests concurrently on the test runtime.
+async fn join_all<F: std::future::Future<Output = reqwest::Response> + Send + 'static>(
+    futs: impl Iterator<Item = F>,
+) -> Vec<reqwest::Response> {
+    let handles: Vec<_> = futs.map(tokio::spawn).collect();
+    let mut out = Vec::new();
+    for h in handles {
+        out.push(h.await.unwrap());
+    }
+    out
+}
+
+#[tokio::test]
+async fn grab_multiple_qty_respects_max_per_order() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let token = login(&client, &base, "family").await;
+    let ticket_type_id = create_on_sale_ticket_type(
+        &client,
+        &base,
+        json!({"price_cents": 250, "inventory_total": 5, "max_qty_per_order": 4}),
+    )
+    .await;
+
+    // Above max_qty_per_order is rejected without touching stock.
+    let resp = client
+        .post(format!("{}/api/tickets/grab", base))
+        .bearer_auth(&token)
+        .json(&json!({"ticket_type_id": ticket_type_id, "qty": 5}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+
+    let order = client
+        .post(format!("{}/api/tickets/grab", base))
+        .bearer_auth(&token)
+        .json(&json!({"ticket_type_id": ticket_type_id, "qty": 4}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(order["qty"], 4);
+    assert_eq!(order["amount_cents"], 1000);
+
+    let remaining: i32 =
+        sqlx::query_scalar("select inventory_remaining from ticket_types where id = $1")
+            .bind(uuid::Uuid::parse_str(&ticket_type_id).unwrap())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 1);
+
+    // Not enough stock left for another pair.
+    let other = login(&client, &base, "friend").await;
+    let resp = client
+        .post(format!("{}/api/tickets/grab", base))
+        .bearer_auth(&other)
+        .json(&json!({"ticket_type_id": ticket_type_id, "qty": 2}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn per_user_limits_hold_across_orders_and_under_concurrency() {
+    let (base, _pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let token = login(&client, &base, "collector").await;
+    let event_id = create_event(&client, &base, json!({"max_per_user": 4})).await;
+    let tt_a = create_ticket_type(&client, &base, &event_id, json!({"max_per_user": 3})).await;
+    let tt_b = create_ticket_type(&client, &base, &event_id, json!({"name": "B"})).await;
+
+    // Ten concurrent single-ticket grabs: only the per-ticket-type cap (3) may succeed.
+    let results = join_all((0..10).map(|_| {
+        let (client, base, token, tt_a) =
+            (client.clone(), base.clone(), token.clone(), tt_a.clone());
+        async move {
+            grab(
+                &client,
+                &base,
+                &token,
+                json!({"ticket_type_id": tt_a, "qty": 1}),
+            )
+            .await
+        }
+    }))
+    .await;
+    let ok = results.iter().filter(|r| r.status().is_success()).count();
+    assert_eq!(ok, 3);
+
+    let resp = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": tt_a, "qty": 1}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let body = resp.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["reason"], "ticket_type_limit");
+
+    // Event-wide cap (4) counts tickets across ticket types.
+    let resp = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": tt_b, "qty": 2}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let body = resp.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["reason"], "event_limit");
+
+    let resp = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": tt_b, "qty": 1}),
+    )
+    .await;
+    assert!(resp.status().is_success());
+
+    // Without a cap a user still gets one active order per ticket type, of any qty.
+    let tt_c = create_on_sale_ticket_type(&client, &base, json!({})).await;
+    let results = join_all((0..5).map(|_| {
+        let (client, base, token, tt_c) =
+            (client.clone(), base.clone(), token.clone(), tt_c.clone());
+        async move {
+            grab(
+                &client,
+                &base,
+                &token,
+                json!({"ticket_type_id": tt_c, "qty": 2}),
+            )
+            .await
+        }
+    }))
+    .await;
+    assert_eq!(
+        results.iter().filter(|r| r.status().is_success()).count(),
+        1
+    );
+
+    let resp = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": tt_c, "qty": 1}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let body = resp.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["reason"], "ticket_type_limit");
+}
+
+#[tokio::test]
+async fn unpaid_orders_expire_and_return_stock() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let token = login(&client, &base, "abandoner").await;
+    let ticket_type_id = create_on_sale_ticket_type(
+        &client,
+        &base,
+        json!({"inventory_total": 2, "payment_window_secs": 1}),
+    )
+    .await;
+
+    let order = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": ticket_type_id, "qty": 2}),
+    )
+    .await
+    .error_for_status()
+    .unwrap()
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    assert!(order["expires_at"].is_string());
+    let order_id = uuid::Uuid::parse_str(order["id"].as_str().unwrap()).unwrap();
+    client
+        .post(format!("{}/api/orders/{}/pay", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    tokio::time::sleep(std::time::Duration::from_millis(1500)).await;
+
+    let (status, canceled): (String, bool) =
+        sqlx::query_as("select status, canceled_at is not null from orders where id = $1")
+            .bind(order_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(status, "CANCELED");
+    assert!(canceled);
+    let payment_status: String =
+        sqlx::query_scalar("select status from payments where order_id = $1")
+            .bind(order_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(payment_status, "FAILED");
+
+    let remaining: i32 =
+        sqlx::query_scalar("select inventory_remaining from ticket_types where id = $1")
+            .bind(uuid::Uuid::parse_str(&ticket_type_id).unwrap())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 2);
+
+    // Paying after expiry is rejected.
+    let resp = client
+        .post(format!("{}/api/orders/{}/pay", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn cancel_order_restores_stock_and_is_idempotent() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let token = login(&client, &base, "changed-mind").await;
+    let ticket_type_id =
+        create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 3})).await;
+
+    let order = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": ticket_type_id, "qty": 3}),
+    )
+    .await
+    .error_for_status()
+    .unwrap()
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    let order_id = order["id"].as_str().unwrap();
+    let session = client
+        .post(format!("{}/api/orders/{}/pay", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+
+    for _ in 0..2 {
+        let canceled = client
+            .post(format!("{}/api/orders/{}/cancel", base, order_id))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(canceled["status"], "CANCELED");
+    }
+
+    // The open payment is closed with the order and voided at the provider.
+    let payment = client
+        .post(format!(
+            "{}{}",
+            base,
+            session["checkout_url"].as_str().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(payment["status"], "FAILED");
+    assert_eq!(payment["failure_reason"], "order canceled");
+    assert_eq!(order_status(&pool, order_id).await, "CANCELED");
+
+    // Stock returned exactly once.
+    let remaining: i32 =
+        sqlx::query_scalar("select inventory_remaining from ticket_types where id = $1")
+            .bind(uuid::Uuid::parse_str(&ticket_type_id).unwrap())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 3);
+
+    // Paid orders can't be canceled.
+    let order = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": ticket_type_id, "qty": 1}),
+    )
+    .await
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    let order_id = order["id"].as_str().unwrap();
+    pay(&client, &base, &token, order_id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let resp = client
+        .post(format!("{}/api/orders/{}/cancel", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+}
+
+#[tokio::test]
+async fn refunds_are_recorded_and_serialized_per_order() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let token = login(&client, &base, "refundee").await;
+    let ticket_type_id =
+        create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 2})).await;
+
+    let order = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": ticket_type_id, "qty": 2}),
+    )
+    .await
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    let order_id = order["id"].as_str().unwrap().to_string();
+    let refunds_url = format!("{}/api/admin/orders/{}/refunds", base, order_id);
+    let admin = admin_token(&client, &base).await;
+
+    // Unpaid orders can't be refunded.
+    let resp = client
+        .post(&refunds_url)
+        .bearer_auth(&admin)
+        .json(&json!({"reason": "early"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+
+    pay(&client, &base, &token, &order_id)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let partial = client
+        .post(&refunds_url)
+        .bearer_auth(&admin)
+        .json(&json!({"amount_cents": 50, "reason": "late show"}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(partial["order"]["status"], "PAID");
+    assert_eq!(partial["order"]["refunded_cents"], 50);
+
+    // Concurrent full refunds: exactly one wins, stock is returned once.
+    let results = join_all((0..5).map(|_| {
+        let (client, url, admin) = (client.clone(), refunds_url.clone(), admin.clone());
+        async move {
+            client
+                .post(url)
+                .bearer_auth(admin)
+                .json(&json!({"reason": "canceled show", "restock": true}))
+                .send()
+                .await
+                .unwrap()
+        }
+    }))
+    .await;
+    assert_eq!(
+        results.iter().filter(|r| r.status().is_success()).count(),
+        1
+    );
+    assert!(results
+        .iter()
+        .filter(|r| !r.status().is_success())
+        .all(|r| r.status().as_u16() == 409));
+
+    let ledger = client
+        .get(&refunds_url)
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    assert_eq!(ledger.len(), 2);
+    assert_eq!(ledger[1]["amount_cents"], 150);
+    assert_eq!(ledger[1]["restocked_qty"], 2);
+    assert_eq!(ledger[1]["actor"], ADMIN_USERNAME);
+
+    let (status, remaining): (String, i32) = sqlx::query_as(
+        "select o.status, t.inventory_remaining from orders o join ticket_types t on t.id = o.ticket_type_id where o.id = $1",
+    )
+    .bind(uuid::Uuid::parse_str(&order_id).unwrap())
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(status, "REFUNDED");
+    assert_eq!(remaining, 2);
+
+    // Free tickets are refunded for 0 and still go back to stock.
+    let free_tt = create_on_sale_ticket_type(
+        &client,
+        &base,
+        json!({"price_cents": 0, "inventory_total": 1}),
+    )
+    .await;
+    let free = grab(&client, &base, &token, json!({"ticket_type_id": free_tt}))
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let free_id = free["id"].as_str().unwrap();
+    pay(&client, &base, &token, free_id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let free_refunds_url = format!("{}/api/admin/orders/{}/refunds", base, free_id);
+    let resp = client
+        .post(&free_refunds_url)
+        .bearer_auth(&admin)
+        .json(&json!({"amount_cents": 1, "reason": "canceled show"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+    let refunded = client
+        .post(&free_refunds_url)
+        .bearer_auth(&admin)
+        .json(&json!({"reason": "canceled show", "restock": true}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(refunded["refund"]["amount_cents"], 0);
+    assert_eq!(refunded["order"]["status"], "REFUNDED");
+    let remaining: i32 =
+        sqlx::query_scalar("select inventory_remaining from ticket_types where id = $1")
+            .bind(uuid::Uuid::parse_str(&free_tt).unwrap())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 1);
+}
+
+async fn order_status(pool: &PgPool, order_id: &str) -> String {
+    sqlx::query_scalar("select status from orders where id = $1")
+        .bind(uuid::Uuid::parse_str(order_id).unwrap())
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn order_is_paid_only_after_provider_confirms() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let token = login(&client, &base, "payer").await;
+    let ticket_type_id = create_on_sale_ticket_type(&client, &base, json!({})).await;
+    let order = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": ticket_type_id}),
+    )
+    .await
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    let order_id = order["id"].as_str().unwrap();
+
+    // An attempt whose provider session was never opened doesn't block paying for long.
+    let stale = uuid::Uuid::new_v4();
+    sqlx::query(
+        r#"insert into payments (id, order_id, provider, status, amount_cents, created_at)
+           values ($1, $2::uuid, 'mock', 'PENDING', 100, now() - interval '2 minutes')"#,
+    )
+    .bind(stale)
+    .bind(order_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let session = client
+        .post(format!("{}/api/orders/{}/pay", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(session["status"], "PENDING");
+    assert_ne!(session["id"], stale.to_string());
+    assert!(session["provider_ref"].is_string());
+    assert_eq!(order_status(&pool, order_id).await, "CREATED");
+    let stale_status: String = sqlx::query_scalar("select status from payments where id = $1")
+        .bind(stale)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(stale_status, "FAILED");
+
+    // Paying again while the attempt is open returns the same session.
+    let again = client
+        .post(format!("{}/api/orders/{}/pay", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(again["id"], session["id"]);
+
+    let completed = client
+        .post(format!(
+            "{}{}",
+            base,
+            session["checkout_url"].as_str().unwrap()
+        ))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(completed["status"], "SUCCEEDED");
+    assert_eq!(order_status(&pool, order_id).await, "PAID");
+}
+
+#[tokio::test]
+async fn failed_and_timed_out_payments_leave_order_unpaid() {
+    for (mode, payment_status, http_status) in
+        [("fail", "FAILED", 200), ("timeout", "PENDING", 202)]
+    {
+        let (base, pool, _guard) = setup_with(|cfg| cfg.mock_payment_mode = mode.into()).await;
+        let client = Client::new();
+
+        let token = login(&client, &base, "unlucky").await;
+        let ticket_type_id = create_on_sale_ticket_type(&client, &base, json!({})).await;
+        let order = grab(
+            &client,
+            &base,
+            &token,
+            json!({"ticket_type_id": ticket_type_id}),
+        )
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+        let order_id = order["id"].as_str().unwrap();
+
+        let resp = pay(&client, &base, &token, order_id).await;
+        assert_eq!(resp.status().as_u16(), http_status);
+        let payment = resp.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(payment["status"], payment_status);
+        assert_eq!(order_status(&pool, order_id).await, "CREATED");
+    }
+}
+
+async fn send_webhook(
+    client: &Client,
+    base: &str,
+    timestamp: i64,
+    secret: &str,
+    event: serde_json::Value,
+) -> reqwest::Response {
+    let body = serde_json::to_vec(&event).unwrap();
+    client
+        .post(format!("{}/api/payments/webhook", base))
+        .header("content-type", "application/json")
+        .header("x-webhook-timestamp", timestamp.to_string())
+        .header(
+            "x-webhook-signature",
+            payments::sign_webhook(secret, timestamp, &body),
+        )
+        .body(body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn payment_webhook_is_signed_deduped_and_never_revives_canceled_orders() {
+    // The reaper stays out of the way so the expired order below is still CREATED.
+    let (base, pool, _guard) = setup_with(|cfg| cfg.order_reaper_interval_ms = 3_600_000).await;
+    let client = Client::new();
+
+    let token = login(&client, &base, "webhooked").await;
+    let ticket_type_id =
+        create_on_sale_ticket_type(&client, &base, json!({"max_per_user": 10})).await;
+
+    let mut sessions = Vec::new();
+    for _ in 0..3 {
+        let order = grab(
+            &client,
+            &base,
+            &token,
+            json!({"ticket_type_id": ticket_type_id}),
+        )
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+        let session = client
+            .post(format!(
+                "{}/api/orders/{}/pay",
+                base,
+                order["id"].as_str().unwrap()
+            ))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        sessions.push(session);
+    }
+    let event = |id: &str, session: &serde_json::Value| {
+        json!({
+            "event_id": id,
+            "provider": "mock",
+            "provider_ref": session["provider_ref"],
+            "type": "payment.succeeded"
+        })
+    };
+    let now = Utc::now().timestamp();
+
+    // Bad signature and stale timestamp are rejected.
+    let resp = send_webhook(
+        &client,
+        &base,
+        now,
+        "wrong-secret",
+        event("evt_1", &sessions[0]),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 401);
+    let resp = send_webhook(
+        &client,
+        &base,
+        now - 3600,
+        WEBHOOK_SECRET,
+        event("evt_1", &sessions[0]),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let ack = send_webhook(
+        &client,
+        &base,
+        now,
+        WEBHOOK_SECRET,
+        event("evt_1", &sessions[0]),
+    )
+    .await
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    assert_eq!(ack["status"], "applied");
+    let order_id = sessions[0]["order_id"].as_str().unwrap();
+    assert_eq!(order_status(&pool, order_id).await, "PAID");
+
+    let ack = send_webhook(
+        &client,
+        &base,
+        now,
+        WEBHOOK_SECRET,
+        event("evt_1", &sessions[0]),
+    )
+    .await
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    assert_eq!(ack["status"], "duplicate");
+
+    // A success arriving after the buyer canceled must not move the order to PAID.
+    let canceled_id = sessions[1]["order_id"].as_str().unwrap();
+    client
+        .post(format!("{}/api/orders/{}/cancel", base, canceled_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let resp = send_webhook(
+        &client,
+        &base,
+        now,
+        WEBHOOK_SECRET,
+        event("evt_2", &sessions[1]),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    assert_eq!(order_status(&pool, canceled_id).await, "CANCELED");
+    let payment_status: String =
+        sqlx::query_scalar("select status from payments where order_id = $1")
+            .bind(uuid::Uuid::parse_str(canceled_id).unwrap())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(payment_status, "SUCCEEDED_ORPHANED");
+
+    // Nor may one arriving after the payment deadline; the captured money is flagged instead.
+    let expired_id = sessions[2]["order_id"].as_str().unwrap();
+    sqlx::query("update orders set expires_at = now() - interval '1 second' where id = $1")
+        .bind(uuid::Uuid::parse_str(expired_id).unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+    let ack = send_webhook(
+        &client,
+        &base,
+        now,
+        WEBHOOK_SECRET,
+        event("evt_3", &sessions[2]),
+    )
+    .await
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    assert_eq!(ack["status"], "applied");
+    assert_eq!(order_status(&pool, expired_id).await, "CREATED");
+    let payment_status: String =
+        sqlx::query_scalar("select status from payments where order_id = $1")
+            .bind(uuid::Uuid::parse_str(expired_id).unwrap())
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(payment_status, "SUCCEEDED_ORPHANED");
+}
+
+#[tokio::test]
+async fn order_history_records_every_transition() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let token = login(&client, &base, "historian").await;
+    let ticket_type_id =
+        create_on_sale_ticket_type(&client, &base, json!({"max_per_user": 10})).await;
+    let order = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": ticket_type_id}),
+    )
+    .await
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    let order_id = order["id"].as_str().unwrap();
+    pay(&client, &base, &token, order_id)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // PAID -> CANCELED is not a legal move.
+    let resp = client
+        .post(format!("{}/api/orders/{}/cancel", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+    let body = resp.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["reason"], "invalid_transition");
+
+    let history = client
+        .get(format!("{}/api/orders/{}/history", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    let moves: Vec<_> = history
+        .iter()
+        .map(|e| (e["from_status"].clone(), e["to_status"].clone()))
+        .collect();
+    assert_eq!(
+        moves,
+        vec![
+            (serde_json::Value::Null, json!("CREATED")),
+            (json!("CREATED"), json!("PAID")),
+        ]
+    );
+    assert_eq!(history[1]["actor"], "provider:mock");
+
+    // Events written in one transaction share created_at and still come back in order.
+    let mut tx = pool.begin().await.unwrap();
+    for (id, actor) in [
+        ("ffffffff-ffff-4fff-bfff-ffffffffffff", "test:first"),
+        ("00000000-0000-4000-8000-000000000000", "test:second"),
+    ] {
+        sqlx::query(
+            r#"insert into order_events (id, order_id, from_status, to_status, actor)
+               values ($1::uuid, $2::uuid, 'PAID', 'PAID', $3)"#,
+        )
+        .bind(id)
+        .bind(order_id)
+        .bind(actor)
+        .execute(&mut *tx)
+        .await
+        .unwrap();
+    }
+    tx.commit().await.unwrap();
+    let history = client
+        .get(format!("{}/api/orders/{}/history", base, order_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    let actors: Vec<_> = history.iter().skip(1).map(|e| e["actor"].clone()).collect();
+    assert_eq!(
+        actors,
+        vec![
+            json!("provider:mock"),
+            json!("test:first"),
+            json!("test:second"),
+        ]
+    );
+}
+
+async fn auth_post(
+    client: &Client,
+    base: &str,
+    path: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    client
+        .post(format!("{}/api/auth/{}", base, path))
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn password_login_and_refresh_token_rotation() {
+    let (base, _pool, _guard) = setup_with(|cfg| cfg.auth_passwordless = false).await;
+    let client = Client::new();
+
+    let creds = json!({"username": "alice", "password": "correct horse"});
+    let resp = auth_post(&client, &base, "register", creds.clone()).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let resp = auth_post(&client, &base, "register", creds.clone()).await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let resp = auth_post(
+        &client,
+        &base,
+        "register",
+        json!({"username": "bob", "password": "short"}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 400);
+
+    let resp = auth_post(&client, &base, "login", json!({"username": "alice"})).await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let resp = auth_post(
+        &client,
+        &base,
+        "login",
+        json!({"username": "alice", "password": "wrong password"}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 401);
+    let resp = auth_post(
+        &client,
+        &base,
+        "login",
+        json!({"username": "nobody", "password": "correct horse"}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let session = auth_post(&client, &base, "login", creds.clone())
+        .await
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    client
+        .get(format!("{}/api/orders/me", base))
+        .bearer_auth(session["token"].as_str().unwrap())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Rotation: the old refresh token is single-use.
+    let first = session["refresh_token"].clone();
+    let rotated = auth_post(&client, &base, "refresh", json!({"refresh_token": first}))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_ne!(rotated["refresh_token"], first);
+    assert_eq!(rotated["username"], "alice");
+
+    // Replaying the old token revokes the whole family, including the rotated token.
+    let resp = auth_post(&client, &base, "refresh", json!({"refresh_token": first})).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    let resp = auth_post(
+        &client,
+        &base,
+        "refresh",
+        json!({"refresh_token": rotated["refresh_token"]}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    // Logout revokes the session's refresh token.
+    let session = auth_post(&client, &base, "login", creds.clone())
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let resp = auth_post(
+        &client,
+        &base,
+        "logout",
+        json!({"refresh_token": session["refresh_token"]}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 204);
+    let resp = auth_post(
+        &client,
+        &base,
+        "refresh",
+        json!({"refresh_token": session["refresh_token"]}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn passwordless_login_is_dev_only_and_never_bypasses_a_password() {
+    let (base, _pool, _guard) = setup().await;
+    let client = Client::new();
+
+    auth_post(
+        &client,
+        &base,
+        "register",
+        json!({"username": "carol", "password": "correct horse"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let resp = auth_post(&client, &base, "login", json!({"username": "carol"})).await;
+    assert_eq!(resp.status().as_u16(), 401);
+
+    let database_url = std::env::var("DATABASE_URL").unwrap();
+    let prod = |configure: fn(&mut Config)| {
+        let mut cfg = test_config(database_url.clone());
+        cfg.app_env = "prod".into();
+        cfg.auth_passwordless = false;
+        cfg.payment_provider = "gateway".into();
+        configure(&mut cfg);
+        cfg.validate()
+    };
+    assert!(prod(|_| {}).is_ok());
+    assert!(prod(|cfg| cfg.jwt_secret = config::DEFAULT_JWT_SECRET.into()).is_err());
+    assert!(prod(|cfg| cfg.auth_passwordless = true).is_err());
+    assert!(prod(|cfg| cfg.payment_provider = "mock".into()).is_err());
+}
+
+#[tokio::test]
+async fn admin_routes_require_the_admin_role() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let starts_at = Utc::now();
+    let event =
+        json!({"name":"concert","starts_at":starts_at,"ends_at":starts_at + Duration::hours(2)});
+    let user = login(&client, &base, "mallory").await;
+
+    for path in ["/api/admin/events", "/admin/events"] {
+        let resp = client
+            .post(format!("{}{}", base, path))
+            .json(&event)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 401);
+        let resp = client
+            .post(format!("{}{}", base, path))
+            .bearer_auth(&user)
+            .json(&event)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 403);
+    }
+
+    let admin = admin_token(&client, &base).await;
+    client
+        .post(format!("{}/admin/events", base))
+        .bearer_auth(&admin)
+        .json(&event)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Promotion takes effect on the next refresh, without logging in again.
+    let session = client
+        .post(format!("{}/api/auth/login", base))
+        .json(&json!({"username": "mallory"}))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(session["role"], "user");
+    sqlx::query("update users set role = 'admin' where username = 'mallory'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    let refreshed = client
+        .post(format!("{}/api/auth/refresh", base))
+        .json(&json!({"refresh_token": session["refresh_token"]}))
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(refreshed["role"], "admin");
+    client
+        .post(format!("{}/api/admin/events", base))
+        .bearer_auth(refreshed["token"].as_str().unwrap())
+        .json(&event)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Bootstrapping an admin never takes over someone else's account.
+    sqlx::query("update users set password_hash = $1 where username = 'mallory'")
+        .bind(auth::hash_password("mallory's own").unwrap())
+        .execute(&pool)
+        .await
+        .unwrap();
+    sqlx::query("update users set role = 'user' where username = 'mallory'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(
+        auth::bootstrap_admin(&pool, "mallory", Some("configured password"))
+            .await
+            .is_err()
+    );
+    let role: String = sqlx::query_scalar("select role from users where username = 'mallory'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(role, "user");
+    auth::bootstrap_admin(&pool, "mallory", Some("mallory's own"))
+        .await
+        .unwrap();
+    let mut cfg = test_config(std::env::var("DATABASE_URL").unwrap());
+    cfg.admin_bootstrap_username = Some("mallory".into());
+    cfg.admin_bootstrap_password = None;
+    sqlx::query("update users set role = 'user'")
+        .execute(&pool)
+        .await
+        .unwrap();
+    assert!(auth::bootstrap_first_admin(&pool, &cfg).await.is_err());
+}
+
+#[tokio::test]
+async fn admin_edits_are_validated_guarded_and_audited() {
+    let (base, _pool, _guard) = setup().await;
+    let client = Client::new();
+    let admin = admin_token(&client, &base).await;
+    let token = login(&client, &base, "editor-buyer").await;
+
+    let patch = |path: String, body: serde_json::Value| {
+        client
+            .patch(format!("{}{}", base, path))
+            .bearer_auth(&admin)
+            .json(&body)
+            .send()
+    };
+
+    let event_id = create_event(&client, &base, json!({"max_per_user": 4})).await;
+    let ticket_type_id = create_ticket_type(&client, &base, &event_id, json!({})).await;
+
+    let resp = patch(
+        format!("/api/admin/events/{}", event_id),
+        json!({"ends_at": Utc::now() - Duration::days(1)}),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+    let event = patch(
+        format!("/api/admin/events/{}", event_id),
+        json!({"name": "renamed", "max_per_user": null}),
+    )
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap()
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    assert_eq!(event["name"], "renamed");
+    assert_eq!(event["max_per_user"], serde_json::Value::Null);
+
+    // No orders yet: the price is freely editable.
+    let tt_path = format!("/api/admin/ticket-types/{}", ticket_type_id);
+    let tt = patch(tt_path.clone(), json!({"price_cents": 120}))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(tt["price_cents"], 120);
+
+    grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": ticket_type_id}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // With orders, price and sale window are locked unless forced; other fields are not.
+    let resp = patch(tt_path.clone(), json!({"price_cents": 150}))
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+    let resp = patch(
+        tt_path.clone(),
+        json!({"sale_ends_at": Utc::now() + Duration::hours(5)}),
+    )
+    .await
+    .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+    patch(
+        tt_path.clone(),
+        json!({"name": "B", "max_qty_per_order": 2}),
+    )
+    .await
+    .unwrap()
+    .error_for_status()
+    .unwrap();
+    let tt = patch(tt_path.clone(), json!({"price_cents": 150, "force": true}))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(tt["price_cents"], 150);
+
+    let log = client
+        .get(format!(
+            "{}/api/admin/audit-log?entity_id={}",
+            base, ticket_type_id
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    assert_eq!(log.len(), 3);
+    assert_eq!(log[0]["forced"], true);
+    assert_eq!(log[0]["actor"], ADMIN_USERNAME);
+    assert_eq!(log[0]["details"]["before"]["price_cents"], 120);
+    assert!(log[1..].iter().all(|e| e["forced"] == false));
+
+    // Events with orders can't be deleted, only archived; archived events can't be bought from.
+    let event_path = format!("{}/api/admin/events/{}", base, event_id);
+    let resp = client
+        .delete(&event_path)
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+    client
+        .post(format!("{}/archive", event_path))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let events = client
+        .get(format!("{}/api/events", base))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    assert!(events.iter().all(|e| e["id"] != event_id.as_str()));
+    let resp = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": ticket_type_id}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+
+    let empty_event = create_event(&client, &base, json!({})).await;
+    let empty_tt = create_ticket_type(&client, &base, &empty_event, json!({})).await;
+    let resp = client
+        .delete(format!("{}/api/admin/ticket-types/{}", base, empty_tt))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 204);
+    let empty_path = format!("{}/api/admin/events/{}", base, empty_event);
+    let resp = client
+        .delete(&empty_path)
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 204);
+    let resp = client
+        .delete(&empty_path)
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn inventory_adjustments_are_ledgered_and_reconcile() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+    let admin = admin_token(&client, &base).await;
+    let token_a = login(&client, &base, "stock-a").await;
+    let token_b = login(&client, &base, "stock-b").await;
+
+    let ticket_type_id =
+        create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 3})).await;
+    let order_a = grab(
+        &client,
+        &base,
+        &token_a,
+        json!({"ticket_type_id": ticket_type_id}),
+    )
+    .await
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    grab(
+        &client,
+        &base,
+        &token_b,
+        json!({"ticket_type_id": ticket_type_id}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    client
+        .post(format!(
+            "{}/api/orders/{}/cancel",
+            base,
+            order_a["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&token_a)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let inventory_url = format!(
+        "{}/api/admin/ticket-types/{}/inventory",
+        base, ticket_type_id
+    );
+    let adjust = |delta: i32| {
+        client
+            .post(&inventory_url)
+            .bearer_auth(&admin)
+            .json(&json!({"delta": delta, "reason": "venue change"}))
+            .send()
+    };
+
+    let tt = adjust(5)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        (
+            tt["inventory_total"].as_i64(),
+            tt["inventory_remaining"].as_i64()
+        ),
+        (Some(8), Some(7))
+    );
+    assert_eq!(adjust(0).await.unwrap().status().as_u16(), 400);
+    // Only unsold seats can be removed.
+    assert_eq!(adjust(-8).await.unwrap().status().as_u16(), 409);
+    let tt = adjust(-7)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        (
+            tt["inventory_total"].as_i64(),
+            tt["inventory_remaining"].as_i64()
+        ),
+        (Some(1), Some(0))
+    );
+    let resp = grab(
+        &client,
+        &base,
+        &token_a,
+        json!({"ticket_type_id": ticket_type_id}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+
+    let ledger = client
+        .get(&inventory_url)
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    let kinds: Vec<_> = ledger.iter().map(|m| m["kind"].as_str().unwrap()).collect();
+    assert_eq!(
+        kinds,
+        ["INITIAL", "RESERVE", "RESERVE", "RELEASE", "ADJUST", "ADJUST"]
+    );
+    assert_eq!(ledger[3]["order_id"], order_a["id"]);
+    assert_eq!(ledger[4]["actor"], ADMIN_USERNAME);
+
+    let reconcile = || async {
+        client
+            .get(format!("{}/api/admin/inventory/reconcile", base))
+            .bearer_auth(&admin)
+            .send()
+            .await
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap()
+    };
+    assert_eq!(reconcile().await["consistent"], true);
+
+    // A change that bypasses the ledger shows up.
+    sqlx::query(
+        "update ticket_types set inventory_remaining = inventory_remaining + 1 where id = $1::uuid",
+    )
+    .bind(&ticket_type_id)
+    .execute(&pool)
+    .await
+    .unwrap();
+    let report = reconcile().await;
+    assert_eq!(report["consistent"], false);
+    assert_eq!(
+        report["discrepancies"][0]["ticket_type_id"],
+        ticket_type_id.as_str()
+    );
+    assert_eq!(report["discrepancies"][0]["ledger_remaining"], 0);
+}
+
+#[tokio::test]
+async fn sharded_inventory_never_oversells_and_stays_accurate() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+    let admin = admin_token(&client, &base).await;
+
+    let event_id = create_event(&client, &base, json!({})).await;
+    for shards in [1, 65] {
+        let resp = client
+            .post(format!("{}/api/admin/events/{}/ticket_types", base, event_id))
+            .bearer_auth(&admin)
+            .json(&json!({
+                "name": "bad", "price_cents": 100, "inventory_total": 10, "inventory_shards": shards,
+                "sale_starts_at": Utc::now(), "sale_ends_at": Utc::now() + Duration::minutes(30)
+            }))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), 400);
+    }
+    let tt = create_ticket_type(&client, &base, &event_id, json!({"inventory_shards": 4})).await;
+
+    // More buyers than seats, all at once: buckets run dry unevenly but the total holds.
+    let mut tokens = Vec::new();
+    for i in 0..16 {
+        tokens.push(login(&client, &base, &format!("shard-buyer-{i}")).await);
+    }
+    let results = join_all(tokens.iter().cloned().map(|token| {
+        let (client, base, tt) = (client.clone(), base.clone(), tt.clone());
+        async move {
+            grab(
+                &client,
+                &base,
+                &token,
+                json!({"ticket_type_id": tt, "qty": 1}),
+            )
+            .await
+        }
+    }))
+    .await;
+    let mut orders = Vec::new();
+    for (i, resp) in results.into_iter().enumerate() {
+        if resp.status().is_success() {
+            let order = resp.json::<serde_json::Value>().await.unwrap();
+            orders.push((i, order["id"].as_str().unwrap().to_string()));
+        }
+    }
+    assert_eq!(orders.len(), 10);
+
+    let buckets: Vec<i32> = sqlx::query_scalar(
+        "select remaining from inventory_buckets where ticket_type_id = $1::uuid",
+    )
+    .bind(&tt)
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(buckets, [0, 0, 0, 0]);
+
+    let listed_remaining = || async {
+        let list = client
+            .get(format!("{}/api/events/{}/ticket_types", base, event_id))
+            .send()
+            .await
+            .unwrap()
+            .json::<Vec<serde_json::Value>>()
+            .await
+            .unwrap();
+        assert_eq!(list[0]["inventory_shards"], 4);
+        list[0]["inventory_remaining"].as_i64().unwrap()
+    };
+    assert_eq!(listed_remaining().await, 0);
+
+    let (buyer, order_id) = &orders[0];
+    client
+        .post(format!("{}/api/orders/{}/cancel", base, order_id))
+        .bearer_auth(&tokens[*buyer])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(listed_remaining().await, 1);
+
+    let adjust = |delta: i32| {
+        client
+            .post(format!("{}/api/admin/ticket-types/{}/inventory", base, tt))
+            .bearer_auth(&admin)
+            .json(&json!({"delta": delta, "reason": "extra row"}))
+            .send()
+    };
+    let body = adjust(6)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        (
+            body["inventory_total"].as_i64(),
+            body["inventory_remaining"].as_i64()
+        ),
+        (Some(16), Some(7))
+    );
+    assert_eq!(adjust(-8).await.unwrap().status().as_u16(), 409);
+    let body = adjust(-7)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(body["inventory_remaining"], 0);
+    assert_eq!(listed_remaining().await, 0);
+
+    let report = client
+        .get(format!("{}/api/admin/inventory/reconcile", base))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(report["consistent"], true);
+}
+
+#[tokio::test]
+async fn stock_gate_rejects_sold_out_grabs_and_reopens_on_release() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+    let tokens = [
+        login(&client, &base, "gate-a").await,
+        login(&client, &base, "gate-b").await,
+        login(&client, &base, "gate-c").await,
+    ];
+
+    // The gate learns about new ticket types when they are created.
+    let tt = create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 2})).await;
+
+    let grab_with_key = |token: &str| {
+        client
+            .post(format!("{}/api/tickets/grab", base))
+            .bearer_auth(token)
+            .header("idempotency-key", "gate-key")
+            .json(&json!({"ticket_type_id": tt}))
+            .send()
+    };
+    let first = grab_with_key(&tokens[0])
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    grab_with_key(&tokens[1])
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let resp = grab_with_key(&tokens[2]).await.unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+    let body = resp.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(body["error"], "conflict: sold out");
+
+    // A retry of a successful grab still gets its order back.
+    let retry = grab_with_key(&tokens[0])
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(retry["id"], first["id"]);
+
+    // Returned stock re-opens the gate immediately.
+    client
+        .post(format!(
+            "{}/api/orders/{}/cancel",
+            base,
+            first["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&tokens[0])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    grab_with_key(&tokens[2])
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let resp = grab(&client, &base, &tokens[0], json!({"ticket_type_id": tt})).await;
+    assert_eq!(resp.status().as_u16(), 409);
+
+    // Sold-out answers come from the gate, which doesn't see stock changed behind its back...
+    sqlx::query("update ticket_types set inventory_remaining = 5 where id = $1::uuid")
+        .bind(&tt)
+        .execute(&pool)
+        .await
+        .unwrap();
+    let resp = grab(&client, &base, &tokens[0], json!({"ticket_type_id": tt})).await;
+    assert_eq!(
+        resp.json::<serde_json::Value>().await.unwrap()["error"],
+        "conflict: sold out"
+    );
+
+    // ...until the next refresh, or an admin adjustment re-reads it.
+    client
+        .post(format!("{}/api/admin/ticket-types/{}/inventory", base, tt))
+        .bearer_auth(admin_token(&client, &base).await)
+        .json(&json!({"delta": 1, "reason": "extra seat"}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    grab(&client, &base, &tokens[0], json!({"ticket_type_id": tt}))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Stock held back for intents never counts as open for grabs, on create or on adjust.
+    let tt = create_on_sale_ticket_type(
+        &client,
+        &base,
+        json!({"inventory_total": 2, "intent_policy": "reserved_share", "intent_reserved_pct": 50}),
+    )
+    .await;
+    let mut share = Vec::new();
+    for i in 0..4 {
+        share.push(login(&client, &base, &format!("gate-share-{i}")).await);
+    }
+    grab(&client, &base, &share[0], json!({"ticket_type_id": tt}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let resp = grab(&client, &base, &share[1], json!({"ticket_type_id": tt})).await;
+    assert_eq!(
+        resp.json::<serde_json::Value>().await.unwrap()["error"],
+        "conflict: sold out"
+    );
+    client
+        .post(format!("{}/api/admin/ticket-types/{}/inventory", base, tt))
+        .bearer_auth(admin_token(&client, &base).await)
+        .json(&json!({"delta": 1, "reason": "extra seat"}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    grab(&client, &base, &share[2], json!({"ticket_type_id": tt}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let resp = grab(&client, &base, &share[3], json!({"ticket_type_id": tt})).await;
+    assert_eq!(
+        resp.json::<serde_json::Value>().await.unwrap()["error"],
+        "conflict: sold out"
+    );
+}
+
+async fn queue_status(
+    client: &Client,
+    base: &str,
+    token: &str,
+    ticket_type_id: &str,
+) -> serde_json::Value {
+    client
+        .get(format!("{}/api/queue/{}", base, ticket_type_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn waiting_room_draws_at_open_admits_in_order_and_gates_grab() {
+    let (base, _pool, _guard) = setup().await;
+    let client = Client::new();
+
+    let opens_at = Utc::now() + Duration::milliseconds(1500);
+    let event_id = create_event(&client, &base, json!({})).await;
+    let tt = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"inventory_total": 2, "queue_enabled": true, "queue_max_active": 2, "sale_starts_at": opens_at}),
+    )
+    .await;
+    let open_tt = create_ticket_type(&client, &base, &event_id, json!({"name": "B"})).await;
+
+    let mut tokens = Vec::new();
+    for i in 0..4 {
+        let token = login(&client, &base, &format!("queuer-{i}")).await;
+        let status = client
+            .post(format!("{}/api/queue/{}/join", base, tt))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(status["state"], "waiting");
+        assert_eq!(status["position"], serde_json::Value::Null);
+        tokens.push(token);
+    }
+    let resp = client
+        .post(format!("{}/api/queue/{}/join", base, open_tt))
+        .bearer_auth(&tokens[0])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+    let resp = client
+        .post(format!("{}/api/purchase-intents", base))
+        .bearer_auth(&tokens[0])
+        .json(&json!({"ticket_type_id": tt}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+
+    // Grabbing a queued ticket type needs an admission.
+    let resp = grab(&client, &base, &tokens[0], json!({"ticket_type_id": tt})).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    assert_eq!(
+        resp.json::<serde_json::Value>().await.unwrap()["reason"],
+        "admission_required"
+    );
+
+    // At open everyone who joined early is drawn into a position; the first two get in.
+    let mut statuses = Vec::new();
+    for _ in 0..50 {
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        statuses.clear();
+        for token in &tokens {
+            statuses.push(queue_status(&client, &base, token, &tt).await);
+        }
+        if statuses.iter().filter(|s| s["state"] == "admitted").count() == 2 {
+            break;
+        }
+    }
+    let mut positions: Vec<i64> = statuses
+        .iter()
+        .map(|s| s["position"].as_i64().unwrap())
+        .collect();
+    positions.sort();
+    assert_eq!(positions, [1, 2, 3, 4]);
+    for s in &statuses {
+        let admitted = s["position"].as_i64().unwrap() <= 2;
+        assert_eq!(s["state"], if admitted { "admitted" } else { "waiting" });
+        assert_eq!(s["admission_token"].is_string(), admitted);
+    }
+
+    // A latecomer lines up behind the draw.
+    let late = login(&client, &base, "queuer-late").await;
+    client
+        .post(format!("{}/api/queue/{}/join", base, tt))
+        .bearer_auth(&late)
+        .send()
+        .await
+        .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let status = queue_status(&client, &base, &late, &tt).await;
+    assert_eq!(
+        (status["position"].as_i64(), status["ahead"].as_i64()),
+        (Some(5), Some(2))
+    );
+
+    let (admitted, waiting): (Vec<_>, Vec<_>) =
+        (0..4).partition(|&i| statuses[i]["state"] == "admitted");
+    let admission = |i: usize| statuses[i]["admission_token"].as_str().unwrap().to_string();
+    let grab_admitted = |token: String, admission: String| {
+        client
+            .post(format!("{}/api/tickets/grab", base))
+            .bearer_auth(token)
+            .header("x-admission-token", admission)
+            .json(&json!({"ticket_type_id": tt}))
+            .send()
+    };
+
+    // Admissions are bound to their user, aren't access tokens, and are spent by a grab.
+    let resp = grab_admitted(tokens[waiting[0]].clone(), admission(admitted[0]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 403);
+    let resp = client
+        .get(format!("{}/api/orders/me", base))
+        .bearer_auth(admission(admitted[0]))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 401);
+    grab_admitted(tokens[admitted[0]].clone(), admission(admitted[0]))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let resp = grab_admitted(tokens[admitted[0]].clone(), admission(admitted[0]))
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 403);
+    grab_admitted(tokens[admitted[1]].clone(), admission(admitted[1]))
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Stock is gone, so nobody else is let in.
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    assert_eq!(
+        queue_status(&client, &base, &tokens[waiting[0]], &tt).await["state"],
+        "sold_out"
+    );
+
+    // The stream ends after a final status.
+    let body = client
+        .get(format!("{}/api/queue/{}/stream", base, tt))
+        .bearer_auth(&tokens[admitted[0]])
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(body.starts_with("data: "), "{body}");
+    assert!(body.contains(r#""state":"done""#), "{body}");
+}
+
+#[tokio::test]
+async fn rate_limits_apply_per_route_group_and_per_user() {
+    let (base, _pool, _guard) = setup_with(|cfg| {
+        let tight = |burst| RateLimitPolicy {
+            per_second: 0.01,
+            burst,
+        };
+        cfg.rate_limits.grab = tight(2);
+        cfg.rate_limits.read = tight(3);
+        cfg.rate_limits.auth = tight(5);
+    })
+    .await;
+    let client = Client::new();
+
+    // Logins 1-4 (two admin logins for the setup helpers).
+    let tt = create_on_sale_ticket_type(&client, &base, json!({"max_per_user": 10})).await;
+    let token_a = login(&client, &base, "limited-a").await;
+    let token_b = login(&client, &base, "limited-b").await;
+
+    let resp = grab(&client, &base, &token_a, json!({"ticket_type_id": tt})).await;
+    assert!(resp.status().is_success());
+    assert_eq!(resp.headers()["x-ratelimit-limit"], "2");
+    assert_eq!(resp.headers()["x-ratelimit-remaining"], "1");
+    grab(&client, &base, &token_a, json!({"ticket_type_id": tt}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let resp = grab(&client, &base, &token_a, json!({"ticket_type_id": tt})).await;
+    assert_eq!(resp.status().as_u16(), 429);
+    assert_eq!(resp.headers()["x-ratelimit-remaining"], "0");
+    let retry_after: u64 = resp.headers()["retry-after"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after >= 1);
+
+    // Same IP, different user: own budget.
+    grab(&client, &base, &token_b, json!({"ticket_type_id": tt}))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Reads have their own bucket (per IP without a token); health checks aren't limited.
+    for _ in 0..3 {
+        client
+            .get(format!("{}/api/events", base))
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+    let resp = client
+        .get(format!("{}/api/events", base))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 429);
+    for _ in 0..10 {
+        let resp = client
+            .get(format!("{}/healthz", base))
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+        assert!(resp.headers().get("x-ratelimit-limit").is_none());
+    }
+
+    // Login 5 is the last one in the burst.
+    login(&client, &base, "limited-c").await;
+    let resp = auth_post(&client, &base, "login", json!({"username": "limited-d"})).await;
+    assert_eq!(resp.status().as_u16(), 429);
+}
+
+/// Fetches a grab challenge and brute-forces a solution.
+async fn solved_challenge(client: &Client, base: &str, token: &str) -> (String, String) {
+    let res = client
+        .get(format!("{}/api/grab-challenge", base))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let challenge = res["challenge"].as_str().unwrap().to_string();
+    let difficulty = res["difficulty"].as_u64().unwrap() as u32;
+    let solution = (0u64..)
+        .map(|n| n.to_string())
+        .find(|s| abuse::solves(&challenge, s, difficulty))
+        .unwrap();
+    (challenge, solution)
+}
+
+async fn grab_with_challenge(
+    client: &Client,
+    base: &str,
+    token: &str,
+    challenge: &str,
+    solution: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    client
+        .post(format!("{}/api/tickets/grab", base))
+        .bearer_auth(token)
+        .header("x-grab-challenge", challenge)
+        .header("x-grab-solution", solution)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn rejection_reason(resp: reqwest::Response) -> (u16, String) {
+    let status = resp.status().as_u16();
+    let body = resp.json::<serde_json::Value>().await.unwrap();
+    (
+        status,
+        body["reason"].as_str().unwrap_or_default().to_string(),
+    )
+}
+
+#[tokio::test]
+async fn abuse_layer_requires_challenges_limits_accounts_and_blocks() {
+    let (base, pool, _guard) = setup_with(|cfg| {
+        cfg.grab_pow_difficulty = 8;
+        cfg.login_max_accounts_per_ip = 3;
+    })
+    .await;
+    let client = Client::new();
+
+    // Admin is the first account on this IP, u1 and u2 fill it up.
+    let admin = admin_token(&client, &base).await;
+    let tt = create_on_sale_ticket_type(&client, &base, json!({"max_per_user": 10})).await;
+    let u1 = login(&client, &base, "abuse-u1").await;
+    let u2 = login(&client, &base, "abuse-u2").await;
+    let resp = auth_post(&client, &base, "login", json!({"username": "abuse-u3"})).await;
+    assert_eq!(
+        rejection_reason(resp).await,
+        (429, "too_many_accounts".into())
+    );
+    // Accounts already seen on the IP can still log in.
+    login(&client, &base, "abuse-u1").await;
+
+    // No challenge, a wrong solution, someone else's challenge.
+    let resp = grab(&client, &base, &u1, json!({"ticket_type_id": tt})).await;
+    assert_eq!(
+        rejection_reason(resp).await,
+        (403, "challenge_required".into())
+    );
+    let (challenge, solution) = solved_challenge(&client, &base, &u1).await;
+    let wrong = (0u64..)
+        .map(|n| n.to_string())
+        .find(|s| !abuse::solves(&challenge, s, 8))
+        .unwrap();
+    let resp = grab_with_challenge(
+        &client,
+        &base,
+        &u1,
+        &challenge,
+        &wrong,
+        json!({"ticket_type_id": tt}),
+    )
+    .await;
+    assert_eq!(
+        rejection_reason(resp).await,
+        (403, "challenge_invalid".into())
+    );
+    let resp = grab_with_challenge(
+        &client,
+        &base,
+        &u2,
+        &challenge,
+        &solution,
+        json!({"ticket_type_id": tt}),
+    )
+    .await;
+    assert_eq!(
+        rejection_reason(resp).await,
+        (403, "challenge_invalid".into())
+    );
+
+    // A solved challenge buys one grab.
+    let resp = grab_with_challenge(
+        &client,
+        &base,
+        &u1,
+        &challenge,
+        &solution,
+        json!({"ticket_type_id": tt}),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let resp = grab_with_challenge(
+        &client,
+        &base,
+        &u1,
+        &challenge,
+        &solution,
+        json!({"ticket_type_id": tt}),
+    )
+    .await;
+    assert_eq!(
+        rejection_reason(resp).await,
+        (403, "challenge_reused".into())
+    );
+    let (challenge, solution) = solved_challenge(&client, &base, &u1).await;
+    let resp = grab_with_challenge(
+        &client,
+        &base,
+        &u1,
+        &challenge,
+        &solution,
+        json!({"ticket_type_id": tt}),
+    )
+    .await;
+    assert!(resp.status().is_success());
+
+    // Blocking a user cuts off their existing token and their logins; unblocking restores both.
+    let u2_id: uuid::Uuid = sqlx::query_scalar("select id from users where username = 'abuse-u2'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    let entry = client
+        .post(format!("{}/api/admin/blocklist", base))
+        .bearer_auth(&admin)
+        .json(&json!({"kind": "user", "value": u2_id, "reason": "scripted grabs"}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let resp = client
+        .get(format!("{}/api/orders/me", base))
+        .bearer_auth(&u2)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rejection_reason(resp).await, (403, "blocked_user".into()));
+    let resp = auth_post(&client, &base, "login", json!({"username": "abuse-u2"})).await;
+    assert_eq!(rejection_reason(resp).await, (403, "blocked_user".into()));
+    let resp = client
+        .post(format!("{}/api/admin/blocklist", base))
+        .bearer_auth(&admin)
+        .json(&json!({"kind": "ip", "value": "not-an-ip", "reason": "x"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+
+    let list = client
+        .get(format!("{}/api/admin/blocklist", base))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(list.as_array().unwrap().len(), 1);
+    assert_eq!(list[0]["actor"], ADMIN_USERNAME);
+    let resp = client
+        .delete(format!(
+            "{}/api/admin/blocklist/{}",
+            base,
+            entry["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 204);
+    client
+        .get(format!("{}/api/orders/me", base))
+        .bearer_auth(&u2)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Admins can query every rejection.
+    let events = client
+        .get(format!("{}/api/admin/abuse-events", base))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    let mut reasons: Vec<&str> = events
+        .iter()
+        .map(|e| e["reason"].as_str().unwrap())
+        .collect();
+    reasons.sort();
+    assert_eq!(
+        reasons,
+        [
+            "blocked_user",
+            "blocked_user",
+            "challenge_invalid",
+            "challenge_invalid",
+            "challenge_required",
+            "challenge_reused",
+            "too_many_accounts",
+        ]
+    );
+    let reused = client
+        .get(format!(
+            "{}/api/admin/abuse-events?reason=challenge_reused",
+            base
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    assert_eq!(reused.len(), 1);
+    assert_eq!(reused[0]["ip"], "127.0.0.1");
+    assert_eq!(reused[0]["details"]["ticket_type_id"], tt.as_str());
+
+    // Blocking the IP shuts out everyone on it, admins included; health checks still answer.
+    client
+        .post(format!("{}/api/admin/blocklist", base))
+        .bearer_auth(&admin)
+        .json(&json!({"kind": "ip", "value": "127.0.0.1", "reason": "botnet"}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let resp = client
+        .get(format!("{}/api/events", base))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rejection_reason(resp).await, (403, "blocked_ip".into()));
+    let resp = client
+        .get(format!("{}/healthz", base))
+        .send()
+        .await
+        .unwrap();
+    assert!(resp.status().is_success());
+    let blocked_ip: i64 = sqlx::query_scalar(
+        "select count(*) from abuse_events where reason = 'blocked_ip' and ip = '127.0.0.1'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(blocked_ip, 1);
+}
+
+async fn create_intent(
+    client: &Client,
+    base: &str,
+    token: &str,
+    ticket_type_id: &str,
+) -> uuid::Uuid {
+    let intent = client
+        .post(format!("{}/api/purchase-intents", base))
+        .bearer_auth(token)
+        .json(&json!({"ticket_type_id": ticket_type_id}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    uuid::Uuid::parse_str(intent["id"].as_str().unwrap()).unwrap()
+}
+
+/// Polls until the intent leaves `ACTIVE`; returns its status and attempts.
+async fn settled_intent(pool: &PgPool, intent_id: uuid::Uuid) -> (String, i32) {
+    for _ in 0..100 {
+        let row: (String, i32) =
+            sqlx::query_as("select status, attempts from purchase_intents where id = $1")
+                .bind(intent_id)
+                .fetch_one(pool)
+                .await
+                .unwrap();
+        if row.0 != "ACTIVE" {
+            return row;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    panic!("intent {intent_id} still ACTIVE");
+}
+
+#[tokio::test]
+async fn intent_workers_share_leases_back_off_and_give_up() {
+    let worker_cfg = IntentWorkerConfig {
+        poll_ms: 20,
+        batch: 3,
+        lease_secs: 30,
+        max_attempts: 3,
+        backoff_base_ms: 20,
+        backoff_max_ms: 100,
+    };
+    let (base, pool, _guard) = setup_with(|cfg| cfg.intent_worker = worker_cfg).await;
+    let client = Client::new();
+    // Two more workers on the same table, as separate processes would be.
+    for _ in 0..2 {
+        worker::spawn_intent_worker(Db { pool: pool.clone() }, worker_cfg);
+    }
+
+    // Every intent is fulfilled exactly once.
+    let tt = create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 12})).await;
+    let mut intents = Vec::new();
+    for i in 0..12 {
+        let token = login(&client, &base, &format!("intent-{i}")).await;
+        intents.push(create_intent(&client, &base, &token, &tt).await);
+    }
+    for id in &intents {
+        assert_eq!(settled_intent(&pool, *id).await, ("FULFILLED".into(), 0));
+    }
+    let (orders, distinct, leased): (i64, i64, i64) = sqlx::query_as(
+        r#"select (select count(*) from orders),
+                  (select count(distinct order_id) from purchase_intents),
+                  (select count(*) from purchase_intents where locked_by is not null)"#,
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((orders, distinct, leased), (12, 12, 0));
+
+    // Sold out: retried with backoff, then given up on.
+    let token = login(&client, &base, "intent-late").await;
+    let late = create_intent(&client, &base, &token, &tt).await;
+    assert_eq!(settled_intent(&pool, late).await, ("FAILED".into(), 3));
+    let last_error: String =
+        sqlx::query_scalar("select last_error from purchase_intents where id = $1")
+            .bind(late)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert!(last_error.contains("out of stock"));
+
+    // Waiting for the sale to open costs no attempts.
+    let event_id = create_event(&client, &base, json!({})).await;
+    let sale_starts_at = Utc::now() + Duration::minutes(10);
+    let upcoming = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"sale_starts_at": sale_starts_at}),
+    )
+    .await;
+    let early = create_intent(&client, &base, &token, &upcoming).await;
+    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+    let (status, attempts, next_attempt_at): (String, i32, chrono::DateTime<Utc>) = sqlx::query_as(
+        "select status, attempts, next_attempt_at from purchase_intents where id = $1",
+    )
+    .bind(early)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((status.as_str(), attempts), ("ACTIVE", 0));
+    assert!((next_attempt_at - sale_starts_at).num_milliseconds().abs() < 1000);
+
+    // A live lease keeps other workers off; once it runs out the intent is picked up.
+    let open = create_on_sale_ticket_type(&client, &base, json!({})).await;
+    let stuck = uuid::Uuid::new_v4();
+    sqlx::query(
+        r#"insert into purchase_intents (id, user_id, ticket_type_id, status, idempotency_key, locked_by, locked_until)
+           select $1, id, $2, 'ACTIVE', 'intent:' || $1, 'crashed', now() + interval '1 hour'
+           from users where username = 'intent-late'"#,
+    )
+    .bind(stuck)
+    .bind(uuid::Uuid::parse_str(&open).unwrap())
+    .execute(&pool)
+    .await
+    .unwrap();
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+    let status: String = sqlx::query_scalar("select status from purchase_intents where id = $1")
+        .bind(stuck)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "ACTIVE");
+    sqlx::query(
+        "update purchase_intents set locked_until = now() - interval '1 second' where id = $1",
+    )
+    .bind(stuck)
+    .execute(&pool)
+    .await
+    .unwrap();
+    assert_eq!(settled_intent(&pool, stuck).await, ("FULFILLED".into(), 0));
+}
+
+#[tokio::test]
+async fn intent_worker_wakes_on_notify_and_at_sale_open() {
+    // Polling effectively off and long backoffs: only wakeups can explain prompt fulfilment.
+    let (base, pool, _guard) = setup_with(|cfg| {
+        cfg.intent_worker = IntentWorkerConfig {
+            poll_ms: 60_000,
+            batch: 50,
+            lease_secs: 30,
+            max_attempts: 10,
+            backoff_base_ms: 60_000,
+            backoff_max_ms: 60_000,
+        };
+    })
+    .await;
+    let client = Client::new();
+    let started = std::time::Instant::now();
+
+    // A new intent is picked up right away.
+    let tt = create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 1})).await;
+    let buyer = login(&client, &base, "wake-buyer").await;
+    let first = create_intent(&client, &base, &buyer, &tt).await;
+    assert_eq!(settled_intent(&pool, first).await, ("FULFILLED".into(), 0));
+
+    // Sold out: the next intent backs off for a minute, until a cancellation returns the seat.
+    let waiter = login(&client, &base, "wake-waiter").await;
+    let second = create_intent(&client, &base, &waiter, &tt).await;
+    for _ in 0..50 {
+        let attempts: i32 =
+            sqlx::query_scalar("select attempts from purchase_intents where id = $1")
+                .bind(second)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        if attempts == 1 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let order_id: uuid::Uuid =
+        sqlx::query_scalar("select order_id from purchase_intents where id = $1")
+            .bind(first)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    client
+        .post(format!("{}/api/orders/{}/cancel", base, order_id))
+        .bearer_auth(&buyer)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(settled_intent(&pool, second).await, ("FULFILLED".into(), 1));
+
+    // An intent waiting for the opening is tried the moment the sale starts.
+    let event_id = create_event(&client, &base, json!({})).await;
+    let sale_starts_at = Utc::now() + Duration::seconds(2);
+    let upcoming = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"sale_starts_at": sale_starts_at}),
+    )
+    .await;
+    let early = create_intent(&client, &base, &buyer, &upcoming).await;
+    assert_eq!(settled_intent(&pool, early).await, ("FULFILLED".into(), 0));
+    let created_at: chrono::DateTime<Utc> = sqlx::query_scalar(
+        "select o.created_at from orders o join purchase_intents i on i.order_id = o.id where i.id = $1",
+    )
+    .bind(early)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert!(created_at >= sale_starts_at);
+    assert!((created_at - sale_starts_at).num_milliseconds() < 1000);
+
+    assert!(started.elapsed() < std::time::Duration::from_secs(30));
+}
+
+#[tokio::test]
+async fn intents_cancel_expire_cap_price_and_fall_back() {
+    let (base, pool, _guard) = setup_with(|_| {}).await;
+    let client = Client::new();
+    let token = login(&client, &base, "intent-options").await;
+    let event_id = create_event(&client, &base, json!({})).await;
+    let upcoming = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"sale_starts_at": Utc::now() + Duration::minutes(10)}),
+    )
+    .await;
+    let intents = format!("{}/api/purchase-intents", base);
+
+    // Bad options are rejected up front.
+    for (body, status) in [
+        (
+            json!({"ticket_type_id": upcoming, "expires_at": Utc::now() - Duration::seconds(1)}),
+            400,
+        ),
+        (
+            json!({"ticket_type_id": upcoming, "max_price_cents": -1}),
+            400,
+        ),
+        (
+            json!({"ticket_type_id": upcoming, "fallback_ticket_type_ids": [uuid::Uuid::new_v4()]}),
+            404,
+        ),
+        (
+            json!({"ticket_type_id": upcoming,
+                   "fallback_ticket_type_ids": (0..6).map(|_| uuid::Uuid::new_v4()).collect::<Vec<_>>()}),
+            400,
+        ),
+    ] {
+        let resp = client
+            .post(&intents)
+            .bearer_auth(&token)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), status, "{body}");
+    }
+
+    // Canceling is owner-only and idempotent; the worker leaves canceled intents alone.
+    let id = create_intent(&client, &base, &token, &upcoming).await;
+    let cancel = format!("{}/api/purchase-intents/{}/cancel", base, id);
+    let other = login(&client, &base, "intent-options-other").await;
+    let resp = client
+        .post(&cancel)
+        .bearer_auth(&other)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 404);
+    for _ in 0..2 {
+        let resp = client
+            .post(&cancel)
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert!(resp.status().is_success());
+        let body = resp.json::<serde_json::Value>().await.unwrap();
+        assert_eq!(body["status"], "CANCELED");
+    }
+
+    // Nothing bought before expires_at: the intent expires instead of waiting on.
+    let body = client
+        .post(&intents)
+        .bearer_auth(&token)
+        .json(&json!({"ticket_type_id": upcoming, "expires_at": Utc::now() + Duration::seconds(1)}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let expiring = uuid::Uuid::parse_str(body["id"].as_str().unwrap()).unwrap();
+    assert_eq!(settled_intent(&pool, expiring).await, ("EXPIRED".into(), 0));
+
+    // Too expensive, then sold out: the worker falls through to the next fallback.
+    let pricey = create_ticket_type(&client, &base, &event_id, json!({"price_cents": 500})).await;
+    let scarce = create_ticket_type(&client, &base, &event_id, json!({"inventory_total": 1})).await;
+    let cheap = create_ticket_type(&client, &base, &event_id, json!({"price_cents": 150})).await;
+    let resp = grab(
+        &client,
+        &base,
+        &other,
+        json!({"ticket_type_id": scarce, "qty": 1}),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let body = client
+        .post(&intents)
+        .bearer_auth(&token)
+        .json(&json!({
+            "ticket_type_id": pricey,
+            "max_price_cents": 200,
+            "fallback_ticket_type_ids": [scarce, pricey, scarce, cheap]
+        }))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(body["max_price_cents"], 200);
+    assert_eq!(body["fallback_ticket_type_ids"], json!([scarce, cheap]));
+    let fallback = uuid::Uuid::parse_str(body["id"].as_str().unwrap()).unwrap();
+    assert_eq!(
+        settled_intent(&pool, fallback).await,
+        ("FULFILLED".into(), 0)
+    );
+    let bought: uuid::Uuid = sqlx::query_scalar(
+        "select o.ticket_type_id from orders o join purchase_intents i on i.order_id = o.id where i.id = $1",
+    )
+    .bind(fallback)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(bought.to_string(), cheap);
+
+    // A fulfilled intent can't be canceled any more.
+    let resp = client
+        .post(format!("{}/api/purchase-intents/{}/cancel", base, fallback))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+
+    // Nothing within the cap: retried like any other failure.
+    let over = client
+        .post(&intents)
+        .bearer_auth(&other)
+        .json(&json!({"ticket_type_id": pricey, "max_price_cents": 100}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let over = uuid::Uuid::parse_str(over["id"].as_str().unwrap()).unwrap();
+    let mut last_error: Option<String> = None;
+    for _ in 0..50 {
+        last_error = sqlx::query_scalar("select last_error from purchase_intents where id = $1")
+            .bind(over)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        if last_error.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert!(last_error.unwrap().contains("max_price_cents"));
+}
+
+#[tokio::test]
+async fn intent_policies_hold_stock_back_from_grabs() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+    let event_id = create_event(&client, &base, json!({})).await;
+
+    for (body, status) in [
+        (json!({"intent_policy": "reserved_share"}), 400),
+        (
+            json!({"intent_policy": "reserved_share", "intent_reserved_pct": 101}),
+            400,
+        ),
+        (
+            json!({"intent_policy": "reserved_share", "intent_reserved_pct": 30, "inventory_shards": 4}),
+            400,
+        ),
+        (json!({"intent_policy": "head_start"}), 400),
+        (
+            json!({"intent_policy": "equal", "intent_head_start_secs": 60}),
+            400,
+        ),
+        (json!({"intent_policy": "lottery"}), 400),
+    ] {
+        let resp = client
+            .post(format!("{}/api/admin/events/{}/ticket_types", base, event_id))
+            .bearer_auth(admin_token(&client, &base).await)
+            .json(&merge(
+                json!({"name": "A", "price_cents": 100, "inventory_total": 10,
+                       "sale_starts_at": Utc::now(), "sale_ends_at": Utc::now() + Duration::minutes(30)}),
+                body.clone(),
+            ))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status().as_u16(), status, "{body}");
+    }
+
+    // 30% of 10 held for intents: twenty grabbers racing three intents split 7 / 3.
+    let tt = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"intent_policy": "reserved_share", "intent_reserved_pct": 30}),
+    )
+    .await;
+    let mut grabbers = Vec::new();
+    for i in 0..20 {
+        grabbers.push(login(&client, &base, &format!("share-grab-{i}")).await);
+    }
+    let mut waiters = Vec::new();
+    for i in 0..3 {
+        waiters.push(login(&client, &base, &format!("share-intent-{i}")).await);
+    }
+    let intents = tokio::spawn({
+        let (client, base, tt) = (client.clone(), base.clone(), tt.clone());
+        async move {
+            let mut ids = Vec::new();
+            for token in &waiters {
+                ids.push(create_intent(&client, &base, token, &tt).await);
+            }
+            ids
+        }
+    });
+    let results = join_all(grabbers.into_iter().map(|token| {
+        let (client, base, tt) = (client.clone(), base.clone(), tt.clone());
+        async move {
+            grab(
+                &client,
+                &base,
+                &token,
+                json!({"ticket_type_id": tt, "qty": 1}),
+            )
+            .await
+        }
+    }))
+    .await;
+    assert_eq!(
+        results.iter().filter(|r| r.status().is_success()).count(),
+        7
+    );
+    let intent_ids = intents.await.unwrap();
+    for id in &intent_ids {
+        assert_eq!(settled_intent(&pool, *id).await, ("FULFILLED".into(), 0));
+    }
+    let stock = |tt: String| {
+        let pool = pool.clone();
+        async move {
+            sqlx::query_as::<_, (i32, i32)>(
+                "select inventory_remaining, intent_reserved_remaining from ticket_types where id = $1::uuid",
+            )
+            .bind(&tt)
+            .fetch_one(&pool)
+            .await
+            .unwrap()
+        }
+    };
+    assert_eq!(stock(tt.clone()).await, (0, 0));
+
+    // A canceled intent order hands its unit back to the reserve, not to grabs.
+    let order_id: uuid::Uuid =
+        sqlx::query_scalar("select order_id from purchase_intents where id = $1")
+            .bind(intent_ids[0])
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    client
+        .post(format!("{}/api/orders/{}/cancel", base, order_id))
+        .bearer_auth(login(&client, &base, "share-intent-0").await)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(stock(tt.clone()).await, (1, 1));
+    let late = login(&client, &base, "share-grab-late").await;
+    let resp = grab(
+        &client,
+        &base,
+        &late,
+        json!({"ticket_type_id": tt, "qty": 1}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+    assert_eq!(stock(tt.clone()).await, (1, 1));
+    let id = create_intent(&client, &base, &late, &tt).await;
+    assert_eq!(settled_intent(&pool, id).await, ("FULFILLED".into(), 0));
+    assert_eq!(stock(tt.clone()).await, (0, 0));
+
+    // Head start: intents buy right away, grabs wait.
+    let tt = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"intent_policy": "head_start", "intent_head_start_secs": 600}),
+    )
+    .await;
+    let token = login(&client, &base, "head-start").await;
+    let resp = grab(
+        &client,
+        &base,
+        &token,
+        json!({"ticket_type_id": tt, "qty": 1}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let id = create_intent(&client, &base, &token, &tt).await;
+    assert_eq!(settled_intent(&pool, id).await, ("FULFILLED".into(), 0));
+
+    // Back to equal: the head start is gone for grabs.
+    let resp = client
+        .patch(format!("{}/api/admin/ticket-types/{}", base, tt))
+        .bearer_auth(admin_token(&client, &base).await)
+        .json(&json!({"intent_policy": "equal"}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(resp["intent_head_start_secs"], serde_json::Value::Null);
+    let other = login(&client, &base, "head-start-late").await;
+    let resp = grab(
+        &client,
+        &base,
+        &other,
+        json!({"ticket_type_id": tt, "qty": 1}),
+    )
+    .await;
+    assert!(resp.status().is_success());
+}
+
+#[tokio::test]
+async fn seated_ticket_types_hold_and_sell_each_seat_once() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+    let event_id = create_event(&client, &base, json!({})).await;
+    let layout = json!([
+        {"section": "A", "row": "1", "seats": 4},
+        {"section": "A", "row": "2", "seats": 3}
+    ]);
+
+    let resp = client
+        .post(format!("{}/api/admin/events/{}/ticket_types", base, event_id))
+        .bearer_auth(admin_token(&client, &base).await)
+        .json(&json!({"name": "S", "price_cents": 100, "inventory_total": 8, "seat_rows": layout,
+                      "sale_starts_at": Utc::now(), "sale_ends_at": Utc::now() + Duration::minutes(30)}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+    let tt = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"inventory_total": 7, "seat_rows": layout}),
+    )
+    .await;
+    let seat_map = |tt: String| {
+        let (client, base) = (client.clone(), base.clone());
+        async move {
+            client
+                .get(format!("{}/api/ticket-types/{}/seats", base, tt))
+                .send()
+                .await
+                .unwrap()
+                .json::<Vec<serde_json::Value>>()
+                .await
+                .unwrap()
+        }
+    };
+    let seats = seat_map(tt.clone()).await;
+    assert_eq!(seats.len(), 7);
+    assert!(seats.iter().all(|s| s["status"] == "AVAILABLE"));
+    let a1_2 = seats
+        .iter()
+        .find(|s| s["row"] == "1" && s["number"] == 2)
+        .unwrap()["id"]
+        .clone();
+
+    // Ten buyers race for the same seat: one gets it.
+    let mut tokens = Vec::new();
+    for i in 0..10 {
+        tokens.push(login(&client, &base, &format!("seat-{i}")).await);
+    }
+    let results = join_all(tokens.iter().cloned().map(|token| {
+        let (client, base, tt, seat) = (client.clone(), base.clone(), tt.clone(), a1_2.clone());
+        async move {
+            grab(
+                &client,
+                &base,
+                &token,
+                json!({"ticket_type_id": tt, "seat_ids": [seat]}),
+            )
+            .await
+        }
+    }))
+    .await;
+    let winners: Vec<usize> = (0..10)
+        .filter(|&i| results[i].status().is_success())
+        .collect();
+    assert_eq!(winners.len(), 1);
+    let winner = tokens[winners[0]].clone();
+
+    // Best available pairs: row 1 keeps 3-4 together, row 2 has 1-2; nothing else is adjacent.
+    let results = join_all(tokens.iter().cloned().map(|token| {
+        let (client, base, tt) = (client.clone(), base.clone(), tt.clone());
+        async move {
+            grab(
+                &client,
+                &base,
+                &token,
+                json!({"ticket_type_id": tt, "qty": 2}),
+            )
+            .await
+        }
+    }))
+    .await;
+    let mut orders = Vec::new();
+    for (i, resp) in results.into_iter().enumerate() {
+        if resp.status().is_success() {
+            let order = resp.json::<serde_json::Value>().await.unwrap();
+            orders.push((tokens[i].clone(), order["id"].as_str().unwrap().to_string()));
+        }
+    }
+    assert_eq!(orders.len(), 2);
+    let mut taken = Vec::new();
+    for (token, order_id) in &orders {
+        let seats = client
+            .get(format!("{}/api/orders/{}/seats", base, order_id))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap()
+            .json::<Vec<serde_json::Value>>()
+            .await
+            .unwrap();
+        assert_eq!(seats.len(), 2);
+        assert_eq!(seats[0]["row"], seats[1]["row"]);
+        assert_eq!(
+            seats[1]["number"].as_i64().unwrap(),
+            seats[0]["number"].as_i64().unwrap() + 1
+        );
+        taken.extend(
+            seats
+                .iter()
+                .map(|s| format!("{}/{}", s["row"], s["number"])),
+        );
+    }
+    taken.sort();
+    assert_eq!(taken, ["\"1\"/3", "\"1\"/4", "\"2\"/1", "\"2\"/2"]);
+    let (held, remaining): (i64, i32) = sqlx::query_as(
+        r#"select (select count(*) from seats where ticket_type_id = $1::uuid and status = 'HELD'),
+                  (select inventory_remaining from ticket_types where id = $1::uuid)"#,
+    )
+    .bind(&tt)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!((held, remaining), (5, 2));
+
+    // Paying sells the seat; canceling puts the pair back on the map.
+    let winner_order = client
+        .get(format!("{}/api/orders/me", base))
+        .bearer_auth(&winner)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap()
+        .into_iter()
+        .find(|o| o["qty"] == 1)
+        .unwrap();
+    assert!(pay(
+        &client,
+        &base,
+        &winner,
+        winner_order["id"].as_str().unwrap()
+    )
+    .await
+    .status()
+    .is_success());
+    let (token, order_id) = &orders[0];
+    client
+        .post(format!("{}/api/orders/{}/cancel", base, order_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let seats = seat_map(tt.clone()).await;
+    let status =
+        |id: &serde_json::Value| seats.iter().find(|s| &s["id"] == id).unwrap()["status"].clone();
+    assert_eq!(status(&a1_2), "SOLD");
+    assert_eq!(
+        seats.iter().filter(|s| s["status"] == "AVAILABLE").count(),
+        4
+    );
+
+    // Intents get the best available seat too.
+    let intent_user = login(&client, &base, "seat-intent").await;
+    let intent = create_intent(&client, &base, &intent_user, &tt).await;
+    assert_eq!(settled_intent(&pool, intent).await, ("FULFILLED".into(), 0));
+    let held: i64 = sqlx::query_scalar(
+        r#"select count(*) from seats s join purchase_intents i on i.order_id = s.order_id
+           where i.id = $1 and s.status = 'HELD'"#,
+    )
+    .bind(intent)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(held, 1);
+
+    // General admission stays a plain counter.
+    let ga = create_ticket_type(&client, &base, &event_id, json!({})).await;
+    let resp = grab(
+        &client,
+        &base,
+        &winner,
+        json!({"ticket_type_id": ga, "seat_ids": [a1_2]}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 400);
+    assert!(seat_map(ga).await.is_empty());
+    let resp = client
+        .post(format!("{}/api/admin/ticket-types/{}/inventory", base, tt))
+        .bearer_auth(admin_token(&client, &base).await)
+        .json(&json!({"delta": 1, "reason": "extra chair"}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+}
+
+async fn hold(
+    client: &Client,
+    base: &str,
+    token: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    client
+        .post(format!("{}/api/holds", base))
+        .bearer_auth(token)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn checkout(client: &Client, base: &str, token: &str, hold_id: &str) -> reqwest::Response {
+    client
+        .post(format!("{}/api/holds/{}/checkout", base, hold_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn remaining(pool: &PgPool, ticket_type_id: &str) -> i32 {
+    sqlx::query_scalar("select inventory_remaining from ticket_types where id = $1::uuid")
+        .bind(ticket_type_id)
+        .fetch_one(pool)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn holds_set_stock_aside_until_checkout_release_or_expiry() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+    let tt = create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 5})).await;
+
+    // Holds and grabs race for the same five tickets: exactly five win, whatever the mix.
+    let mut tokens = Vec::new();
+    for i in 0..20 {
+        tokens.push(login(&client, &base, &format!("holder-{i}")).await);
+    }
+    let results = join_all(tokens.iter().cloned().enumerate().map(|(i, token)| {
+        let (client, base, tt) = (client.clone(), base.clone(), tt.clone());
+        async move {
+            if i % 2 == 0 {
+                hold(&client, &base, &token, json!({"ticket_type_id": tt})).await
+            } else {
+                grab(&client, &base, &token, json!({"ticket_type_id": tt})).await
+            }
+        }
+    }))
+    .await;
+    let won = results.iter().filter(|r| r.status().is_success()).count();
+    assert!(results
+        .iter()
+        .all(|r| r.status().is_success() || r.status().as_u16() == 409));
+    assert_eq!(won, 5);
+    let (held, ordered): (i64, i64) = sqlx::query_as(
+        r#"select (select coalesce(sum(qty), 0) from holds where ticket_type_id = $1::uuid and status = 'ACTIVE'),
+                  (select coalesce(sum(qty), 0) from orders where ticket_type_id = $1::uuid)"#,
+    )
+    .bind(&tt)
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(held + ordered, 5);
+    assert_eq!(remaining(&pool, &tt).await, 0);
+
+    // Held stock is off sale for everyone else.
+    let tt = create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 2})).await;
+    let mut holds = Vec::new();
+    for token in &tokens[..2] {
+        let h = hold(&client, &base, token, json!({"ticket_type_id": tt})).await;
+        let h = h
+            .error_for_status()
+            .unwrap()
+            .json::<serde_json::Value>()
+            .await
+            .unwrap();
+        assert_eq!(h["status"], "ACTIVE");
+        holds.push((token.clone(), h["id"].as_str().unwrap().to_string()));
+    }
+    assert_eq!(
+        grab(&client, &base, &tokens[2], json!({"ticket_type_id": tt}))
+            .await
+            .status()
+            .as_u16(),
+        409
+    );
+
+    // Checkout doesn't compete again, even though nothing is left on sale.
+    let (token, hold_id) = holds[0].clone();
+    let order = checkout(&client, &base, &token, &hold_id)
+        .await
+        .error_for_status()
+        .unwrap();
+    let order = order.json::<serde_json::Value>().await.unwrap();
+    assert_eq!(
+        (order["status"].clone(), order["amount_cents"].clone()),
+        (json!("CREATED"), json!(100))
+    );
+    let again = checkout(&client, &base, &token, &hold_id)
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(again["id"], order["id"]);
+    assert_eq!(remaining(&pool, &tt).await, 0);
+    assert!(pay(&client, &base, &token, order["id"].as_str().unwrap())
+        .await
+        .status()
+        .is_success());
+    let stranger = login(&client, &base, "holder-stranger").await;
+    assert_eq!(
+        checkout(&client, &base, &stranger, &holds[1].1)
+            .await
+            .status()
+            .as_u16(),
+        404
+    );
+
+    // Releasing puts the ticket straight back on sale.
+    let (token, hold_id) = holds[1].clone();
+    let released = client
+        .delete(format!("{}/api/holds/{}", base, hold_id))
+        .bearer_auth(&token)
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(released["status"], "RELEASED");
+    assert_eq!(
+        checkout(&client, &base, &token, &hold_id)
+            .await
+            .status()
+            .as_u16(),
+        409
+    );
+    assert_eq!(remaining(&pool, &tt).await, 1);
+    assert!(
+        grab(&client, &base, &stranger, json!({"ticket_type_id": tt}))
+            .await
+            .status()
+            .is_success()
+    );
+
+    // Expired holds are swept back into stock.
+    let tt = create_on_sale_ticket_type(&client, &base, json!({"inventory_total": 2})).await;
+    let resp = hold(
+        &client,
+        &base,
+        &stranger,
+        json!({"ticket_type_id": tt, "ttl_secs": 3600}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let resp = hold(
+        &client,
+        &base,
+        &stranger,
+        json!({"ticket_type_id": tt, "qty": 2, "ttl_secs": 1}),
+    )
+    .await;
+    let hold_id = resp.json::<serde_json::Value>().await.unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(remaining(&pool, &tt).await, 0);
+    let mut status = String::new();
+    for _ in 0..50 {
+        status = sqlx::query_scalar("select status from holds where id = $1::uuid")
+            .bind(&hold_id)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        if status != "ACTIVE" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(status, "EXPIRED");
+    assert_eq!(remaining(&pool, &tt).await, 2);
+    assert_eq!(
+        checkout(&client, &base, &stranger, &hold_id)
+            .await
+            .status()
+            .as_u16(),
+        409
+    );
+
+    // Seats are held too, and move to the order at checkout.
+    let event_id = create_event(&client, &base, json!({})).await;
+    let tt = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"inventory_total": 2, "seat_rows": [{"section": "A", "row": "1", "seats": 2}]}),
+    )
+    .await;
+    let seats = client
+        .get(format!("{}/api/ticket-types/{}/seats", base, tt))
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    let seat = seats[0]["id"].clone();
+    let resp = hold(
+        &client,
+        &base,
+        &stranger,
+        json!({"ticket_type_id": tt, "seat_ids": [seat]}),
+    )
+    .await;
+    let hold_id = resp.json::<serde_json::Value>().await.unwrap()["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let resp = grab(
+        &client,
+        &base,
+        &tokens[1],
+        json!({"ticket_type_id": tt, "seat_ids": [seat]}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let order = checkout(&client, &base, &stranger, &hold_id)
+        .await
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let order_seats = client
+        .get(format!(
+            "{}/api/orders/{}/seats",
+            base,
+            order["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&stranger)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    assert_eq!(order_seats.len(), 1);
+    assert_eq!(
+        (
+            order_seats[0]["id"].clone(),
+            order_seats[0]["status"].clone()
+        ),
+        (seat, json!("HELD"))
+    );
+
+    let report = client
+        .get(format!("{}/api/admin/inventory/reconcile", base))
+        .bearer_auth(admin_token(&client, &base).await)
+        .send()
+        .await
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(report["consistent"], true);
+}
+
+async fn create_promo_code(
+    client: &Client,
+    base: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    client
+        .post(format!("{}/api/admin/promo-codes", base))
+        .bearer_auth(admin_token(client, base).await)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn promo_codes_discount_orders_and_never_over_redeem() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+    let event_id = create_event(&client, &base, json!({})).await;
+    let tt = create_ticket_type(
+        &client,
+        &base,
+        &event_id,
+        json!({"inventory_total": 50, "max_per_user": 50}),
+    )
+    .await;
+    let other_tt = create_on_sale_ticket_type(&client, &base, json!({})).await;
+
+    let resp = create_promo_code(
+        &client,
+        &base,
+        json!({"code": "SALE10", "kind": "percent", "amount_off_cents": 10}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let sale = create_promo_code(
+        &client,
+        &base,
+        json!({"code": "SALE10", "kind": "percent", "percent_off": 10, "max_uses": 3}),
+    )
+    .await
+    .error_for_status()
+    .unwrap()
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    let resp = create_promo_code(
+        &client,
+        &base,
+        json!({"code": "sale10", "kind": "fixed", "amount_off_cents": 5}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+
+    // Ten buyers race for three uses: three discounted orders, nobody else buys anything.
+    let mut tokens = Vec::new();
+    for i in 0..10 {
+        tokens.push(login(&client, &base, &format!("promo-{i}")).await);
+    }
+    let results = join_all(tokens.iter().cloned().map(|token| {
+        let (client, base, tt) = (client.clone(), base.clone(), tt.clone());
+        async move {
+            grab(
+                &client,
+                &base,
+                &token,
+                json!({"ticket_type_id": tt, "qty": 2, "promo_code": "sale10"}),
+            )
+            .await
+        }
+    }))
+    .await;
+    let mut orders = Vec::new();
+    for (i, resp) in results.into_iter().enumerate() {
+        if resp.status().is_success() {
+            let order = resp.json::<serde_json::Value>().await.unwrap();
+            assert_eq!(order["amount_cents"], 180);
+            orders.push((tokens[i].clone(), order["id"].as_str().unwrap().to_string()));
+        } else {
+            assert_eq!(resp.status().as_u16(), 409);
+        }
+    }
+    assert_eq!(orders.len(), 3);
+    let remaining: i32 =
+        sqlx::query_scalar("select inventory_remaining from ticket_types where id = $1::uuid")
+            .bind(&tt)
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 44);
+
+    // Canceling an order gives its use back.
+    let (token, order_id) = &orders[0];
+    client
+        .post(format!("{}/api/orders/{}/cancel", base, order_id))
+        .bearer_auth(token)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let late = login(&client, &base, "promo-late").await;
+    let resp = grab(
+        &client,
+        &base,
+        &late,
+        json!({"ticket_type_id": tt, "promo_code": "SALE10"}),
+    )
+    .await;
+    assert_eq!(
+        resp.json::<serde_json::Value>().await.unwrap()["amount_cents"],
+        90
+    );
+
+    // Fixed codes are capped at the order total; once per user, scoped to one ticket type.
+    let flat = create_promo_code(
+        &client,
+        &base,
+        json!({"code": "FLAT", "kind": "fixed", "amount_off_cents": 150, "once_per_user": true, "ticket_type_ids": [tt]}),
+    )
+    .await
+    .error_for_status()
+    .unwrap()
+    .json::<serde_json::Value>()
+    .await
+    .unwrap();
+    let resp = grab(
+        &client,
+        &base,
+        &late,
+        json!({"ticket_type_id": tt, "promo_code": "FLAT"}),
+    )
+    .await;
+    assert_eq!(
+        resp.json::<serde_json::Value>().await.unwrap()["amount_cents"],
+        0
+    );
+    let resp = grab(
+        &client,
+        &base,
+        &late,
+        json!({"ticket_type_id": tt, "promo_code": "FLAT"}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let resp = grab(
+        &client,
+        &base,
+        &tokens[1],
+        json!({"ticket_type_id": other_tt, "promo_code": "FLAT"}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let resp = grab(
+        &client,
+        &base,
+        &tokens[1],
+        json!({"ticket_type_id": tt, "promo_code": "NOPE"}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let resp = create_promo_code(
+        &client,
+        &base,
+        json!({"code": "LATER", "kind": "percent", "percent_off": 50, "valid_from": Utc::now() + Duration::hours(1)}),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let resp = grab(
+        &client,
+        &base,
+        &tokens[1],
+        json!({"ticket_type_id": tt, "promo_code": "LATER"}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 400);
+
+    // Admin edits are guarded and audited.
+    let admin = admin_token(&client, &base).await;
+    let patch_code = |id: serde_json::Value, body: serde_json::Value| {
+        let (client, base, admin) = (client.clone(), base.clone(), admin.clone());
+        async move {
+            client
+                .patch(format!(
+                    "{}/api/admin/promo-codes/{}",
+                    base,
+                    id.as_str().unwrap()
+                ))
+                .bearer_auth(admin)
+                .json(&body)
+                .send()
+                .await
+                .unwrap()
+        }
+    };
+    assert_eq!(
+        patch_code(sale["id"].clone(), json!({"max_uses": 2}))
+            .await
+            .status()
+            .as_u16(),
+        409
+    );
+    let resp = patch_code(
+        sale["id"].clone(),
+        json!({"max_uses": null, "active": false}),
+    )
+    .await;
+    let updated = resp
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        (updated["max_uses"].clone(), updated["uses"].clone()),
+        (json!(null), json!(3))
+    );
+    let resp = grab(
+        &client,
+        &base,
+        &tokens[1],
+        json!({"ticket_type_id": tt, "promo_code": "SALE10"}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let resp = client
+        .delete(format!(
+            "{}/api/admin/promo-codes/{}",
+            base,
+            flat["id"].as_str().unwrap()
+        ))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 409);
+    let resp = client
+        .get(format!("{}/api/admin/promo-codes", base))
+        .bearer_auth(&late)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 403);
+    let audited: i64 = sqlx::query_scalar(
+        "select count(*) from admin_audit_log where action = 'promo_code.update'",
+    )
+    .fetch_one(&pool)
+    .await
+    .unwrap();
+    assert_eq!(audited, 1);
+
+    // Codes also apply at hold checkout; a bad one leaves the hold to check out again.
+    create_promo_code(
+        &client,
+        &base,
+        json!({"code": "HOLD20", "kind": "percent", "percent_off": 20, "max_uses": 1}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let held = hold(&client, &base, &late, json!({"ticket_type_id": other_tt}))
+        .await
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    let checkout_with = |promo_code: &'static str| {
+        let (client, base, late) = (client.clone(), base.clone(), late.clone());
+        let url = format!(
+            "{}/api/holds/{}/checkout",
+            base,
+            held["id"].as_str().unwrap()
+        );
+        async move {
+            client
+                .post(url)
+                .bearer_auth(late)
+                .json(&json!({"promo_code": promo_code}))
+                .send()
+                .await
+                .unwrap()
+        }
+    };
+    assert_eq!(checkout_with("NOPE").await.status().as_u16(), 400);
+    let order = checkout_with("hold20")
+        .await
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(order["amount_cents"], 80);
+    let uses: i32 = sqlx::query_scalar("select uses from promo_codes where code = 'HOLD20'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(uses, 1);
+}
+
+#[tokio::test]
+async fn presale_admits_allow_listed_users_and_code_holders_early() {
+    let (base, pool, _guard) = setup().await;
+    let client = Client::new();
+    let admin = admin_token(&client, &base).await;
+    let event_id = create_event(&client, &base, json!({})).await;
+    let presale = json!({
+        "inventory_total": 10,
+        "max_per_user": 10,
+        "presale_starts_at": Utc::now() - Duration::minutes(1),
+        "sale_starts_at": Utc::now() + Duration::minutes(30),
+        "sale_ends_at": Utc::now() + Duration::minutes(60)
+    });
+    let resp = client
+        .post(format!(
+            "{}/api/admin/events/{}/ticket_types",
+            base, event_id
+        ))
+        .bearer_auth(&admin)
+        .json(&merge(
+            json!({"name": "P", "price_cents": 100}),
+            merge(
+                presale.clone(),
+                json!({"presale_starts_at": Utc::now() + Duration::hours(1)}),
+            ),
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+    // The waiting room only admits from sale_starts_at, so presales can't be queued.
+    let resp = client
+        .post(format!(
+            "{}/api/admin/events/{}/ticket_types",
+            base, event_id
+        ))
+        .bearer_auth(&admin)
+        .json(&merge(
+            json!({"name": "P", "price_cents": 100}),
+            merge(presale.clone(), json!({"queue_enabled": true})),
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+    let tt = create_ticket_type(&client, &base, &event_id, presale).await;
+    let resp = client
+        .patch(format!("{}/api/admin/ticket-types/{}", base, tt))
+        .bearer_auth(&admin)
+        .json(&json!({"queue_enabled": true}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 400);
+
+    let fan = login(&client, &base, "presale-fan").await;
+    let rando = login(&client, &base, "presale-rando").await;
+    let (status, reason) =
+        rejection_reason(grab(&client, &base, &rando, json!({"ticket_type_id": tt})).await).await;
+    assert_eq!((status, reason.as_str()), (403, "presale_required"));
+
+    let presale_url =
+        |path: &str| format!("{}/api/admin/ticket-types/{}/presale/{}", base, tt, path);
+    let resp = client
+        .post(presale_url("allowlist"))
+        .bearer_auth(&fan)
+        .json(&json!({"usernames": ["presale-fan"]}))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 403);
+    let upload = client
+        .post(presale_url("allowlist"))
+        .bearer_auth(&admin)
+        .json(&json!({"usernames": ["presale-fan", "presale-ghost", "presale-fan"]}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<serde_json::Value>()
+        .await
+        .unwrap();
+    assert_eq!(
+        upload,
+        json!({"added": 1, "total": 1, "unknown_usernames": ["presale-ghost"]})
+    );
+    assert!(grab(&client, &base, &fan, json!({"ticket_type_id": tt}))
+        .await
+        .status()
+        .is_success());
+
+    // Each code unlocks one presale purchase; racing holders of the same code get one.
+    let codes = client
+        .post(presale_url("codes"))
+        .bearer_auth(&admin)
+        .json(&json!({"count": 3}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    assert_eq!(codes.len(), 3);
+    let code = |i: usize| codes[i]["code"].as_str().unwrap().to_string();
+    let mut racers = Vec::new();
+    for i in 0..5 {
+        racers.push(login(&client, &base, &format!("presale-racer-{i}")).await);
+    }
+    let shared = code(0).to_lowercase();
+    let results = join_all(racers.iter().cloned().map(|token| {
+        let (client, base, tt, shared) = (client.clone(), base.clone(), tt.clone(), shared.clone());
+        async move {
+            grab(
+                &client,
+                &base,
+                &token,
+                json!({"ticket_type_id": tt, "presale_code": shared}),
+            )
+            .await
+        }
+    }))
+    .await;
+    assert_eq!(
+        results.iter().filter(|r| r.status().is_success()).count(),
+        1
+    );
+    assert!(results
+        .iter()
+        .all(|r| r.status().is_success() || r.status().as_u16() == 403));
+
+    // A purchase that fails doesn't spend the code; a hold can use it too.
+    let resp = grab(
+        &client,
+        &base,
+        &rando,
+        json!({"ticket_type_id": tt, "qty": 50, "presale_code": code(1)}),
+    )
+    .await;
+    assert_eq!(resp.status().as_u16(), 409);
+    let uses: i32 = sqlx::query_scalar("select uses from presale_codes where code = $1")
+        .bind(code(1))
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(uses, 0);
+    let resp = hold(
+        &client,
+        &base,
+        &rando,
+        json!({"ticket_type_id": tt, "presale_code": code(1)}),
+    )
+    .await;
+    assert!(resp.status().is_success());
+    let listed = client
+        .get(presale_url("codes"))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap()
+        .json::<Vec<serde_json::Value>>()
+        .await
+        .unwrap();
+    assert_eq!(
+        listed
+            .iter()
+            .map(|c| c["uses"].as_i64().unwrap())
+            .sum::<i64>(),
+        2
+    );
+
+    // Removed from the list: back to waiting for the public sale, which needs nothing.
+    let fan_id: uuid::Uuid =
+        sqlx::query_scalar("select id from users where username = 'presale-fan'")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+    let resp = client
+        .delete(presale_url(&format!("allowlist/{fan_id}")))
+        .bearer_auth(&admin)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status().as_u16(), 204);
+    assert_eq!(
+        grab(&client, &base, &fan, json!({"ticket_type_id": tt}))
+            .await
+            .status()
+            .as_u16(),
+        403
+    );
+    client
+        .patch(format!("{}/api/admin/ticket-types/{}", base, tt))
+        .bearer_auth(&admin)
+        .json(&json!({"sale_starts_at": Utc::now() - Duration::seconds(1), "presale_starts_at": null, "force": true}))
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert!(grab(&client, &base, &fan, json!({"ticket_type_id": tt}))
+        .await
+        .status()
+        .is_success());
+}
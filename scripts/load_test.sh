@@ -5,10 +5,23 @@ cd "$(dirname "$0")/.."
 BASE_URL=${BASE_URL:-http://localhost:8080}
 CONCURRENCY=${CONCURRENCY:-50}
 DURATION=${DURATION:-10s}
+# Admin account, e.g. the one from ADMIN_BOOTSTRAP_USERNAME / ADMIN_BOOTSTRAP_PASSWORD.
+ADMIN_USERNAME=${ADMIN_USERNAME:-admin}
+ADMIN_PASSWORD=${ADMIN_PASSWORD:?set ADMIN_PASSWORD}
+# Set to 2..64 to spread the stock over that many bucket rows.
+INVENTORY_SHARDS=${INVENTORY_SHARDS:-null}
 
-# Login to get token
-login=$(curl -sS -X POST "$BASE_URL/api/auth/login" -H 'content-type: application/json' -d '{"username":"load-user"}')
-token=$(echo "$login" | python3 -c 'import sys,json; print(json.load(sys.stdin)["token"])')
+login() {
+  curl -sS -X POST "$BASE_URL/api/auth/login" -H 'content-type: application/json' \
+    -d "{\"username\":\"$1\",\"password\":\"$2\"}" |
+    python3 -c 'import sys,json; print(json.load(sys.stdin)["token"])'
+}
+
+admin_token=$(login "$ADMIN_USERNAME" "$ADMIN_PASSWORD")
+# Registering again just returns 409.
+curl -sS -o /dev/null -X POST "$BASE_URL/api/auth/register" -H 'content-type: application/json' \
+  -d '{"username":"load-user","password":"load-user-password"}'
+token=$(login load-user load-user-password)
 
 now=$(date -u +%Y-%m-%dT%H:%M:%SZ)
 ends=$(date -u -d "+2 hour" +%Y-%m-%dT%H:%M:%SZ)
@@ -16,11 +29,13 @@ sale_starts=$(date -u -d "-1 minute" +%Y-%m-%dT%H:%M:%SZ)
 sale_ends=$(date -u -d "+30 minute" +%Y-%m-%dT%H:%M:%SZ)
 
 event=$(curl -sS -X POST "$BASE_URL/api/admin/events" -H 'content-type: application/json' \
+  -H "Authorization: Bearer $admin_token" \
   -d "{\"name\":\"Load Test\",\"starts_at\":\"$now\",\"ends_at\":\"$ends\"}")
 event_id=$(echo "$event" | python3 -c 'import sys,json; print(json.load(sys.stdin)["id"])')
 
 tt=$(curl -sS -X POST "$BASE_URL/api/admin/events/$event_id/ticket_types" -H 'content-type: application/json' \
-  -d "{\"name\":\"LT\",\"price_cents\":1,\"inventory_total\":1000000,\"sale_starts_at\":\"$sale_starts\",\"sale_ends_at\":\"$sale_ends\"}")
+  -H "Authorization: Bearer $admin_token" \
+  -d "{\"name\":\"LT\",\"price_cents\":1,\"inventory_total\":1000000,\"inventory_shards\":$INVENTORY_SHARDS,\"sale_starts_at\":\"$sale_starts\",\"sale_ends_at\":\"$sale_ends\"}")
 ticket_type_id=$(echo "$tt" | python3 -c 'import sys,json; print(json.load(sys.stdin)["id"])')
 
 cat > /tmp/grab_body.json <<EOF